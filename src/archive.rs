@@ -0,0 +1,171 @@
+//! Archives of users' items, for backups and for moving blogs between servers.
+//!
+//! An archive starts with [`MAGIC`], followed by one record per item. Each
+//! record is an `ItemListEntry` describing the item, then the item's bytes,
+//! exactly as they were signed. Each of those is written as a varint length
+//! followed by that many bytes.
+//!
+//! Archives don't need to be trusted. Imports check every item's signature,
+//! just like an upload.
+//!
+//! Attached files are not (yet) included.
+
+use std::io::{BufRead, Write};
+
+use failure::{Error, bail, format_err};
+use protobuf::{CodedInputStream, CodedOutputStream, Message};
+
+use crate::backend::{Backend, Cursor, ItemRow, Signature, Timestamp, UserID};
+use crate::protos::{Item, ItemListEntry, ProtoValid};
+use crate::server::item_to_entry;
+
+/// Identifies a file as a FeoBlog archive, and its format version.
+pub(crate) const MAGIC: &[u8] = b"FeoBlog archive v1\n";
+
+/// ItemListEntries are small. Anything bigger means a corrupt archive.
+const MAX_ENTRY_SIZE: u32 = 1024;
+
+/// Write an archive of items, in the order that this server received them.
+/// If `users` is None, exports all items.
+/// Returns how many items were written.
+pub(crate) fn export(backend: &dyn Backend, users: Option<&[UserID]>, out: &mut dyn Write) -> Result<u64, Error> {
+    let mut output = CodedOutputStream::new(out);
+    output.write_raw_bytes(MAGIC)?;
+
+    let mut count = 0;
+    let mut write_items = |user: Option<&UserID>| {
+        backend.received_items(user, &Cursor::after(Timestamp{ unix_utc_ms: 0 }), &mut |row| {
+            let item = Item::parse_from_bytes(&row.item_bytes)?;
+            let entry = item_to_entry(&item, &row).write_to_bytes()?;
+            write_chunk(&mut output, &entry)?;
+            write_chunk(&mut output, &row.item_bytes)?;
+            count += 1;
+            Ok(true)
+        })
+    };
+
+    match users {
+        None => write_items(None)?,
+        Some(users) => for user in users {
+            write_items(Some(user))?;
+        },
+    }
+
+    output.flush()?;
+    Ok(count)
+}
+
+/// What happened when we imported an archive.
+#[derive(Debug, Default)]
+pub(crate) struct ImportStats {
+    /// Items saved to the backend.
+    pub saved: u64,
+
+    /// Items that were already saved.
+    pub existing: u64,
+
+    /// Why each rejected item was rejected. (Bad signatures, invalid Items, too large.)
+    pub rejected: Vec<String>,
+}
+
+/// Save the items from an archive.
+///
+/// Like uploads, items may be at most `max_item_size` bytes, unless their
+/// author is a server user with their own limit.
+///
+/// Invalid items are skipped, and listed in the returned stats. Errors reading
+/// the archive itself stop the import, but items before that are kept.
+pub(crate) fn import(backend: &mut dyn Backend, input: &mut dyn BufRead, max_item_size: usize) -> Result<ImportStats, Error> {
+    let mut input = CodedInputStream::from_buffered_reader(input);
+    let magic = input.read_raw_bytes(MAGIC.len() as u32)
+        .map_err(|_| format_err!("Not a FeoBlog archive"))?;
+    if magic != MAGIC {
+        bail!("Not a FeoBlog archive, or an unsupported version");
+    }
+
+    let mut stats = ImportStats::default();
+    while !input.eof()? {
+        let entry = read_chunk(&mut input, MAX_ENTRY_SIZE)?;
+        let entry = ItemListEntry::parse_from_bytes(&entry)?;
+        let size = input.read_raw_varint32()?;
+
+        let user = UserID::from_vec(entry.get_user_id().get_bytes().to_vec());
+        let signature = Signature::from_vec(entry.get_signature().get_bytes().to_vec());
+        let (user, signature) = match (user, signature) {
+            (Ok(user), Ok(signature)) => (user, signature),
+            _ => {
+                input.skip_raw_bytes(size)?;
+                stats.rejected.push("Entry with an invalid user ID or signature".into());
+                continue;
+            },
+        };
+        let item_url = format!("/u/{}/i/{}/", user.to_base58(), signature.to_base58());
+
+        let user_max_item_size = backend.server_user(&user)?.and_then(|user| user.max_item_size);
+        let max_size = user_max_item_size.map(|size| size as usize).unwrap_or(max_item_size);
+        if size as usize > max_size {
+            input.skip_raw_bytes(size)?;
+            stats.rejected.push(format!("{}: {} bytes is larger than the limit of {} bytes", item_url, size, max_size));
+            continue;
+        }
+        let bytes = input.read_raw_bytes(size)?;
+
+        if !signature.is_valid(&user, &bytes) {
+            stats.rejected.push(format!("{}: Invalid signature", item_url));
+            continue;
+        }
+
+        let item = match Item::parse_from_bytes(&bytes) {
+            Ok(item) => item,
+            Err(error) => {
+                stats.rejected.push(format!("{}: {}", item_url, error));
+                continue;
+            },
+        };
+        if let Err(error) = item.validate() {
+            stats.rejected.push(format!("{}: {}", item_url, error));
+            continue;
+        }
+
+        if backend.user_item_exists(&user, &signature)? {
+            stats.existing += 1;
+            continue;
+        }
+
+        // Like uploads, refuse new items from users who revoked their ID:
+        if backend.user_revoked(&user)? {
+            stats.rejected.push(format!("{}: user ID has been revoked", item_url));
+            continue;
+        }
+
+        let row = ItemRow{
+            user,
+            signature,
+            timestamp: Timestamp{ unix_utc_ms: item.get_timestamp_ms_utc() },
+            // Like items we sync, these are new to this server:
+            received: Timestamp::now(),
+            item_bytes: bytes,
+        };
+        backend.save_user_item(&row, &item)?;
+        stats.saved += 1;
+    }
+
+    Ok(stats)
+}
+
+fn write_chunk(output: &mut CodedOutputStream, bytes: &[u8]) -> Result<(), Error> {
+    output.write_raw_varint32(bytes.len() as u32)?;
+    output.write_raw_bytes(bytes)?;
+    Ok(())
+}
+
+fn read_chunk(input: &mut CodedInputStream, max_size: u32) -> Result<Vec<u8>, Error> {
+    let size = input.read_raw_varint32()?;
+    if size > max_size {
+        bail!("Archive is corrupt: found a {} byte record, but the maximum is {}", size, max_size);
+    }
+    Ok(input.read_raw_bytes(size)?)
+}
+
+#[cfg(test)]
+mod tests;
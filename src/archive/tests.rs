@@ -0,0 +1,157 @@
+use super::*;
+
+use crate::backend::{Factory, ServerUser};
+use crate::client::{Keys, new_item};
+use crate::server::MAX_ITEM_SIZE;
+use crate::testing::TempDB;
+
+fn save_post(factory: &dyn Factory, keys: &Keys, title: &str) -> Signature {
+    let mut item = new_item();
+    item.mut_post().set_title(title.into());
+    save_item(factory, keys, &item)
+}
+
+fn save_item(factory: &dyn Factory, keys: &Keys, item: &Item) -> Signature {
+    let (signature, bytes) = keys.sign(item).unwrap();
+    let row = ItemRow{
+        user: keys.user.clone(),
+        signature: signature.clone(),
+        timestamp: Timestamp{ unix_utc_ms: item.get_timestamp_ms_utc() },
+        received: Timestamp::now(),
+        item_bytes: bytes,
+    };
+    factory.open().unwrap().save_user_item(&row, item).unwrap();
+    signature
+}
+
+fn exists(factory: &dyn Factory, keys: &Keys, signature: &Signature) -> bool {
+    factory.open().unwrap().user_item_exists(&keys.user, signature).unwrap()
+}
+
+#[test]
+fn export_and_import() {
+    let source_db = TempDB::new("archive-source");
+    let source = source_db.factory();
+    let (alice, bob) = (Keys::generate(), Keys::generate());
+    let alice_posts = vec![save_post(&source, &alice, "one"), save_post(&source, &alice, "two")];
+    let bob_post = save_post(&source, &bob, "bob");
+
+    let mut archive = vec![];
+    let users = [alice.user.clone()];
+    let count = export(source.open().unwrap().as_ref(), Some(&users), &mut archive).unwrap();
+    assert_eq!(2, count);
+    assert!(archive.starts_with(MAGIC));
+
+    let dest_db = TempDB::new("archive-dest");
+    let dest = dest_db.factory();
+    let stats = import(dest.open().unwrap().as_mut(), &mut archive.as_slice(), MAX_ITEM_SIZE).unwrap();
+    assert_eq!(2, stats.saved);
+    assert!(stats.rejected.is_empty());
+    for signature in &alice_posts {
+        assert!(exists(&dest, &alice, signature));
+    }
+    assert!(!exists(&dest, &bob, &bob_post));
+
+    // Importing everything skips what we already have:
+    let mut archive = vec![];
+    assert_eq!(3, export(source.open().unwrap().as_ref(), None, &mut archive).unwrap());
+    let stats = import(dest.open().unwrap().as_mut(), &mut archive.as_slice(), MAX_ITEM_SIZE).unwrap();
+    assert_eq!(1, stats.saved);
+    assert_eq!(2, stats.existing);
+    assert!(exists(&dest, &bob, &bob_post));
+}
+
+#[test]
+fn import_rejects_tampered_items() {
+    let source_db = TempDB::new("archive-tampered");
+    let source = source_db.factory();
+    let alice = Keys::generate();
+    save_post(&source, &alice, "Original title");
+
+    let mut archive = vec![];
+    export(source.open().unwrap().as_ref(), None, &mut archive).unwrap();
+
+    // Same length, so the archive is still well-formed:
+    let position = archive.windows(8).position(|w| w == b"Original").unwrap();
+    archive[position..position + 8].copy_from_slice(b"Modified");
+
+    let dest_db = TempDB::new("archive-tampered-dest");
+    let dest = dest_db.factory();
+    let stats = import(dest.open().unwrap().as_mut(), &mut archive.as_slice(), MAX_ITEM_SIZE).unwrap();
+    assert_eq!(0, stats.saved);
+    assert_eq!(1, stats.rejected.len());
+    assert!(stats.rejected[0].contains("Invalid signature"), "{}", stats.rejected[0]);
+}
+
+#[test]
+fn import_checks_item_size_limits() {
+    let source_db = TempDB::new("archive-large");
+    let source = source_db.factory();
+    let alice = Keys::generate();
+    let mut item = new_item();
+    item.mut_post().set_body("x".repeat(MAX_ITEM_SIZE));
+    let large = save_item(&source, &alice, &item);
+    let small = save_post(&source, &alice, "small");
+
+    let mut archive = vec![];
+    export(source.open().unwrap().as_ref(), None, &mut archive).unwrap();
+
+    // Items over the limit are skipped, not errors:
+    let dest_db = TempDB::new("archive-large-dest");
+    let dest = dest_db.factory();
+    let stats = import(dest.open().unwrap().as_mut(), &mut archive.as_slice(), MAX_ITEM_SIZE).unwrap();
+    assert_eq!(1, stats.saved);
+    assert_eq!(1, stats.rejected.len());
+    assert!(stats.rejected[0].contains("larger than the limit"), "{}", stats.rejected[0]);
+    assert!(exists(&dest, &alice, &small));
+    assert!(!exists(&dest, &alice, &large));
+
+    // Users may have a larger limit of their own:
+    dest.open().unwrap().add_server_user(&ServerUser{
+        user: alice.user.clone(),
+        notes: "".into(),
+        on_homepage: true,
+        max_bytes: None,
+        max_item_size: Some(MAX_ITEM_SIZE as u64 * 2),
+    }).unwrap();
+    let stats = import(dest.open().unwrap().as_mut(), &mut archive.as_slice(), MAX_ITEM_SIZE).unwrap();
+    assert_eq!(1, stats.saved);
+    assert!(exists(&dest, &alice, &large));
+}
+
+#[test]
+fn import_rejects_revoked_users() {
+    let source_db = TempDB::new("archive-revoked");
+    let source = source_db.factory();
+    let alice = Keys::generate();
+    let post = save_post(&source, &alice, "Posted before the revocation");
+
+    let mut archive = vec![];
+    export(source.open().unwrap().as_ref(), None, &mut archive).unwrap();
+
+    let dest_db = TempDB::new("archive-revoked-dest");
+    let dest = dest_db.factory();
+    let mut revocation = new_item();
+    revocation.mut_profile().set_revoked(true);
+    save_item(&dest, &alice, &revocation);
+
+    let stats = import(dest.open().unwrap().as_mut(), &mut archive.as_slice(), MAX_ITEM_SIZE).unwrap();
+    assert_eq!(0, stats.saved);
+    assert_eq!(1, stats.rejected.len());
+    assert!(stats.rejected[0].contains("revoked"), "{}", stats.rejected[0]);
+    assert!(!exists(&dest, &alice, &post));
+}
+
+#[test]
+fn import_rejects_other_files() {
+    let db = TempDB::new("archive-invalid");
+    let factory = db.factory();
+
+    assert!(import(factory.open().unwrap().as_mut(), &mut &b"SQLite format 3\0 and more"[..], MAX_ITEM_SIZE).is_err());
+    assert!(import(factory.open().unwrap().as_mut(), &mut &b""[..], MAX_ITEM_SIZE).is_err());
+
+    // Truncated:
+    let mut archive = MAGIC.to_vec();
+    archive.extend_from_slice(&[10, 1, 2, 3]);
+    assert!(import(factory.open().unwrap().as_mut(), &mut archive.as_slice(), MAX_ITEM_SIZE).is_err());
+}
@@ -1,44 +1,67 @@
 //! Types for data storage/retrieval.
 
 pub(crate) mod sqlite;
+#[cfg(feature = "postgres")]
+pub(crate) mod postgres;
+pub(crate) mod nonblocking;
+mod verify;
+
+#[cfg(test)]
+pub(crate) mod memory;
+#[cfg(test)]
+pub(crate) mod tests;
 
 use crate::protos::Item;
 use core::str::FromStr;
 use std::marker::PhantomData;
-use failure::{Error, ResultExt, bail, format_err};
+use failure::{ResultExt, bail, format_err};
 use bs58;
 use serde::{Deserialize, de::{self, Visitor}};
 use sodiumoxide::crypto::sign;
 
 
 /// Knows how to open Backend "connections".
-pub trait Factory
+///
+/// Factories are shared between the web server's worker threads.
+pub trait Factory: Send + Sync
 {
     fn open(&self) -> Result<Box<dyn Backend>, Error>;
 }
 
+impl <F: Factory + ?Sized> Factory for std::sync::Arc<F>
+{
+    fn open(&self) -> Result<Box<dyn Backend>, Error> {
+        (**self).open()
+    }
+}
+
 /// Represents a connection to the backend, and logic we want to perform
 /// with it.
 pub trait Backend
 {
-    // TODO: Remove reliance on failure::Error. We should define our own error
-    // type here. Should probably impl Error, which requires changes in sqlite.
-    // Maybe Box<dyn Error> is sufficient? https://github.com/dtolnay/anyhow/issues/25
-    
-    /// Set up the initial DB state, maybe running migrations.
-    fn setup(&self) -> Result<(), Error>;
+    /// Create the schema for a new, empty database.
+    /// Fails if the database has already been initialized.
+    fn init(&self) -> Result<MigrationReport, Error>;
+
+    /// Check the database's schema version, and which migrations it needs.
+    fn status(&self) -> Result<SchemaStatus, Error>;
+
+    /// Bring an existing database schema up to the version this code expects.
+    fn migrate(&self, options: &MigrateOptions) -> Result<MigrationReport, Error>;
 
     /// Find most recent items for users flagged to be displayed on the
-    /// home page, which have timestamps before `before`.
+    /// home page, which come after `before`.
     /// Items are returned through callback, and will continue to be fetched while callback continues
     /// to return Ok(true).
-    fn homepage_items<'a>(&self, before: Timestamp, callback: &'a mut dyn FnMut(ItemDisplayRow) -> Result<bool,Error>) -> Result<(), Error>;
+    ///
+    /// All item listings are sorted by [`Cursor`] order. (Newest first.)
+    fn homepage_items<'a>(&self, before: &Cursor, callback: &'a mut dyn FnMut(ItemDisplayRow) -> Result<bool,Error>) -> Result<(), Error>;
 
     /// Find the most recent items for a particular user
     fn user_items<'a>(
         &self,
         user: &UserID,
-        before: Timestamp,
+        before: &Cursor,
         callback: &'a mut dyn FnMut(ItemRow) -> Result<bool, Error>,
     ) -> Result<(), Error>;
 
@@ -46,10 +69,46 @@ pub trait Backend
     fn user_feed_items<'a>(
         &self,
         user_id: &UserID,
-        before: Timestamp,
+        before: &Cursor,
         callback: &'a mut dyn FnMut(ItemDisplayRow) -> Result<bool, Error>,
     ) -> Result<(), Error>;
 
+    /// Find the most recent replies to an item.
+    /// If `followed_by` is specified, only include replies from that user and
+    /// users they follow. Display names are those that `followed_by` would see.
+    fn reply_items<'a>(
+        &self,
+        user_id: &UserID,
+        signature: &Signature,
+        followed_by: Option<&UserID>,
+        before: &Cursor,
+        callback: FnIter<'a, ItemDisplayRow>,
+    ) -> Result<(), Error>;
+
+    /// Find items whose text matches `query`, newest first.
+    /// `query` is plain text. Items must contain all of its words.
+    fn search_items<'a>(
+        &self,
+        query: &str,
+        scope: &SearchScope,
+        before: &Cursor,
+        callback: FnIter<'a, ItemDisplayRow>,
+    ) -> Result<(), Error>;
+
+    /// Find items in the order this server received them, oldest first,
+    /// starting after `after`. If `user` is specified, only list their items.
+    ///
+    /// Unlike other listings, this one is sorted by (received, user ID,
+    /// signature), so its positions come from [`ItemRow::received_cursor`].
+    /// It lets mirrors fetch just the items that arrived since they last
+    /// looked, including ones with older timestamps.
+    fn received_items<'a>(
+        &self,
+        user: Option<&UserID>,
+        after: &Cursor,
+        callback: FnIter<'a, ItemRow>,
+    ) -> Result<(), Error>;
+
     /// Find one particular UserItem
     fn user_item(&self, user: &UserID, signature: &Signature) -> Result<Option<ItemRow>, Error>;
 
@@ -69,6 +128,18 @@ pub trait Backend
     /// Add a new "server user" who is explicitly allowed to post to this server.
     fn add_server_user(&self, server_user: &ServerUser) -> Result<(), Error>;
 
+    /// Update the settings for an existing "server user".
+    /// Fails if the user isn't a server user.
+    fn update_server_user(&self, server_user: &ServerUser) -> Result<(), Error>;
+
+    /// Revoke a user's direct access to post to this server.
+    /// Their existing items are not deleted.
+    /// Fails if the user isn't a server user.
+    fn remove_server_user(&self, user: &UserID) -> Result<(), Error>;
+
+    /// Count the items (and their bytes) stored for a user.
+    fn user_usage(&self, user: &UserID) -> Result<UsageStats, Error>;
+
     /// Get the Item(Row) that represents the user's most recently saved profile, if it exists.
     fn user_profile(&self, user_id: &UserID) -> Result<Option<ItemRow>, Error>;
 
@@ -77,16 +148,163 @@ pub trait Backend
     /// This is true if any of these are true:
     /// * The user is a "server user" (given direct permission to post to this server)
     /// * The user is followed by a "server user". (We want their content so we can create a feed.)
+    ///
+    /// ... unless the user ID has been revoked.
     fn user_known(&self, user_id: &UserID) -> Result<bool, Error>;
 
+    /// Have we stored a profile that revokes this user ID? (See: `Profile.revoked`)
+    fn user_revoked(&self, user_id: &UserID) -> Result<bool, Error>;
+
     /// Check whether a user has remaiing quota/permissions to upload a particular item.
     fn quota_check_item(&self, user_id: &UserID, bytes: &[u8], item: &Item) -> Result<Option<QuotaDenyReason>, Error>;
+
+    /// Check whether a user has remaining quota to upload a file attached to `item`.
+    fn quota_check_attachment(&self, user_id: &UserID, item: &Item, size: u64) -> Result<Option<QuotaDenyReason>, Error>;
+
+    /// Save the contents of a file, keyed by its multihash.
+    /// The caller must verify that `hash` matches `data`.
+    fn save_blob(&self, hash: &[u8], data: &[u8]) -> Result<(), Error>;
+
+    /// Get the contents of a file by its multihash.
+    fn blob(&self, hash: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Efficiently check whether we have the contents of a file.
+    fn blob_exists(&self, hash: &[u8]) -> Result<bool, Error>;
+
+    /// Get the state of syncing from a remote server, if we've tried it before.
+    fn sync_server(&self, url: &str) -> Result<Option<SyncServer>, Error>;
+
+    /// Save the state of syncing from a remote server.
+    fn save_sync_server(&self, server: &SyncServer) -> Result<(), Error>;
+
+    /// The remote server's received time for the newest of a user's items
+    /// that we've synced from it. (See: [`Backend::received_items`])
+    fn sync_position(&self, url: &str, user: &UserID) -> Result<Option<Timestamp>, Error>;
+
+    /// Record how far we've synced a user's items from a remote server.
+    fn save_sync_position(&self, url: &str, user: &UserID, received: Timestamp) -> Result<(), Error>;
+
+    /// Queue an item to be sent to another server.
+    /// Does nothing if it's already queued for that server.
+    fn add_outbox_entry(&self, url: &str, user: &UserID, signature: &Signature) -> Result<(), Error>;
+
+    /// List items queued to be sent to other servers, by when they're next due.
+    /// If `due` is specified, only list those due to be sent by then.
+    fn outbox_entries<'a>(&self, due: Option<Timestamp>, callback: FnIter<'a, OutboxEntry>) -> Result<(), Error>;
+
+    /// Save an entry's attempts to send it. Fails if it's not queued.
+    fn update_outbox_entry(&self, entry: &OutboxEntry) -> Result<(), Error>;
+
+    /// Remove an item from the queue for a server. (ex: once it's been sent.)
+    fn remove_outbox_entry(&self, url: &str, user: &UserID, signature: &Signature) -> Result<(), Error>;
+
+    /// Re-check every stored item, and the data derived from them.
+    /// See [`VerifyOptions`] for how problems may be fixed.
+    fn verify(&mut self, options: &VerifyOptions) -> Result<VerifyReport, Error>;
 }
 
 /// A callback function used for callback iteration through large database resultsets.
 /// Each row T will be sent to the callback. The callback should return Ok(true) to continue iteration.
 type FnIter<'a, T> = &'a mut dyn FnMut(T) -> Result<bool, Error>; 
 
+/// Why a [`Backend`] call failed.
+///
+/// Callers can tell bad requests (which they might report back to a client)
+/// apart from failures of the storage itself.
+#[derive(Debug)]
+pub enum Error {
+    /// The thing to be updated or removed doesn't exist.
+    NotFound(String),
+
+    /// The change conflicts with data that's already stored.
+    /// (ex: a duplicate item or server user, or an initialized database.)
+    Conflict(String),
+
+    /// The data to be stored is invalid.
+    Validation(String),
+
+    /// Storing the data would exceed what the user is allowed to store.
+    Quota(QuotaDenyReason),
+
+    /// Anything else. (ex: database, I/O, or corrupt data errors.)
+    Storage(failure::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound(message) => write!(f, "{}", message),
+            Self::Conflict(message) => write!(f, "{}", message),
+            Self::Validation(message) => write!(f, "{}", message),
+            Self::Quota(reason) => write!(f, "{}", reason),
+            Self::Storage(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<failure::Error> for Error {
+    fn from(error: failure::Error) -> Self {
+        // Don't lose the kind of an Error that was passed through failure:
+        match error.downcast::<Error>() {
+            Ok(error) => error,
+            Err(error) => Error::Storage(error),
+        }
+    }
+}
+
+impl <D> From<failure::Context<D>> for Error
+where D: std::fmt::Display + Send + Sync + 'static
+{
+    fn from(error: failure::Context<D>) -> Self {
+        Error::Storage(error.into())
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(error: rusqlite::Error) -> Self {
+        use rusqlite::ffi::{SQLITE_CONSTRAINT_PRIMARYKEY, SQLITE_CONSTRAINT_UNIQUE};
+        match &error {
+            rusqlite::Error::SqliteFailure(e, message)
+            if e.extended_code == SQLITE_CONSTRAINT_UNIQUE || e.extended_code == SQLITE_CONSTRAINT_PRIMARYKEY => {
+                Error::Conflict(message.clone().unwrap_or_else(|| e.to_string()))
+            },
+            _ => Error::Storage(error.into()),
+        }
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl From<::postgres::Error> for Error {
+    fn from(error: ::postgres::Error) -> Self {
+        if error.code() == Some(&::postgres::error::SqlState::UNIQUE_VIOLATION) {
+            if let Some(db_error) = error.as_db_error() {
+                return Error::Conflict(db_error.message().to_string());
+            }
+        }
+        Error::Storage(error.into())
+    }
+}
+
+impl From<r2d2::Error> for Error {
+    fn from(error: r2d2::Error) -> Self {
+        Error::Storage(error.into())
+    }
+}
+
+impl From<crate::protos::ValidationError> for Error {
+    fn from(error: crate::protos::ValidationError) -> Self {
+        Error::Validation(error.to_string())
+    }
+}
+
+impl From<protobuf::ProtobufError> for Error {
+    fn from(error: protobuf::ProtobufError) -> Self {
+        Error::Storage(error.into())
+    }
+}
+
 /// A UserID is a nacl public key. (32 bytes)
 #[derive(Debug, Clone)]
 pub struct UserID {
@@ -101,12 +319,12 @@ impl UserID {
         bs58::encode(self.bytes()).into_string()
     }
 
-    pub fn from_base58(value: &str) -> Result<Self, Error> {
+    pub fn from_base58(value: &str) -> Result<Self, failure::Error> {
         let bytes = bs58::decode(value).into_vec()?;
         Self::from_vec(bytes)
     }
 
-    pub fn from_vec(bytes: Vec<u8>) -> Result<Self, Error> {
+    pub fn from_vec(bytes: Vec<u8>) -> Result<Self, failure::Error> {
         if bytes.len() != USER_ID_BYTES {
             bail!("Expected {} bytes but found {}", USER_ID_BYTES, bytes.len());
         }
@@ -140,7 +358,7 @@ pub struct Signature {
 const SIGNATURE_BYTES: usize = 64;
 
 impl Signature {
-    pub fn from_vec(bytes: Vec<u8>) -> Result<Self, Error> {
+    pub fn from_vec(bytes: Vec<u8>) -> Result<Self, failure::Error> {
         if bytes.len() != SIGNATURE_BYTES {
             bail!("Signature expected {} bytes but found {}", SIGNATURE_BYTES, bytes.len());
         }
@@ -152,7 +370,7 @@ impl Signature {
         Ok( Signature{ signature } )
     }
 
-    pub fn from_base58(value: &str) -> Result<Self, Error> {
+    pub fn from_base58(value: &str) -> Result<Self, failure::Error> {
         let bytes = bs58::decode(value).into_vec()?;
         Self::from_vec(bytes)
     }
@@ -207,7 +425,7 @@ impl <T: FromStr> FromStrVisitor<T> {
     }
 }
 
-impl <'de, T: FromStr<Err=Error>> Visitor<'de> for FromStrVisitor<T> 
+impl <'de, T: FromStr<Err=failure::Error>> Visitor<'de> for FromStrVisitor<T> 
 {
     type Value = T;
 
@@ -233,6 +451,7 @@ impl <'de, T: FromStr<Err=Error>> Visitor<'de> for FromStrVisitor<T>
 /// sent to the back-end. (This avoids each back-end having to re-implement
 /// validation logic). Likewise, the front-end may want to validate data returned
 /// by the backend to ensure it hasn't been modified or bit-rot.
+#[derive(Clone)]
 pub struct ItemRow {
     pub user: UserID,
     pub signature: Signature,
@@ -247,6 +466,98 @@ pub struct ItemRow {
     pub item_bytes: Vec<u8>,
 }
 
+impl ItemRow {
+    /// The position of this item in a list of items.
+    pub fn cursor(&self) -> Cursor {
+        Cursor {
+            timestamp: self.timestamp,
+            item: Some((self.user.clone(), self.signature.clone())),
+        }
+    }
+
+    /// The position of this item in a list sorted by when we received items.
+    pub fn received_cursor(&self) -> Cursor {
+        Cursor {
+            timestamp: self.received,
+            item: Some((self.user.clone(), self.signature.clone())),
+        }
+    }
+}
+
+/// A position in a list of items.
+///
+/// Lists are sorted by (timestamp, user ID, signature), descending. Since many
+/// items can share a timestamp, the user ID and signature let us resume a list
+/// exactly where we left off.
+#[derive(Clone)]
+pub struct Cursor {
+    pub timestamp: Timestamp,
+
+    /// The item at this position. If None, the cursor is positioned
+    /// after all items with `timestamp`, so lists resume at items older than it.
+    /// (Or, in lists sorted oldest first, at items newer than it.)
+    pub item: Option<(UserID, Signature)>,
+}
+
+impl Cursor {
+    /// A cursor that lists items older than `timestamp`.
+    pub fn before(timestamp: Timestamp) -> Self {
+        Cursor { timestamp, item: None }
+    }
+
+    /// A cursor that lists items received after `timestamp`.
+    /// (See: [`Backend::received_items`].)
+    pub fn after(timestamp: Timestamp) -> Self {
+        Cursor { timestamp, item: None }
+    }
+
+    /// An opaque string representation of the cursor, for use in URLs.
+    pub fn to_base58(&self) -> String {
+        let mut bytes = self.timestamp.unix_utc_ms.to_be_bytes().to_vec();
+        if let Some((user, signature)) = &self.item {
+            bytes.extend_from_slice(user.bytes());
+            bytes.extend_from_slice(signature.bytes());
+        }
+        bs58::encode(bytes).into_string()
+    }
+
+    pub fn from_base58(value: &str) -> Result<Self, failure::Error> {
+        let bytes = bs58::decode(value).into_vec()?;
+        if bytes.len() != 8 && bytes.len() != 8 + USER_ID_BYTES + SIGNATURE_BYTES {
+            bail!("Invalid cursor");
+        }
+
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&bytes[..8]);
+        let timestamp = Timestamp{ unix_utc_ms: i64::from_be_bytes(timestamp) };
+
+        let item = if bytes.len() > 8 {
+            let user = UserID::from_vec(bytes[8..8 + USER_ID_BYTES].to_vec())?;
+            let signature = Signature::from_vec(bytes[8 + USER_ID_BYTES..].to_vec())?;
+            Some((user, signature))
+        } else {
+            None
+        };
+
+        Ok(Cursor{ timestamp, item })
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = failure::Error;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Cursor::from_base58(value)
+    }
+}
+
+impl <'de> Deserialize<'de> for Cursor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: serde::Deserializer<'de> 
+    {
+        deserializer.deserialize_str(FromStrVisitor::<Self>::new())
+    }
+}
+
 /// An [`ItemRow`] that has extra information (fetched via joins)
 pub struct ItemDisplayRow {
     pub item: ItemRow,
@@ -255,6 +566,19 @@ pub struct ItemDisplayRow {
     pub display_name: Option<String>
 }
 
+/// Which items [`Backend::search_items`] should search.
+#[derive(Debug, Clone)]
+pub enum SearchScope {
+    /// All items on the server.
+    All,
+
+    /// Items posted by a single user.
+    User(UserID),
+
+    /// Items in a user's feed. (Their own items, and those of users they follow.)
+    Feed(UserID),
+}
+
 /// Profile information from the `profile` table. `profile` table.
 /// Expected to be fetched via join/query on userID, so that's excluded.
 pub struct Profile {
@@ -273,6 +597,94 @@ pub struct ServerUser {
     pub user: UserID,
     pub notes: String,
     pub on_homepage: bool,
+
+    /// How many bytes of Items the server will store for this user.
+    /// None = unlimited.
+    pub max_bytes: Option<u64>,
+
+    /// The largest Item (in bytes) this user may upload.
+    /// None = the server's default. (See: `feoblog serve --max-item-size`)
+    pub max_item_size: Option<u64>,
+}
+
+/// What we know about syncing items from a remote server.
+/// i.e.: A row in the sync_server table.
+#[derive(Clone)]
+pub struct SyncServer {
+    /// The server's base URL, as listed in a Profile.
+    pub url: String,
+
+    /// How many syncs in a row have failed.
+    pub failures: u32,
+
+    /// After failures, don't try this server again until this time.
+    pub retry_after: Option<Timestamp>,
+
+    /// The error from the most recent failed sync.
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VerifyOptions {
+    /// Move items that fail verification out of the item table, and rebuild
+    /// profile data that doesn't match users' newest profiles.
+    pub quarantine: bool,
+}
+
+/// The results of [`Backend::verify`].
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// How many items were checked.
+    pub items: u64,
+
+    pub problems: Vec<VerifyProblem>,
+
+    /// Intact items that don't meet the current validation rules. (ex: they
+    /// were accepted by an older version.) These are never quarantined.
+    pub warnings: Vec<VerifyProblem>,
+}
+
+#[derive(Debug)]
+pub struct VerifyProblem {
+    /// Where the problem is. ex: `/u/<userID>/i/<signature>/`
+    pub location: String,
+
+    pub description: String,
+
+    /// What we did about it, if anything.
+    pub fix: Option<&'static str>,
+}
+
+/// An item queued to be sent to another server.
+/// i.e.: A row in the outbox table.
+#[derive(Clone)]
+pub struct OutboxEntry {
+    /// The base URL of the server to send to.
+    pub url: String,
+    pub user: UserID,
+    pub signature: Signature,
+
+    /// How many attempts to send this item have failed.
+    pub attempts: u32,
+
+    /// When to try sending it next. None if we've given up.
+    pub next_attempt: Option<Timestamp>,
+
+    /// The error from the most recent failed attempt.
+    pub last_error: Option<String>,
+}
+
+/// How much a user is storing on the server.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UsageStats {
+    /// The number of Items stored.
+    pub item_count: u64,
+
+    /// The total size of those items' protobuf bytes.
+    pub item_bytes: u64,
+
+    /// The total size of files attached to those items, which have been uploaded.
+    pub attachment_bytes: u64,
 }
 
 #[derive(Copy, Clone)]
@@ -291,26 +703,93 @@ impl Timestamp {
     }
 
     pub fn format_with_offset(self, minutes: i16) -> String {
+        self.to_offset(minutes).format("%Y-%m-%d %H:%M:%S %z")
+    }
+
+    /// Format for Atom feeds. ex: `2020-01-02T03:04:05-07:00`
+    pub fn format_rfc3339(self, minutes: i16) -> String {
+        self.to_offset(minutes).format(time::Format::Rfc3339)
+    }
+
+    /// Format for RSS feeds. ex: `Thu, 02 Jan 2020 03:04:05 -0700`
+    pub fn format_rfc2822(self, minutes: i16) -> String {
+        self.to_offset(minutes).format("%a, %d %b %Y %H:%M:%S %z")
+    }
+
+    fn to_offset(self, minutes: i16) -> time::OffsetDateTime {
         use time::{Duration, UtcOffset, OffsetDateTime};
         use std::ops::Add;
 
         let ms = Duration::milliseconds(self.unix_utc_ms);
         let datetime = OffsetDateTime::unix_epoch().add(ms);
         let offset = UtcOffset::minutes(minutes);
-        let datetime = datetime.to_offset(offset);
+        datetime.to_offset(offset)
+    }
+}
+/// The result of [`Backend::status`].
+#[derive(Debug)]
+pub struct SchemaStatus {
+    /// The schema version of the database, or None if it hasn't been initialized.
+    pub version: Option<u32>,
+
+    /// The newest schema version that this code knows about.
+    pub latest_version: u32,
 
-        datetime.format("%Y-%m-%d %H:%M:%S %z")
+    /// Descriptions of the migrations that [`Backend::migrate`] would apply, in order.
+    pub pending: Vec<&'static str>,
+}
+
+/// Options that control how [`Backend::migrate`] upgrades a database.
+#[derive(Debug, Clone)]
+pub struct MigrateOptions {
+    /// Run the migrations, but roll them back instead of committing them.
+    pub dry_run: bool,
+
+    /// Make a copy of the database before modifying it.
+    pub backup: bool,
+}
+
+impl Default for MigrateOptions {
+    fn default() -> Self {
+        MigrateOptions {
+            dry_run: false,
+            backup: true,
+        }
     }
 }
+
+/// Describes what a call to [`Backend::migrate`] did (or would do, for a dry run).
+#[derive(Debug)]
+pub struct MigrationReport {
+    /// The schema version before migrating.
+    pub from_version: u32,
+
+    /// The schema version after migrating.
+    pub to_version: u32,
+
+    /// Descriptions of each migration that was applied, in order.
+    pub applied: Vec<&'static str>,
+
+    /// Where a backup of the database was written, if one was made.
+    pub backup_file: Option<String>,
+}
+
 /// A reason why a user can't post an Item or file attachment.
+#[derive(Debug)]
 pub enum QuotaDenyReason {
     /// The user already has enough items newer than this one such that posting this one would exceed the quota.
     /// 
-    // TODO: Use this.
-    #[allow(dead_code)]
+    /// Quotas favor a user's newest items, so older items may still be
+    /// accepted up until newer ones fill the quota.
     NewerItemsExceedQuota {
         /// The maximum bytes of Items this user can store on the server.
         max_bytes: u64,
+
+        /// Bytes already used by items (and attachments) at least as new as this one.
+        newer_bytes: u64,
+
+        /// The size of the rejected item or attachment.
+        new_bytes: u64,
     },
 
     /// This user is not known to the server, so not allowed to post.
@@ -323,8 +802,12 @@ pub enum QuotaDenyReason {
 impl std::fmt::Display for QuotaDenyReason {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::NewerItemsExceedQuota { max_bytes } => 
-                write!(f, "Newer items exceed {} byte quota.", max_bytes),
+            Self::NewerItemsExceedQuota { max_bytes, newer_bytes, new_bytes } => 
+                write!(
+                    f,
+                    "Newer items use {} of this user's {} byte quota. Can't store {} more bytes.",
+                    newer_bytes, max_bytes, new_bytes,
+                ),
             Self::UnknownUser => 
                 write!(f, "This user is not known to the server."),
             Self::ProfileRevoked => 
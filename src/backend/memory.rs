@@ -0,0 +1,724 @@
+//! A Backend that keeps everything in memory, for tests that don't need a
+//! real database.
+//!
+//! It should behave just like the SQLite backend. The shared tests in
+//! [`super::tests`] run against both to check that. Its data is lost when its
+//! Factory and Connections are dropped.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use failure::format_err;
+
+use crate::backend::{self, Error, FnIter};
+use crate::backend::{UserID, Signature, ItemRow, ItemDisplayRow, Timestamp, ServerUser, QuotaDenyReason, UsageStats, SearchScope, Cursor, MigrateOptions, MigrationReport, SchemaStatus, SyncServer, OutboxEntry};
+use crate::backend::verify::{FollowRows, NewestProfiles, ProfileRows, add_profile, check_profiles, check_rules, item_location, max_bytes, verify_item};
+use crate::protos::Item;
+
+mod tests;
+
+/// There's no schema to migrate, but [`backend::Backend::status`] still
+/// reports a version.
+const VERSION: u32 = 1;
+
+/// (user_id, signature)
+type ItemKey = (Vec<u8>, Vec<u8>);
+
+/// (url, user_id, signature)
+type OutboxKey = (String, Vec<u8>, Vec<u8>);
+
+/// How many bytes this server will store for a user.
+#[derive(Clone, Copy)]
+enum Quota {
+    /// Not a user the server stores data for.
+    Unknown,
+    Unlimited,
+    MaxBytes(u64),
+}
+
+/// Opens connections that share the same data.
+#[derive(Clone, Default)]
+pub(crate) struct Factory
+{
+    data: Arc<Mutex<Data>>,
+}
+
+impl Factory {
+    /// A new, uninitialized, backend.
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+}
+
+impl backend::Factory for Factory
+{
+    fn open(&self) -> Result<Box<dyn backend::Backend>, Error>
+    {
+        Ok(Box::new(Connection{ data: self.data.clone() }))
+    }
+}
+
+/// Each method locks the data for as long as it runs, so they're all atomic.
+/// Listings are collected before calling callbacks, so callbacks may use
+/// other Connections.
+pub(crate) struct Connection
+{
+    data: Arc<Mutex<Data>>,
+}
+
+/// The equivalent of the SQLite backend's tables.
+#[derive(Default)]
+struct Data {
+    version: Option<u32>,
+    items: BTreeMap<ItemKey, StoredItem>,
+    profiles: ProfileRows,
+    /// source_user_id -> their follows
+    follows: HashMap<Vec<u8>, FollowRows>,
+    server_users: BTreeMap<Vec<u8>, ServerUser>,
+    blobs: HashMap<Vec<u8>, Vec<u8>>,
+    sync_servers: HashMap<String, SyncServer>,
+    /// (url, user_id) -> received
+    sync_positions: HashMap<(String, Vec<u8>), Timestamp>,
+    outbox: BTreeMap<OutboxKey, OutboxEntry>,
+}
+
+/// An item, and the data that the SQL backends derive from it.
+struct StoredItem {
+    row: ItemRow,
+
+    /// The words in a post's or profile's text, for [`Data::search`].
+    words: BTreeSet<String>,
+
+    /// Only a user's current profile is searchable. (See: [`Data::searchable`])
+    profile: bool,
+
+    /// (hash, size) of each attached file.
+    attachments: Vec<(Vec<u8>, u64)>,
+
+    /// The (user_id, signature) of the item that this one replies to.
+    reply_to: Option<ItemKey>,
+}
+
+impl Connection
+{
+    fn data(&self) -> MutexGuard<'_, Data>
+    {
+        self.data.lock().expect("memory backend lock")
+    }
+
+    /// Find how many bytes this server will store for a user.
+    fn user_quota(&self, user_id: &UserID) -> Quota
+    {
+        let data = self.data();
+        if let Some(server_user) = data.server_users.get(user_id.bytes()) {
+            return match server_user.max_bytes {
+                None => Quota::Unlimited,
+                Some(max_bytes) => Quota::MaxBytes(max_bytes),
+            };
+        }
+
+        // Check those followed by "server users", and use the most generous quota:
+        let mut quota = Quota::Unknown;
+        for (source, follows) in &data.follows {
+            if !data.server_users.contains_key(source) { continue; }
+            let follow_max_bytes = match follows.get(user_id.bytes()) {
+                Some((_, max_bytes)) => *max_bytes,
+                None => continue,
+            };
+            quota = match (quota, follow_max_bytes) {
+                (Quota::Unlimited, _) | (_, None) => Quota::Unlimited,
+                (Quota::MaxBytes(a), Some(b)) => Quota::MaxBytes(std::cmp::max(a, b)),
+                (Quota::Unknown, Some(b)) => Quota::MaxBytes(b),
+            };
+        }
+
+        quota
+    }
+
+    /// Check whether a user may store `new_bytes` more bytes for an item at `timestamp_ms_utc`.
+    fn quota_check(&self, user_id: &UserID, timestamp_ms_utc: i64, new_bytes: u64) -> Option<QuotaDenyReason>
+    {
+        if self.data().revoked(user_id.bytes()) {
+            return Some(QuotaDenyReason::ProfileRevoked);
+        }
+
+        let max_bytes = match self.user_quota(user_id) {
+            Quota::Unknown => return Some(QuotaDenyReason::UnknownUser),
+            Quota::Unlimited => return None,
+            Quota::MaxBytes(max_bytes) => max_bytes,
+        };
+
+        // Items (and their attachments) at least as new as this one take priority:
+        let newer_bytes = self.data().usage(user_id, |item| item.row.timestamp.unix_utc_ms >= timestamp_ms_utc);
+        let newer_bytes = newer_bytes.item_bytes + newer_bytes.attachment_bytes;
+        if newer_bytes + new_bytes > max_bytes {
+            return Some(QuotaDenyReason::NewerItemsExceedQuota{ max_bytes, newer_bytes, new_bytes });
+        }
+
+        None
+    }
+}
+
+impl Data {
+    /// Items before `before` that match `filter`, in [`Cursor`] order. (Newest first.)
+    fn items_before(&self, before: &Cursor, filter: impl Fn(&StoredItem) -> bool) -> Vec<&StoredItem>
+    {
+        let before = cursor_key(before);
+        let mut items: Vec<&StoredItem> = self.items.values()
+            .filter(|item| item_key(&item.row) < before && filter(item))
+            .collect();
+        items.sort_by(|a, b| item_key(&b.row).cmp(&item_key(&a.row)));
+        items
+    }
+
+    fn follows(&self, source: &[u8], followed: &[u8]) -> bool
+    {
+        self.follows.get(source).is_some_and(|follows| follows.contains_key(followed))
+    }
+
+    fn revoked(&self, user_id: &[u8]) -> bool
+    {
+        self.profiles.get(user_id).is_some_and(|(_, _, revoked)| *revoked)
+    }
+
+    /// Like the SQL backends, only index posts and users' current profiles.
+    fn searchable(&self, item: &StoredItem) -> bool
+    {
+        !item.profile || self.profiles.get(item.row.user.bytes())
+            .is_some_and(|(signature, _, _)| signature.as_slice() == item.row.signature.bytes())
+    }
+
+    /// Does this item belong in `user_id`'s feed? (Their own items, and
+    /// those of users they follow.)
+    fn in_feed(&self, user_id: &[u8], item: &StoredItem) -> bool
+    {
+        let author = item.row.user.bytes();
+        author == user_id || self.follows(user_id, author)
+    }
+
+    /// Prefer displaying the name that `viewer` has assigned to the author.
+    fn display_row(&self, item: &StoredItem, viewer: Option<&[u8]>) -> ItemDisplayRow
+    {
+        let author = item.row.user.bytes();
+        let not_empty = |it: &String| !it.trim().is_empty();
+        let follow_display_name = viewer
+            .and_then(|viewer| self.follows.get(viewer))
+            .and_then(|follows| follows.get(author))
+            .map(|(display_name, _)| display_name.clone());
+        let display_name = self.profiles.get(author).map(|(_, display_name, _)| display_name.clone());
+
+        ItemDisplayRow{
+            item: item.row.clone(),
+            display_name: follow_display_name.filter(not_empty).or(display_name).filter(not_empty),
+        }
+    }
+
+    /// Count a user's items (and their uploaded attachments) which match `filter`.
+    fn usage(&self, user_id: &UserID, filter: impl Fn(&StoredItem) -> bool) -> UsageStats
+    {
+        let mut usage = UsageStats::default();
+        let items = self.items.values().filter(|item| item.row.user.bytes() == user_id.bytes() && filter(item));
+        for item in items {
+            usage.item_count += 1;
+            usage.item_bytes += item.row.item_bytes.len() as u64;
+            usage.attachment_bytes += item.attachments.iter()
+                .filter(|(hash, _)| self.blobs.contains_key(hash))
+                .map(|(_, size)| size)
+                .sum::<u64>();
+        }
+        usage
+    }
+
+    /// We're saving a profile. If it's new, update the profile and follows.
+    /// Revocations always replace other profiles, and revoked users follow no one.
+    fn update_profile(&mut self, item_row: &ItemRow, item: &Item)
+    {
+        let user_id = item_row.user.bytes().to_vec();
+        let previous = self.profiles.get(&user_id)
+            .and_then(|(signature, _, revoked)| {
+                let prev = self.items.get(&(user_id.clone(), signature.clone()))?;
+                Some((*revoked, prev.row.timestamp.unix_utc_ms))
+            });
+
+        // Never replace a newer profile's (or a revocation's) metadata:
+        let profile = item.get_profile();
+        if let Some(previous) = previous {
+            if previous >= (profile.get_revoked(), item.timestamp_ms_utc) {
+                return;
+            }
+        }
+
+        // Behavior is undefined if duplicate follows exist in a Profile. So we just replace:
+        let follows: FollowRows = profile.get_follows().iter()
+            .filter(|_| !profile.get_revoked())
+            .map(|follow| (
+                follow.get_user().get_bytes().to_vec(),
+                (follow.get_display_name().to_string(), max_bytes(follow.get_max_bytes())),
+            ))
+            .collect();
+        self.follows.insert(user_id.clone(), follows);
+
+        self.profiles.insert(
+            user_id,
+            (item_row.signature.bytes().to_vec(), profile.get_display_name().to_string(), profile.get_revoked()),
+        );
+    }
+}
+
+/// Where an item is in [`Cursor`] order.
+fn item_key(row: &ItemRow) -> (i64, &[u8], &[u8])
+{
+    (row.timestamp.unix_utc_ms, row.user.bytes(), row.signature.bytes())
+}
+
+/// Where a cursor is in [`Cursor`] order. A cursor without an item comes
+/// after every item at its timestamp. An empty user ID gets us that, since it
+/// sorts before all others.
+fn cursor_key(cursor: &Cursor) -> (i64, &[u8], &[u8])
+{
+    match &cursor.item {
+        Some((user, signature)) => (cursor.timestamp.unix_utc_ms, user.bytes(), signature.bytes()),
+        None => (cursor.timestamp.unix_utc_ms, &[], &[]),
+    }
+}
+
+/// Like [`cursor_key`], but for [`Backend::received_items`](backend::Backend::received_items),
+/// which lists items oldest first.
+fn received_cursor_key(cursor: &Cursor) -> (i64, &[u8], &[u8])
+{
+    match &cursor.item {
+        Some((user, signature)) => (cursor.timestamp.unix_utc_ms, user.bytes(), signature.bytes()),
+        None => (cursor.timestamp.unix_utc_ms.saturating_add(1), &[], &[]),
+    }
+}
+
+fn received_key(row: &ItemRow) -> (i64, &[u8], &[u8])
+{
+    (row.received.unix_utc_ms, row.user.bytes(), row.signature.bytes())
+}
+
+/// Split text into lowercase words. Like the SQL backends' full-text search,
+/// a word is a run of letters and numbers.
+fn words(text: &str) -> impl Iterator<Item=String> + '_
+{
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+}
+
+/// Pass rows to a callback until it returns false.
+fn each<T>(rows: Vec<T>, callback: FnIter<'_, T>) -> Result<(), Error>
+{
+    for row in rows {
+        if !callback(row)? { break; }
+    }
+    Ok(())
+}
+
+impl backend::Backend for Connection
+{
+    fn init(&self) -> Result<MigrationReport, Error>
+    {
+        let mut data = self.data();
+        if let Some(version) = data.version {
+            return Err(Error::Conflict(format!("The database has already been initialized. (version {})", version)));
+        }
+        data.version = Some(VERSION);
+
+        Ok(MigrationReport{
+            from_version: VERSION,
+            to_version: VERSION,
+            applied: vec![],
+            backup_file: None,
+        })
+    }
+
+    fn status(&self) -> Result<SchemaStatus, Error>
+    {
+        Ok(SchemaStatus{
+            version: self.data().version,
+            latest_version: VERSION,
+            pending: vec![],
+        })
+    }
+
+    fn migrate(&self, _options: &MigrateOptions) -> Result<MigrationReport, Error>
+    {
+        let version = match self.data().version {
+            Some(version) => version,
+            None => Err(format_err!("The database hasn't been initialized."))?,
+        };
+
+        Ok(MigrationReport{
+            from_version: version,
+            to_version: version,
+            applied: vec![],
+            backup_file: None,
+        })
+    }
+
+    fn homepage_items(
+        &self,
+        before: &Cursor,
+        callback: &mut dyn FnMut(ItemDisplayRow) -> Result<bool,Error>
+    ) -> Result<(), Error> {
+        let rows = {
+            let data = self.data();
+            let on_homepage = |item: &StoredItem| {
+                data.server_users.get(item.row.user.bytes()).is_some_and(|user| user.on_homepage)
+            };
+            data.items_before(before, on_homepage).into_iter()
+                .map(|item| data.display_row(item, None))
+                .collect()
+        };
+        each(rows, callback)
+    }
+
+    fn user_items(
+        &self,
+        user: &UserID,
+        before: &Cursor,
+        callback: &mut dyn FnMut(ItemRow) -> Result<bool,Error>
+    ) -> Result<(), Error> {
+        let rows = {
+            let data = self.data();
+            data.items_before(before, |item| item.row.user.bytes() == user.bytes()).into_iter()
+                .map(|item| item.row.clone())
+                .collect()
+        };
+        each(rows, callback)
+    }
+
+    fn user_feed_items(
+        &self,
+        user_id: &UserID,
+        before: &Cursor,
+        callback: &mut dyn FnMut(ItemDisplayRow) -> Result<bool, Error>,
+    ) -> Result<(), Error> {
+        let rows = {
+            let data = self.data();
+            data.items_before(before, |item| data.in_feed(user_id.bytes(), item)).into_iter()
+                .map(|item| data.display_row(item, Some(user_id.bytes())))
+                .collect()
+        };
+        each(rows, callback)
+    }
+
+    fn search_items<'a>(
+        &self,
+        query: &str,
+        scope: &SearchScope,
+        before: &Cursor,
+        callback: FnIter<'a, ItemDisplayRow>,
+    ) -> Result<(), Error> {
+        let query: BTreeSet<String> = words(query).collect();
+        if query.is_empty() {
+            return Ok(());
+        }
+
+        let rows = {
+            let data = self.data();
+            let in_scope = |item: &StoredItem| match scope {
+                SearchScope::All => true,
+                SearchScope::User(user_id) => item.row.user.bytes() == user_id.bytes(),
+                SearchScope::Feed(user_id) => data.in_feed(user_id.bytes(), item),
+            };
+            data.items_before(before, |item| {
+                query.is_subset(&item.words) && data.searchable(item) && in_scope(item)
+            }).into_iter()
+                .map(|item| data.display_row(item, None))
+                .collect()
+        };
+        each(rows, callback)
+    }
+
+    fn reply_items<'a>(
+        &self,
+        user_id: &UserID,
+        signature: &Signature,
+        followed_by: Option<&UserID>,
+        before: &Cursor,
+        callback: FnIter<'a, ItemDisplayRow>,
+    ) -> Result<(), Error> {
+        let reply_to = (user_id.bytes().to_vec(), signature.bytes().to_vec());
+        let followed_by = followed_by.map(|user| user.bytes());
+        let rows = {
+            let data = self.data();
+            let is_reply = |item: &StoredItem| {
+                item.reply_to.as_ref() == Some(&reply_to)
+                && followed_by.is_none_or(|user_id| data.in_feed(user_id, item))
+            };
+            data.items_before(before, is_reply).into_iter()
+                .map(|item| data.display_row(item, followed_by))
+                .collect()
+        };
+        each(rows, callback)
+    }
+
+    fn received_items<'a>(
+        &self,
+        user: Option<&UserID>,
+        after: &Cursor,
+        callback: FnIter<'a, ItemRow>,
+    ) -> Result<(), Error> {
+        let after = received_cursor_key(after);
+        let rows = {
+            let data = self.data();
+            let mut rows: Vec<ItemRow> = data.items.values()
+                .map(|item| &item.row)
+                .filter(|row| received_key(row) > after)
+                .filter(|row| user.is_none_or(|user| row.user.bytes() == user.bytes()))
+                .cloned()
+                .collect();
+            rows.sort_by(|a, b| received_key(a).cmp(&received_key(b)));
+            rows
+        };
+        each(rows, callback)
+    }
+
+    fn user_item(&self, user: &UserID, signature: &Signature) -> Result<Option<ItemRow>, Error> {
+        let key = (user.bytes().to_vec(), signature.bytes().to_vec());
+        Ok(self.data().items.get(&key).map(|item| item.row.clone()))
+    }
+
+    fn user_item_exists(&self, user: &UserID, signature: &Signature) -> Result<bool, Error> {
+        let key = (user.bytes().to_vec(), signature.bytes().to_vec());
+        Ok(self.data().items.contains_key(&key))
+    }
+
+    fn save_user_item(&mut self, row: &ItemRow, item: &Item) -> Result<(), Error>
+    {
+        let mut data = self.data();
+        let key = (row.user.bytes().to_vec(), row.signature.bytes().to_vec());
+        if data.items.contains_key(&key) {
+            return Err(Error::Conflict(format!("Item {} already exists", item_location(&key.0, &key.1))));
+        }
+
+        let mut stored = StoredItem{
+            row: row.clone(),
+            words: BTreeSet::new(),
+            profile: item.has_profile(),
+            attachments: vec![],
+            reply_to: None,
+        };
+        if item.has_post() {
+            let post = item.get_post();
+            stored.words.extend(words(post.get_title()).chain(words(post.get_body())));
+            stored.attachments = post.get_attachments().iter()
+                .map(|attachment| (attachment.get_hash().to_vec(), attachment.get_size()))
+                .collect();
+            if post.has_reply_to() {
+                let reply_to = post.get_reply_to();
+                stored.reply_to = Some((
+                    reply_to.get_user_id().get_bytes().to_vec(),
+                    reply_to.get_signature().get_bytes().to_vec(),
+                ));
+            }
+        } else if item.has_profile() {
+            let profile = item.get_profile();
+            stored.words.extend(words(profile.get_display_name()).chain(words(profile.get_about())));
+        }
+        data.items.insert(key, stored);
+
+        if item.has_profile() {
+            data.update_profile(row, item);
+        }
+
+        Ok(())
+    }
+
+    fn server_user(&self, user: &UserID) -> Result<Option<ServerUser>, Error> {
+        Ok(self.data().server_users.get(user.bytes()).cloned())
+    }
+
+    fn server_users<'a>(&self, cb: FnIter<'a, ServerUser>) -> Result<(), Error> {
+        let mut users: Vec<ServerUser> = self.data().server_users.values().cloned().collect();
+        // server_users is already sorted by user ID, and this sort is stable:
+        users.sort_by_key(|user| user.on_homepage);
+        each(users, cb)
+    }
+
+    fn add_server_user(&self, server_user: &ServerUser) -> Result<(), Error> {
+        let mut data = self.data();
+        let user_id = server_user.user.bytes().to_vec();
+        if data.server_users.contains_key(&user_id) {
+            return Err(Error::Conflict(format!("{} is already a server user", server_user.user.to_base58())));
+        }
+        data.server_users.insert(user_id, server_user.clone());
+        Ok(())
+    }
+
+    fn update_server_user(&self, server_user: &ServerUser) -> Result<(), Error> {
+        match self.data().server_users.get_mut(server_user.user.bytes()) {
+            Some(existing) => *existing = server_user.clone(),
+            None => return Err(Error::NotFound(format!("{} is not a server user", server_user.user.to_base58()))),
+        }
+        Ok(())
+    }
+
+    fn remove_server_user(&self, user: &UserID) -> Result<(), Error> {
+        if self.data().server_users.remove(user.bytes()).is_none() {
+            return Err(Error::NotFound(format!("{} is not a server user", user.to_base58())));
+        }
+        Ok(())
+    }
+
+    fn user_usage(&self, user: &UserID) -> Result<UsageStats, Error> {
+        Ok(self.data().usage(user, |_| true))
+    }
+
+    fn user_profile(&self, user: &UserID) -> Result<Option<ItemRow>, Error> {
+        let data = self.data();
+        let row = data.profiles.get(user.bytes())
+            .and_then(|(signature, _, _)| data.items.get(&(user.bytes().to_vec(), signature.clone())))
+            .map(|item| item.row.clone());
+        Ok(row)
+    }
+
+    fn user_known(&self, user_id: &UserID) -> Result<bool, Error> {
+        let data = self.data();
+        let known = data.server_users.contains_key(user_id.bytes())
+            || data.server_users.keys().any(|source| data.follows(source, user_id.bytes()));
+        Ok(known && !data.revoked(user_id.bytes()))
+    }
+
+    fn user_revoked(&self, user_id: &UserID) -> Result<bool, Error> {
+        Ok(self.data().revoked(user_id.bytes()))
+    }
+
+    fn quota_check_item(&self, user_id: &UserID, bytes: &[u8], item: &Item) -> Result<Option<QuotaDenyReason>, Error> {
+        Ok(self.quota_check(user_id, item.timestamp_ms_utc, bytes.len() as u64))
+    }
+
+    fn quota_check_attachment(&self, user_id: &UserID, item: &Item, size: u64) -> Result<Option<QuotaDenyReason>, Error> {
+        Ok(self.quota_check(user_id, item.timestamp_ms_utc, size))
+    }
+
+    fn save_blob(&self, hash: &[u8], data: &[u8]) -> Result<(), Error> {
+        // Content-addressed, so if it already exists, it's identical:
+        self.data().blobs.entry(hash.to_vec()).or_insert_with(|| data.to_vec());
+        Ok(())
+    }
+
+    fn blob(&self, hash: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.data().blobs.get(hash).cloned())
+    }
+
+    fn blob_exists(&self, hash: &[u8]) -> Result<bool, Error> {
+        Ok(self.data().blobs.contains_key(hash))
+    }
+
+    fn sync_server(&self, url: &str) -> Result<Option<SyncServer>, Error> {
+        Ok(self.data().sync_servers.get(url).cloned())
+    }
+
+    fn save_sync_server(&self, server: &SyncServer) -> Result<(), Error> {
+        self.data().sync_servers.insert(server.url.clone(), server.clone());
+        Ok(())
+    }
+
+    fn sync_position(&self, url: &str, user: &UserID) -> Result<Option<Timestamp>, Error> {
+        let key = (url.to_string(), user.bytes().to_vec());
+        Ok(self.data().sync_positions.get(&key).copied())
+    }
+
+    fn save_sync_position(&self, url: &str, user: &UserID, received: Timestamp) -> Result<(), Error> {
+        self.data().sync_positions.insert((url.to_string(), user.bytes().to_vec()), received);
+        Ok(())
+    }
+
+    fn add_outbox_entry(&self, url: &str, user: &UserID, signature: &Signature) -> Result<(), Error> {
+        let key = (url.to_string(), user.bytes().to_vec(), signature.bytes().to_vec());
+        self.data().outbox.entry(key).or_insert_with(|| OutboxEntry{
+            url: url.to_string(),
+            user: user.clone(),
+            signature: signature.clone(),
+            attempts: 0,
+            next_attempt: Some(Timestamp::now()),
+            last_error: None,
+        });
+        Ok(())
+    }
+
+    fn outbox_entries<'a>(&self, due: Option<Timestamp>, callback: FnIter<'a, OutboxEntry>) -> Result<(), Error> {
+        let due = due.map(|t| t.unix_utc_ms);
+        let mut entries: Vec<OutboxEntry> = self.data().outbox.values()
+            .filter(|entry| match (due, entry.next_attempt) {
+                (None, _) => true,
+                (Some(due), Some(next)) => next.unix_utc_ms <= due,
+                (Some(_), None) => false,
+            })
+            .cloned()
+            .collect();
+        // Entries we've given up on (None) sort last. The outbox is already
+        // sorted by (url, user_id, signature), and this sort is stable:
+        entries.sort_by_key(|entry| (entry.next_attempt.is_none(), entry.next_attempt.map(|t| t.unix_utc_ms)));
+        each(entries, callback)
+    }
+
+    fn update_outbox_entry(&self, entry: &OutboxEntry) -> Result<(), Error> {
+        let key = (entry.url.clone(), entry.user.bytes().to_vec(), entry.signature.bytes().to_vec());
+        match self.data().outbox.get_mut(&key) {
+            Some(existing) => *existing = entry.clone(),
+            None => return Err(Error::NotFound(format!("{} is not queued for {}", entry.signature.to_base58(), entry.url))),
+        }
+        Ok(())
+    }
+
+    fn remove_outbox_entry(&self, url: &str, user: &UserID, signature: &Signature) -> Result<(), Error> {
+        let key = (url.to_string(), user.bytes().to_vec(), signature.bytes().to_vec());
+        self.data().outbox.remove(&key);
+        Ok(())
+    }
+
+    fn verify(&mut self, options: &backend::VerifyOptions) -> Result<backend::VerifyReport, Error> {
+        let mut report = backend::VerifyReport::default();
+        // There's no quarantine table, so bad items are just dropped.
+        let remove_fix = if options.quarantine { Some("Removed") } else { None };
+        let rebuild_fix = if options.quarantine { Some("Rebuilt from the newest profile") } else { None };
+
+        let mut data = self.data();
+        let mut bad_items: Vec<ItemKey> = vec![];
+        let mut newest_profiles = NewestProfiles::new();
+        for (key, stored) in &data.items {
+            report.items += 1;
+            let row = &stored.row;
+            match verify_item(&key.0, &key.1, row.timestamp.unix_utc_ms, &row.item_bytes) {
+                Ok((user, signature, item)) => {
+                    check_rules(&user, &signature, &item, &mut report);
+                    add_profile(&mut newest_profiles, row.clone(), item);
+                },
+                Err(reason) => {
+                    report.problems.push(backend::VerifyProblem{
+                        location: item_location(&key.0, &key.1),
+                        description: reason,
+                        fix: remove_fix,
+                    });
+                    bad_items.push(key.clone());
+                },
+            }
+        }
+
+        // Users whose profile data we need to rebuild, and from which profile:
+        let rebuilds = check_profiles(&newest_profiles, &data.profiles, &data.follows, rebuild_fix, &mut report);
+
+        if !options.quarantine {
+            return Ok(report);
+        }
+
+        for key in &bad_items {
+            data.items.remove(key);
+        }
+
+        for (user_id, expected) in &rebuilds {
+            data.profiles.remove(user_id);
+            data.follows.remove(user_id);
+            if let Some((row, item)) = expected {
+                data.update_profile(row, item);
+            }
+        }
+
+        Ok(report)
+    }
+}
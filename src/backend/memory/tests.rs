@@ -0,0 +1,9 @@
+use super::*;
+
+fn with_factory(_name: &str, test: fn(&dyn backend::Factory)) {
+    let factory = Factory::new();
+    backend::Factory::open(&factory).unwrap().init().unwrap();
+    test(&factory);
+}
+
+crate::backend::tests::backend_tests!(with_factory);
@@ -0,0 +1,128 @@
+//! Runs [`Backend`] calls on a pool of blocking threads, so that async code
+//! (like the web server's request handlers) can keep handling other requests
+//! while it waits on the database.
+
+use std::sync::Arc;
+
+use actix_web::error::BlockingError;
+use actix_web::web;
+use failure::format_err;
+use futures::SinkExt as _;
+use futures::channel::mpsc;
+use futures::executor::block_on;
+
+use crate::backend::{Backend, Cursor, Error, Factory, FnIter, ItemDisplayRow, ItemRow, SearchScope, Signature, UserID};
+
+/// How many rows a listing may get ahead of its stream.
+const STREAM_BUFFER: usize = 32;
+
+/// A stream of rows from a Backend listing. Dropping it stops the listing.
+pub(crate) type RowStream<T> = mpsc::Receiver<Result<T, Error>>;
+
+/// An async interface to a [`Factory`]'s Backends.
+///
+/// Each call opens its own Backend on the blocking thread pool. Listings are
+/// streamed back instead of calling [`FnIter`] callbacks.
+#[derive(Clone)]
+pub(crate) struct AsyncBackend {
+    factory: Arc<dyn Factory>,
+}
+
+impl AsyncBackend {
+    pub fn new(factory: Arc<dyn Factory>) -> Self {
+        AsyncBackend{ factory }
+    }
+
+    /// Open a Backend, and call `f` with it, on the blocking thread pool.
+    pub async fn run<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut dyn Backend) -> Result<T, Error> + Send + 'static,
+    {
+        let factory = self.factory.clone();
+        let result = web::block(move || {
+            let mut backend = factory.open()?;
+            f(backend.as_mut())
+        }).await;
+
+        result.map_err(|error| match error {
+            BlockingError::Error(error) => error,
+            BlockingError::Canceled => Error::Storage(format_err!("The backend's thread pool shut down")),
+        })
+    }
+
+    /// Run a Backend listing on the blocking thread pool, and stream its rows.
+    ///
+    /// The listing waits while the stream's buffer is full, so it only reads
+    /// as far ahead as the stream's consumer. Errors end the stream.
+    ///
+    /// Must be called within an actix System.
+    pub fn stream<T, F>(&self, list: F) -> RowStream<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn Backend, FnIter<'_, T>) -> Result<(), Error> + Send + 'static,
+    {
+        let (mut sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        let factory = self.factory.clone();
+        let task = web::block(move || -> Result<(), Error> {
+            let mut rows = sender.clone();
+            let result = factory.open().and_then(|backend| {
+                list(backend.as_ref(), &mut |row| {
+                    // Fails once the stream has been dropped, which stops the listing:
+                    Ok(block_on(rows.send(Ok(row))).is_ok())
+                })
+            });
+            if let Err(error) = result {
+                let _ = block_on(sender.send(Err(error)));
+            }
+            Ok(())
+        });
+
+        // web::block() doesn't start until it's polled:
+        actix_web::rt::spawn(async move {
+            let _ = task.await;
+        });
+        receiver
+    }
+
+    /// See: [`Backend::homepage_items`]
+    pub fn homepage_items(&self, before: Cursor) -> RowStream<ItemDisplayRow> {
+        self.stream(move |backend, callback| backend.homepage_items(&before, callback))
+    }
+
+    /// See: [`Backend::user_items`]
+    pub fn user_items(&self, user: UserID, before: Cursor) -> RowStream<ItemRow> {
+        self.stream(move |backend, callback| backend.user_items(&user, &before, callback))
+    }
+
+    /// See: [`Backend::user_feed_items`]
+    pub fn user_feed_items(&self, user: UserID, before: Cursor) -> RowStream<ItemDisplayRow> {
+        self.stream(move |backend, callback| backend.user_feed_items(&user, &before, callback))
+    }
+
+    /// See: [`Backend::reply_items`]
+    pub fn reply_items(
+        &self,
+        user: UserID,
+        signature: Signature,
+        followed_by: Option<UserID>,
+        before: Cursor,
+    ) -> RowStream<ItemDisplayRow> {
+        self.stream(move |backend, callback| {
+            backend.reply_items(&user, &signature, followed_by.as_ref(), &before, callback)
+        })
+    }
+
+    /// See: [`Backend::search_items`]
+    pub fn search_items(&self, query: String, scope: SearchScope, before: Cursor) -> RowStream<ItemDisplayRow> {
+        self.stream(move |backend, callback| backend.search_items(&query, &scope, &before, callback))
+    }
+
+    /// See: [`Backend::received_items`]
+    pub fn received_items(&self, user: Option<UserID>, after: Cursor) -> RowStream<ItemRow> {
+        self.stream(move |backend, callback| backend.received_items(user.as_ref(), &after, callback))
+    }
+}
+
+#[cfg(test)]
+mod tests;
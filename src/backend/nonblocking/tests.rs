@@ -0,0 +1,105 @@
+use super::*;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures::StreamExt as _;
+use futures::channel::oneshot;
+
+use crate::backend::{ServerUser, Timestamp, memory};
+
+fn backend() -> AsyncBackend {
+    let factory = memory::Factory::new();
+    factory.open().unwrap().init().unwrap();
+    AsyncBackend::new(Arc::new(factory))
+}
+
+fn test_user(n: u8) -> UserID {
+    UserID::from_vec(vec![n; 32]).unwrap()
+}
+
+#[test]
+fn run_calls() {
+    actix_web::rt::System::new("test").block_on(async {
+        let backend = backend();
+        let user = test_user(1);
+
+        let server_user = ServerUser{ user: user.clone(), notes: "".into(), on_homepage: true, max_bytes: None, max_item_size: None };
+        backend.run(move |backend| backend.add_server_user(&server_user)).await.unwrap();
+        let known = {
+            let user = user.clone();
+            backend.run(move |backend| backend.user_known(&user)).await.unwrap()
+        };
+        assert!(known);
+
+        // Errors come back from the pool:
+        let server_user = ServerUser{ user, notes: "".into(), on_homepage: true, max_bytes: None, max_item_size: None };
+        assert!(backend.run(move |backend| backend.add_server_user(&server_user)).await.is_err());
+    });
+}
+
+#[test]
+fn stream_rows() {
+    actix_web::rt::System::new("test").block_on(async {
+        let backend = backend();
+        let user = test_user(1);
+        {
+            let user = user.clone();
+            backend.run(move |backend| {
+                for n in 1..=100u8 {
+                    let row = ItemRow{
+                        user: user.clone(),
+                        signature: Signature::from_vec(vec![n; 64]).unwrap(),
+                        timestamp: Timestamp{ unix_utc_ms: n.into() },
+                        received: Timestamp{ unix_utc_ms: n.into() },
+                        item_bytes: vec![],
+                    };
+                    backend.save_user_item(&row, &crate::protos::Item::new())?;
+                }
+                Ok(())
+            }).await.unwrap();
+        }
+
+        let rows: Vec<_> = backend.user_items(user, Cursor::before(Timestamp{ unix_utc_ms: 1000 })).collect().await;
+        let found: Vec<u8> = rows.into_iter().map(|row| row.unwrap().signature.bytes()[0]).collect();
+        assert_eq!((1..=100).rev().collect::<Vec<u8>>(), found);
+    });
+}
+
+#[test]
+fn dropping_a_stream_stops_the_listing() {
+    actix_web::rt::System::new("test").block_on(async {
+        let backend = backend();
+        let listed = Arc::new(AtomicUsize::new(0));
+        let (done, finished) = oneshot::channel();
+
+        let counter = listed.clone();
+        let mut rows = backend.stream(move |_backend, callback| {
+            for n in 0..10_000 {
+                counter.fetch_add(1, Ordering::SeqCst);
+                if !callback(n)? { break; }
+            }
+            let _ = done.send(());
+            Ok(())
+        });
+
+        assert_eq!(Some(0), rows.next().await.map(|row| row.unwrap()));
+        drop(rows);
+        finished.await.unwrap();
+        assert!(listed.load(Ordering::SeqCst) < 100, "listed {} rows", listed.load(Ordering::SeqCst));
+    });
+}
+
+#[test]
+fn stream_errors() {
+    actix_web::rt::System::new("test").block_on(async {
+        let backend = backend();
+        let rows: Vec<Result<u32, Error>> = backend.stream(|_backend, callback| {
+            callback(1)?;
+            Err(format_err!("Nope.").into())
+        }).collect().await;
+
+        assert_eq!(2, rows.len());
+        assert_eq!(1, *rows[0].as_ref().unwrap());
+        assert_eq!("Nope.", rows[1].as_ref().unwrap_err().to_string());
+    });
+}
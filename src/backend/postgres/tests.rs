@@ -0,0 +1,162 @@
+//! These need a PostgreSQL database to run in. Set FEOBLOG_TEST_DATABASE_URL
+//! to its URL to run them. (ex: postgres://postgres@localhost/feoblog_test)
+//! Each test creates (and drops) its own schema there.
+//!
+//! They fail without one, so that building with the postgres feature can't
+//! look like it passed them.
+
+use super::*;
+
+const URL_VAR: &str = "FEOBLOG_TEST_DATABASE_URL";
+
+/// A new schema in the test database, which is dropped when this is.
+struct TempSchema {
+    admin: postgres::Client,
+    name: String,
+    factory: Factory,
+}
+
+impl TempSchema {
+    /// Panics if there's no test database.
+    fn new(name: &str) -> Self {
+        let url = std::env::var(URL_VAR)
+            .unwrap_or_else(|_| panic!("Set {} to run the PostgreSQL tests.", URL_VAR));
+
+        let mut config: postgres::Config = url.parse().expect("database URL");
+        let mut admin = config.connect(NoTls).expect("connecting to the test database");
+        let name = format!("feoblog_test_{}_{}", std::process::id(), name);
+        admin.batch_execute(&format!("DROP SCHEMA IF EXISTS {0} CASCADE; CREATE SCHEMA {0}", name)).unwrap();
+
+        config.options(&format!("-csearch_path={}", name));
+        let factory = Factory::with_config(config).unwrap();
+        TempSchema{ admin, name, factory }
+    }
+
+    fn connection(&self) -> Connection {
+        Connection{ conn: RefCell::new(self.factory.pool.get().expect("connection")) }
+    }
+}
+
+impl Drop for TempSchema {
+    fn drop(&mut self) {
+        let _ = self.admin.batch_execute(&format!("DROP SCHEMA {} CASCADE", self.name));
+    }
+}
+
+fn with_factory(name: &str, test: fn(&dyn backend::Factory)) {
+    let schema = TempSchema::new(name);
+    schema.connection().init().unwrap();
+    test(&schema.factory);
+}
+
+crate::backend::tests::backend_tests!(with_factory);
+
+fn add_widget_table(tx: &mut Transaction) -> Result<(), Error> {
+    tx.batch_execute("CREATE TABLE widget(name TEXT)")?;
+    Ok(())
+}
+
+fn add_widget_color(tx: &mut Transaction) -> Result<(), Error> {
+    tx.batch_execute("ALTER TABLE widget ADD COLUMN color TEXT")?;
+    Ok(())
+}
+
+fn fail(_tx: &mut Transaction) -> Result<(), Error> {
+    Err(format_err!("Nope.").into())
+}
+
+const TEST_MIGRATIONS: &[Migration] = &[
+    Migration{ from_version: BASE_VERSION, description: "Add widget table", apply: add_widget_table },
+    Migration{ from_version: BASE_VERSION + 1, description: "Add widget colors", apply: add_widget_color },
+];
+
+fn table_exists(conn: &Connection, name: &str) -> bool {
+    conn.client().query_one("SELECT to_regclass($1) IS NOT NULL", &[&name]).unwrap().get(0)
+}
+
+#[test]
+fn migrations_are_contiguous() {
+    for (index, migration) in MIGRATIONS.iter().enumerate() {
+        assert_eq!(BASE_VERSION + index as u32, migration.from_version, "{}", migration.description);
+    }
+}
+
+#[test]
+fn init_new_database() {
+    let schema = TempSchema::new("init");
+    let conn = schema.connection();
+
+    let status = conn.status().unwrap();
+    assert_eq!(None, status.version);
+    assert!(status.pending.is_empty());
+
+    let report = conn.init().unwrap();
+    assert_eq!(status.latest_version, report.to_version);
+    assert!(report.backup_file.is_none());
+
+    let status = conn.status().unwrap();
+    assert_eq!(Some(status.latest_version), status.version);
+    assert!(status.pending.is_empty());
+
+    // Can't initialize twice:
+    assert!(conn.init().is_err());
+
+    // Migrating an up-to-date DB is a no-op:
+    let report = conn.migrate(&MigrateOptions::default()).unwrap();
+    assert!(report.applied.is_empty());
+    assert!(report.backup_file.is_none());
+}
+
+#[test]
+fn migrate_stepwise() {
+    let schema = TempSchema::new("stepwise");
+    let conn = schema.connection();
+    conn.setup_new().unwrap();
+
+    let options = MigrateOptions{ dry_run: false, backup: false };
+    let report = conn.migrate_with(&TEST_MIGRATIONS[..1], &options).unwrap();
+    assert_eq!((BASE_VERSION, BASE_VERSION + 1), (report.from_version, report.to_version));
+    assert!(table_exists(&conn, "widget"));
+
+    // Only the remaining migration is applied:
+    let report = conn.migrate_with(TEST_MIGRATIONS, &options).unwrap();
+    assert_eq!(vec!["Add widget colors"], report.applied);
+    assert_eq!(Some(BASE_VERSION + 2), conn.get_version().unwrap());
+
+    // Code that doesn't know about newer versions refuses to touch the DB:
+    assert!(conn.migrate_with(&TEST_MIGRATIONS[..1], &options).is_err());
+}
+
+#[test]
+fn migrate_dry_run() {
+    let schema = TempSchema::new("dry_run");
+    let conn = schema.connection();
+    conn.setup_new().unwrap();
+
+    // There's nothing to back up to, so no backup is made:
+    let options = MigrateOptions{ dry_run: true, backup: true };
+    let report = conn.migrate_with(TEST_MIGRATIONS, &options).unwrap();
+    assert_eq!(BASE_VERSION + 2, report.to_version);
+    assert_eq!(2, report.applied.len());
+    assert!(report.backup_file.is_none());
+
+    assert!(!table_exists(&conn, "widget"));
+    assert_eq!(Some(BASE_VERSION), conn.get_version().unwrap());
+}
+
+#[test]
+fn migrate_failure_rolls_back() {
+    let schema = TempSchema::new("rollback");
+    let conn = schema.connection();
+    conn.setup_new().unwrap();
+
+    let migrations = &[
+        Migration{ from_version: BASE_VERSION, description: "Add widget table", apply: add_widget_table },
+        Migration{ from_version: BASE_VERSION + 1, description: "Fail", apply: fail },
+    ];
+    let options = MigrateOptions{ dry_run: false, backup: false };
+    assert!(conn.migrate_with(migrations, &options).is_err());
+
+    assert!(!table_exists(&conn, "widget"));
+    assert_eq!(Some(BASE_VERSION), conn.get_version().unwrap());
+}
@@ -5,16 +5,105 @@
 //! Mostly, this makes data management trivial since it's all in one file.
 //! But if performance is an issue we can implement a different backend.
 
-use crate::protos::Item;
+use crate::protos::{Item, ItemRef};
 use rusqlite::NO_PARAMS;
 use crate::backend::FnIter;
-use crate::backend::{self, UserID, Signature, ItemRow, ItemDisplayRow, Timestamp, ServerUser, QuotaDenyReason};
+use crate::backend::Backend as _;
+use crate::backend::verify::{FollowRows, NewestProfiles, ProfileRows, add_profile, check_profiles, check_rules, item_location, verify_item};
+use crate::backend::{self, Error, UserID, Signature, ItemRow, ItemDisplayRow, Timestamp, ServerUser, QuotaDenyReason, UsageStats, SearchScope, Cursor, MigrateOptions, MigrationReport, SchemaStatus};
 
-use failure::{Error, bail, ResultExt};
+use std::collections::HashMap;
+
+use failure::{ResultExt, format_err};
 use protobuf::Message as _;
-use rusqlite::{params, OptionalExtension, Row};
+use rusqlite::{named_params, params, OptionalExtension, Row};
+
+#[cfg(test)]
+mod tests;
+
+/// The schema version created by [`Connection::setup_new`].
+/// Newer versions are reached by applying [`MIGRATIONS`].
+const BASE_VERSION: u32 = 3;
+
+/// All schema migrations, in order.
+///
+/// `MIGRATIONS[n]` upgrades the schema from `BASE_VERSION + n` to
+/// `BASE_VERSION + n + 1`. Once a migration has been released, don't modify
+/// it. Add a new one instead.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from_version: 3,
+        description: "Add per-follow quotas",
+        apply: add_follow_quotas,
+    },
+    Migration {
+        from_version: 4,
+        description: "Add file attachments",
+        apply: add_attachments,
+    },
+    Migration {
+        from_version: 5,
+        description: "Index replies",
+        apply: add_replies,
+    },
+    Migration {
+        from_version: 6,
+        description: "Add full-text search",
+        apply: add_search,
+    },
+    Migration {
+        from_version: 7,
+        description: "Add sync state",
+        apply: add_sync_state,
+    },
+    Migration {
+        from_version: 8,
+        description: "Add outbox",
+        apply: add_outbox,
+    },
+    Migration {
+        from_version: 9,
+        description: "Add item quarantine",
+        apply: add_quarantine,
+    },
+    Migration {
+        from_version: 10,
+        description: "Add per-user item size limits",
+        apply: add_max_item_size,
+    },
+    Migration {
+        from_version: 11,
+        description: "Add profile revocations",
+        apply: add_profile_revoked,
+    },
+    Migration {
+        from_version: 12,
+        description: "Only index users' current profiles for search",
+        apply: unindex_old_profiles,
+    },
+];
+
+/// How many bytes this server will store for a user.
+#[derive(Clone, Copy)]
+enum Quota {
+    /// Not a user the server stores data for.
+    Unknown,
+    Unlimited,
+    MaxBytes(u64),
+}
 
-const CURRENT_VERSION: u32 = 3;
+/// One step in upgrading the database schema.
+struct Migration {
+    /// The version that this migration upgrades from. (It upgrades to from_version + 1.)
+    from_version: u32,
+
+    /// A short, human-readable description of the change.
+    description: &'static str,
+
+    /// Applies the change. This is run inside a transaction, so must not
+    /// execute statements (like VACUUM) that SQLite can't run in one.
+    apply: fn(&Connection) -> Result<(), Error>,
+}
 
 type Pool = r2d2::Pool<r2d2_sqlite::SqliteConnectionManager>;
 type PConn = r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>;
@@ -52,15 +141,18 @@ pub(crate) struct Connection
 
 impl Connection
 {
+    /// Create the schema at BASE_VERSION.
     fn setup_new(&self) -> Result<(), Error>
     {
+        let tx = self.conn.unchecked_transaction()?;
+
         self.run("
             CREATE TABLE version (
                 -- The current version of the database schema.
                 version INTEGER
             )
         ")?;
-        self.run("INSERT INTO version VALUES(3)")?;
+        self.conn.execute("INSERT INTO version VALUES(?)", params![BASE_VERSION])?;
 
         self.run("
             CREATE TABLE item(
@@ -166,17 +258,7 @@ impl Connection
             ON profile(user_id)
         ")?;
 
-
-        // TODO: Store file attachments, etc:
-        // self.run("
-        //     CREATE TABLE blob(
-        //         -- A content-addressable store for many kinds of data.
-        //         hash BLOB PRIMARY KEY, -- multihash of the data.
-        //         data BLOB
-        //     )
-        // ")?; 
-
-
+        tx.commit()?;
         Ok(())
     }
 
@@ -213,27 +295,496 @@ impl Connection
         Ok(version)
     }
 
+    fn migrate_with(&self, migrations: &[Migration], options: &MigrateOptions) -> Result<MigrationReport, Error>
+    {
+        let latest = BASE_VERSION + migrations.len() as u32;
+        let version = match self.get_version()? {
+            Some(version) => version,
+            None => Err(format_err!("The database has not been initialized."))?,
+        };
+
+        if version > latest {
+            Err(format_err!(
+                "DB version ({}) newer than current version ({})",
+                version,
+                latest
+            ))?;
+        }
+        if version < BASE_VERSION {
+            Err(format_err!(
+                "DB version ({}) is older than the oldest supported version ({})",
+                version,
+                BASE_VERSION
+            ))?;
+        }
+
+        let mut report = MigrationReport {
+            from_version: version,
+            to_version: version,
+            applied: vec![],
+            backup_file: None,
+        };
+
+        let pending = &migrations[(version - BASE_VERSION) as usize ..];
+        if pending.is_empty() {
+            return Ok(report);
+        }
+
+        if options.backup && !options.dry_run {
+            report.backup_file = self.backup(version)?;
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        for migration in pending {
+            if migration.from_version != report.to_version {
+                Err(format_err!(
+                    "Migration \"{}\" expects version {}, but DB is at version {}",
+                    migration.description,
+                    migration.from_version,
+                    report.to_version,
+                ))?;
+            }
+
+            (migration.apply)(self).with_context(|_| {
+                format!("Error migrating from version {}: {}", migration.from_version, migration.description)
+            })?;
+
+            report.to_version = migration.from_version + 1;
+            tx.execute("UPDATE version SET version = ?", params![report.to_version])?;
+            report.applied.push(migration.description);
+        }
+
+        if options.dry_run {
+            tx.rollback()?;
+        } else {
+            tx.commit().context("committing migrations")?;
+        }
+
+        Ok(report)
+    }
+
+    /// Find how many bytes this server will store for a user.
+    fn user_quota(&self, user_id: &UserID) -> Result<Quota, Error>
+    {
+        if let Some(server_user) = self.server_user(user_id)? {
+            return Ok(match server_user.max_bytes {
+                None => Quota::Unlimited,
+                Some(max_bytes) => Quota::MaxBytes(max_bytes),
+            });
+        };
+
+        // Check those followed by "server users":
+        let mut statement = self.conn.prepare("
+            SELECT
+                f.max_bytes
+            FROM
+                follow AS f
+                INNER JOIN server_user AS su ON su.user_id = f.source_user_id
+            WHERE
+                f.followed_user_id = ?
+        ")?;
+        let mut rows = statement.query(params![user_id.bytes()])?;
+
+        // Use the most generous quota of all follows:
+        // (Revoked users' follows have already been removed.)
+        let mut quota = Quota::Unknown;
+        while let Some(row) = rows.next()? {
+            quota = match (quota, from_max_bytes(row.get(0)?)) {
+                (Quota::Unlimited, _) | (_, None) => Quota::Unlimited,
+                (Quota::MaxBytes(a), Some(b)) => Quota::MaxBytes(std::cmp::max(a, b)),
+                (Quota::Unknown, Some(b)) => Quota::MaxBytes(b),
+            };
+        }
+
+        // TODO: When "pinning" is implemented, allow posting items which are pinned by server users and their follows.
+        // TODO: I've since decided that "pinning" might be prone to abuse. I should write up my thoughts there.
+
+        Ok(quota)
+    }
+
+    /// Check whether a user may store `new_bytes` more bytes for an item at `timestamp_ms_utc`.
+    fn quota_check(&self, user_id: &UserID, timestamp_ms_utc: i64, new_bytes: u64) -> Result<Option<QuotaDenyReason>, Error>
+    {
+        if self.user_revoked(user_id)? {
+            return Ok(Some(QuotaDenyReason::ProfileRevoked));
+        }
+
+        let max_bytes = match self.user_quota(user_id)? {
+            Quota::Unknown => return Ok(Some(QuotaDenyReason::UnknownUser)),
+            Quota::Unlimited => return Ok(None),
+            Quota::MaxBytes(max_bytes) => max_bytes,
+        };
+
+        // Items (and their attachments) at least as new as this one take priority:
+        let newer_bytes: i64 = self.conn.query_row_named("
+            SELECT
+                (
+                    SELECT COALESCE(SUM(LENGTH(bytes)), 0)
+                    FROM item
+                    WHERE user_id = :user_id
+                    AND unix_utc_ms >= :timestamp
+                ) + (
+                    SELECT COALESCE(SUM(a.size), 0)
+                    FROM attachment AS a
+                    INNER JOIN item AS i USING (user_id, signature)
+                    WHERE a.user_id = :user_id
+                    AND i.unix_utc_ms >= :timestamp
+                    AND a.hash IN (SELECT hash FROM blob)
+                )
+        ", named_params!{
+            ":user_id": user_id.bytes(),
+            ":timestamp": timestamp_ms_utc,
+        }, |row| row.get(0))?;
+
+        let newer_bytes = newer_bytes as u64;
+        if newer_bytes + new_bytes > max_bytes {
+            return Ok(Some(QuotaDenyReason::NewerItemsExceedQuota{ max_bytes, newer_bytes, new_bytes }));
+        }
+
+        Ok(None)
+    }
+
+    /// Copy the database to a new file alongside the original.
+    /// Returns None for databases that aren't backed by a file.
+    fn backup(&self, version: u32) -> Result<Option<String>, Error>
+    {
+        let db_file: String = self.conn.query_row(
+            "SELECT file FROM pragma_database_list WHERE name = 'main'",
+            NO_PARAMS,
+            |row| row.get(0),
+        )?;
+
+        if db_file.is_empty() {
+            return Ok(None);
+        }
+
+        let backup_file = format!("{}.v{}.{}.bak", db_file, version, Timestamp::now().unix_utc_ms);
+        self.conn.execute("VACUUM INTO ?", params![backup_file])
+            .with_context(|_| format!("Error backing up database to {}", backup_file))?;
+
+        Ok(Some(backup_file))
+    }
+}
+
+fn add_follow_quotas(conn: &Connection) -> Result<(), Error> {
+    conn.run("
+        ALTER TABLE follow
+        -- How many bytes of the followed user's items to store on behalf of
+        -- the source user. NULL/0 = unlimited.
+        ADD COLUMN max_bytes INTEGER
+    ")
+}
+
+fn add_attachments(conn: &Connection) -> Result<(), Error> {
+    conn.run("
+        CREATE TABLE blob(
+            -- A content-addressable store for many kinds of data.
+            hash BLOB PRIMARY KEY, -- multihash of the data.
+            data BLOB
+        )
+    ")?;
+
+    conn.run("
+        CREATE TABLE attachment(
+            -- Files which Items say are attached to them.
+            -- The file contents live in `blob`, and may not have been uploaded yet.
+            user_id BLOB,
+            signature BLOB,
+            name TEXT,
+            size INTEGER,
+            hash BLOB
+        )
+    ")?;
+
+    conn.run("
+        CREATE UNIQUE INDEX attachment_primary_idx
+        ON attachment(user_id, signature, name)
+    ")?;
+
+    conn.run("
+        CREATE INDEX attachment_hash_idx
+        ON attachment(hash)
+    ")
+}
+
+fn add_replies(conn: &Connection) -> Result<(), Error> {
+    conn.run("
+        CREATE TABLE reply(
+            -- Posts which are replies to other Items.
+            user_id BLOB,
+            signature BLOB,
+            reply_to_user_id BLOB,
+            reply_to_signature BLOB
+        )
+    ")?;
+
+    conn.run("
+        CREATE UNIQUE INDEX reply_primary_idx
+        ON reply(user_id, signature)
+    ")?;
+
+    conn.run("
+        CREATE INDEX reply_to_idx
+        ON reply(reply_to_user_id, reply_to_signature)
+    ")?;
+
+    // Clients may have uploaded replies before we indexed them:
+    let mut stmt = conn.conn.prepare("SELECT user_id, signature, bytes FROM item")?;
+    let mut rows = stmt.query(NO_PARAMS)?;
+    while let Some(row) = rows.next()? {
+        let bytes: Vec<u8> = row.get(2)?;
+        let mut item = Item::new();
+        item.merge_from_bytes(&bytes)?;
+        if item.get_post().has_reply_to() {
+            let user_id: Vec<u8> = row.get(0)?;
+            let signature: Vec<u8> = row.get(1)?;
+            save_reply(&conn.conn, &user_id, &signature, item.get_post().get_reply_to())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn add_search(conn: &Connection) -> Result<(), Error> {
+    conn.run("
+        CREATE VIRTUAL TABLE item_text USING fts5(
+            -- Searchable text from Items.
+            -- For Profiles, title is the display_name and body is the about text.
+            title,
+            body,
+            user_id UNINDEXED,
+            signature UNINDEXED
+        )
+    ")?;
+
+    let mut stmt = conn.conn.prepare("SELECT user_id, signature, bytes FROM item")?;
+    let mut rows = stmt.query(NO_PARAMS)?;
+    while let Some(row) = rows.next()? {
+        let bytes: Vec<u8> = row.get(2)?;
+        let mut item = Item::new();
+        item.merge_from_bytes(&bytes)?;
+        let user_id: Vec<u8> = row.get(0)?;
+        let signature: Vec<u8> = row.get(1)?;
+        save_item_text(&conn.conn, &user_id, &signature, &item)?;
+    }
+
+    Ok(())
+}
+
+fn add_sync_state(conn: &Connection) -> Result<(), Error> {
+    conn.run("
+        CREATE TABLE sync_server(
+            -- Remote servers that we sync items from.
+            url TEXT PRIMARY KEY,
+            -- How many syncs in a row have failed.
+            failures INTEGER,
+            -- Don't retry the server before this time. NULL = any time.
+            retry_after_utc_ms INTEGER,
+            last_error TEXT
+        )
+    ")?;
+
+    conn.run("
+        CREATE TABLE sync_user(
+            -- How far we've synced each user's items from each remote server.
+            url TEXT,
+            user_id BLOB,
+            -- The remote server's received_utc_ms for the newest item we've seen.
+            received_utc_ms INTEGER,
+            PRIMARY KEY (url, user_id)
+        )
+    ")
+}
+
+fn add_outbox(conn: &Connection) -> Result<(), Error> {
+    conn.run("
+        CREATE TABLE outbox(
+            -- Items to send to other servers.
+            url TEXT,
+            user_id BLOB,
+            signature BLOB,
+            -- How many attempts to send the item have failed.
+            attempts INTEGER,
+            -- When to try next. NULL = we've given up.
+            next_attempt_utc_ms INTEGER,
+            last_error TEXT,
+            PRIMARY KEY (url, user_id, signature)
+        )
+    ")?;
+
+    conn.run("
+        CREATE INDEX outbox_next_attempt_idx
+        ON outbox(next_attempt_utc_ms)
+    ")
+}
+
+/// Add a table to hold items that `feoblog db verify --quarantine` removes.
+fn add_quarantine(conn: &Connection) -> Result<(), Error> {
+    conn.run("
+        CREATE TABLE quarantine(
+            -- Items that `feoblog db verify` found to be invalid. They're
+            -- moved here from the item table so that we stop serving them,
+            -- but can still be inspected.
+            user_id BLOB,
+            signature BLOB,
+            unix_utc_ms INTEGER,
+            received_utc_ms INTEGER,
+            bytes BLOB,
+
+            -- Why the item was quarantined.
+            reason TEXT,
+            quarantined_utc_ms INTEGER
+        )
+    ")
+}
+
+fn add_max_item_size(conn: &Connection) -> Result<(), Error> {
+    conn.run("
+        ALTER TABLE server_user
+        -- The largest item (in bytes) that the user may upload.
+        -- NULL/0 = the server's default.
+        ADD COLUMN max_item_size INTEGER
+    ")
+}
+
+fn add_profile_revoked(conn: &Connection) -> Result<(), Error> {
+    conn.run("
+        ALTER TABLE profile
+        -- 1 if the profile revokes the user ID. (See: Profile.revoked)
+        -- Revocations replace (and are never replaced by) other profiles.
+        ADD COLUMN revoked INTEGER NOT NULL DEFAULT 0
+    ")
+}
+
+fn unindex_old_profiles(conn: &Connection) -> Result<(), Error> {
+    // Earlier versions indexed every version of a profile:
+    let mut stale = vec![];
+    let mut stmt = conn.conn.prepare("
+        SELECT t.rowid, i.bytes
+        FROM item_text AS t
+        INNER JOIN item AS i ON (i.user_id = t.user_id AND i.signature = t.signature)
+        LEFT OUTER JOIN profile AS p ON (p.user_id = t.user_id AND p.signature = t.signature)
+        WHERE p.user_id IS NULL
+    ")?;
+    let mut rows = stmt.query(NO_PARAMS)?;
+    while let Some(row) = rows.next()? {
+        let bytes: Vec<u8> = row.get(1)?;
+        let mut item = Item::new();
+        item.merge_from_bytes(&bytes)?;
+        if item.has_profile() {
+            stale.push(row.get::<_, i64>(0)?);
+        }
+    }
+    drop(rows);
+
+    for rowid in stale {
+        conn.conn.execute("DELETE FROM item_text WHERE rowid = ?", params![rowid])?;
+    }
+    Ok(())
+}
+
+/// Remove a user's current profile from the search index.
+fn unindex_profile(conn: &rusqlite::Connection, user_id: &[u8]) -> Result<(), Error> {
+    conn.execute_named("
+        DELETE FROM item_text
+        WHERE user_id = :user_id
+        AND signature IN (SELECT signature FROM profile WHERE user_id = :user_id)
+    ", named_params!{ ":user_id": user_id })?;
+    Ok(())
+}
+
+/// Index an item's text for search.
+/// Only users' current profiles are indexed. (See: [`update_profile`])
+fn save_item_text(conn: &rusqlite::Connection, user_id: &[u8], signature: &[u8], item: &Item) -> Result<(), Error> {
+    let (title, body) = if item.has_post() {
+        let post = item.get_post();
+        (post.get_title(), post.get_body())
+    } else if item.has_profile() {
+        let profile = item.get_profile();
+        (profile.get_display_name(), profile.get_about())
+    } else {
+        return Ok(());
+    };
+
+    conn.execute("
+        INSERT INTO item_text(title, body, user_id, signature)
+        VALUES (?, ?, ?, ?)
+    ", params![title, body, user_id, signature])?;
+    Ok(())
+}
+
+/// SQL parameters to compare a row's (unix_utc_ms, user_id, signature) against.
+/// A cursor without an item comes after every item at its timestamp. An empty
+/// BLOB gets us that, since it sorts before all other BLOBs.
+fn cursor_params(cursor: &Cursor) -> (i64, &[u8], &[u8]) {
+    match &cursor.item {
+        Some((user, signature)) => (cursor.timestamp.unix_utc_ms, user.bytes(), signature.bytes()),
+        None => (cursor.timestamp.unix_utc_ms, &[], &[]),
+    }
+}
+
+/// Like [`cursor_params`], but for lists sorted in ascending order, where a
+/// cursor without an item comes before every item after its timestamp.
+fn received_cursor_params(cursor: &Cursor) -> (i64, &[u8], &[u8]) {
+    match &cursor.item {
+        Some((user, signature)) => (cursor.timestamp.unix_utc_ms, user.bytes(), signature.bytes()),
+        None => (cursor.timestamp.unix_utc_ms.saturating_add(1), &[], &[]),
+    }
+}
+
+/// Convert plain text into an FTS5 query which matches all of its words.
+/// Quoting each word keeps users' input from being parsed as FTS5 syntax.
+fn fts_query(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn save_reply(conn: &rusqlite::Connection, user_id: &[u8], signature: &[u8], reply_to: &ItemRef) -> Result<(), Error> {
+    conn.execute("
+        INSERT INTO reply(user_id, signature, reply_to_user_id, reply_to_signature)
+        VALUES (?, ?, ?, ?)
+    ", params![
+        user_id,
+        signature,
+        reply_to.get_user_id().get_bytes(),
+        reply_to.get_signature().get_bytes(),
+    ])?;
+    Ok(())
+}
+
+/// Convert a max_bytes (or max_item_size) column, where NULL or 0 mean None.
+/// (ex: "unlimited", or "the server's default")
+fn from_max_bytes(value: Option<i64>) -> Option<u64> {
+    value.filter(|bytes| *bytes > 0).map(|bytes| bytes as u64)
+}
+
+fn to_max_bytes(value: Option<u64>) -> i64 {
+    value.map(|bytes| bytes as i64).unwrap_or(0)
 }
 
 /// We're saving a profile. If it's new, update the profile and follow tables.
+/// Revocations always replace other profiles, and revoked users follow no one.
 fn update_profile(conn: &rusqlite::Savepoint, item_row: &ItemRow, item: &Item) -> Result<(), Error> {
 
-    let prev_timestamp: Option<i64> =  
+    let previous: Option<(i64, bool)> =  
         conn.prepare("
-            SELECT i.unix_utc_ms
+            SELECT i.unix_utc_ms, p.revoked
             FROM profile AS p
             INNER JOIN item AS i USING (user_id, signature)
             WHERE user_id = ?
         ")?
         .query(params![ item_row.user.bytes() ])?
         .next()?
-        .map(|row| row.get(0))
+        .map(|row| -> rusqlite::Result<_> { Ok((row.get(0)?, row.get(1)?)) })
         .transpose()?
     ;
 
-    // Never replace a newer profile's metadata:
-    if let Some(previous) = prev_timestamp {
-        if previous >= item.timestamp_ms_utc {
+    // Never replace a newer profile's (or a revocation's) metadata:
+    let revoked = item.get_profile().get_revoked();
+    if let Some((prev_timestamp, prev_revoked)) = previous {
+        if (prev_revoked, prev_timestamp) >= (revoked, item.timestamp_ms_utc) {
             return Ok(())
         }
     }
@@ -241,64 +792,91 @@ fn update_profile(conn: &rusqlite::Savepoint, item_row: &ItemRow, item: &Item) -
     // Replace all follows with new ones listed in the profile:
     conn.execute("DELETE FROM follow WHERE source_user_id = ?", params![item_row.user.bytes()])?;
 
+    // ... and the profile that's searchable:
+    unindex_profile(conn, item_row.user.bytes())?;
+    save_item_text(conn, item_row.user.bytes(), item_row.signature.bytes(), item)?;
+
     // Behavior is undefined if duplicate follows exist in a Profile. So we just replace:
     let mut add_follow = conn.prepare("
-        INSERT OR REPLACE INTO follow (source_user_id, followed_user_id, display_name)
-        VALUES (?, ?, ?)
+        INSERT OR REPLACE INTO follow (source_user_id, followed_user_id, display_name, max_bytes)
+        VALUES (?, ?, ?, ?)
     ")?;
 
-    for follow in item.get_profile().get_follows() {
+    let follows = if revoked { &[][..] } else { item.get_profile().get_follows() };
+    for follow in follows {
         add_follow.execute(params![
             item_row.user.bytes(),
             follow.get_user().get_bytes(),
             follow.get_display_name(),
+            follow.get_max_bytes() as i64,
         ])?;
     }
 
     let mut add_profile = conn.prepare("
-        INSERT OR REPLACE INTO profile(user_id, signature, display_name)
-        VALUES (?,?,?)
+        INSERT OR REPLACE INTO profile(user_id, signature, display_name, revoked)
+        VALUES (?,?,?,?)
     ")?;
     add_profile.execute(params![
         item_row.user.bytes(),
         item_row.signature.bytes(),
-        item.get_profile().get_display_name()
+        item.get_profile().get_display_name(),
+        revoked,
     ])?;
 
     Ok(())
 }
 
+/// An item row that failed [`verify_item`].
+struct BadItem {
+    rowid: i64,
+    user_id: Vec<u8>,
+    signature: Vec<u8>,
+    reason: String,
+}
+
 impl backend::Backend for Connection
 {
 
-    fn setup(&self) -> Result<(), Error>
+    fn init(&self) -> Result<MigrationReport, Error>
     {
-        let version = match self.get_version()? {
-            None => {
-                // TODO: This shouldn't be automatic, should force user to
-                // explicitly create a new data store.
-                return self.setup_new();
+        if let Some(version) = self.get_version()? {
+            return Err(Error::Conflict(format!("The database has already been initialized. (version {})", version)));
+        }
+
+        self.setup_new()?;
+
+        // Nothing to back up in a brand new database:
+        self.migrate(&MigrateOptions{ backup: false, dry_run: false })
+    }
+
+    fn status(&self) -> Result<SchemaStatus, Error>
+    {
+        let version = self.get_version()?;
+        let pending = match version {
+            Some(version) if version >= BASE_VERSION => {
+                MIGRATIONS.iter()
+                    .filter(|m| m.from_version >= version)
+                    .map(|m| m.description)
+                    .collect()
             },
-            Some(version) => version
+            _ => vec![],
         };
-        if version == CURRENT_VERSION {
-            return Ok(());
-        }
-        if version > CURRENT_VERSION {
-            bail!(
-                "DB version ({}) newer than current version ({})",
-                version,
-                CURRENT_VERSION
-            );
-        }
 
-        // TODO:
-        bail!("DB version {} is unknown. Migration not implemented.", version);
+        Ok(SchemaStatus {
+            version,
+            latest_version: BASE_VERSION + MIGRATIONS.len() as u32,
+            pending,
+        })
+    }
+
+    fn migrate(&self, options: &MigrateOptions) -> Result<MigrationReport, Error>
+    {
+        self.migrate_with(MIGRATIONS, options)
     }
 
     fn homepage_items<'a>(
         &self,
-        before: Timestamp,
+        before: &Cursor,
         callback: &'a mut dyn FnMut(ItemDisplayRow) -> Result<bool,Error>
     ) -> Result<(), Error> {
         let mut stmt = self.conn.prepare("
@@ -311,17 +889,20 @@ impl backend::Backend for Connection
                 , p.display_name
             FROM item AS i
             LEFT OUTER JOIN profile AS p USING (user_id)
-            WHERE unix_utc_ms < ?
+            WHERE (unix_utc_ms, user_id, i.signature) < (?, ?, ?)
             AND user_id IN (
                 SELECT user_id
                 FROM server_user
                 WHERE on_homepage = 1
             )
-            ORDER BY unix_utc_ms DESC
+            ORDER BY unix_utc_ms DESC, user_id DESC, i.signature DESC
         ")?;
 
+        let (timestamp, cursor_user, cursor_signature) = cursor_params(before);
         let mut rows = stmt.query(params![
-            before.unix_utc_ms,
+            timestamp,
+            cursor_user,
+            cursor_signature,
         ])?;
 
         let to_item_profile_row = |row: &Row<'_>| -> Result<ItemDisplayRow, Error> {
@@ -334,9 +915,10 @@ impl backend::Backend for Connection
                 item_bytes: row.get(4)?,
             };
 
+            let display_name: Option<String> = row.get(5)?;
             Ok(ItemDisplayRow{
                 item,
-                display_name: row.get(5)?
+                display_name: display_name.filter(|name| !name.trim().is_empty()),
             })
         };
 
@@ -352,7 +934,7 @@ impl backend::Backend for Connection
     fn user_items<'a>(
         &self,
         user: &UserID,
-        before: Timestamp,
+        before: &Cursor,
         callback: &'a mut dyn FnMut(ItemRow) -> Result<bool,Error>
     ) -> Result<(), Error> {
         let mut stmt = self.conn.prepare("
@@ -364,13 +946,16 @@ impl backend::Backend for Connection
                 , bytes
             FROM item AS i
             WHERE
-                unix_utc_ms < ?
+                (unix_utc_ms, user_id, signature) < (?, ?, ?)
                 AND user_id = ?
-            ORDER BY unix_utc_ms DESC
+            ORDER BY unix_utc_ms DESC, signature DESC
         ")?;
 
+        let (timestamp, cursor_user, cursor_signature) = cursor_params(before);
         let mut rows = stmt.query(params![
-            before.unix_utc_ms,
+            timestamp,
+            cursor_user,
+            cursor_signature,
             user.bytes(),
         ])?;
 
@@ -398,7 +983,7 @@ impl backend::Backend for Connection
     fn user_feed_items<'a>(
         &self,
         user_id: &UserID,
-        before: Timestamp,
+        before: &Cursor,
         callback: &'a mut dyn FnMut(ItemDisplayRow) -> Result<bool, Error>,
     ) -> Result<(), Error> {
         let mut stmt = self.conn.prepare("
@@ -416,7 +1001,7 @@ impl backend::Backend for Connection
                 i.user_id = f.followed_user_id
                 AND f.source_user_id = :user_id
             )
-            WHERE unix_utc_ms < :timestamp
+            WHERE (unix_utc_ms, user_id, i.signature) < (:timestamp, :cursor_user, :cursor_signature)
             AND (
                 user_id IN (
                     SELECT followed_user_id
@@ -425,13 +1010,16 @@ impl backend::Backend for Connection
                 )
                 OR user_id = :user_id
             )
-            ORDER BY unix_utc_ms DESC
+            ORDER BY unix_utc_ms DESC, user_id DESC, i.signature DESC
         ")?;
 
-        let mut rows = stmt.query_named(&[
-            (":timestamp", &before.unix_utc_ms),
-            (":user_id", &user_id.bytes())
-        ])?;
+        let (timestamp, cursor_user, cursor_signature) = cursor_params(before);
+        let mut rows = stmt.query_named(named_params!{
+            ":timestamp": timestamp,
+            ":cursor_user": cursor_user,
+            ":cursor_signature": cursor_signature,
+            ":user_id": user_id.bytes(),
+        })?;
 
         let to_item_profile_row = |row: &Row<'_>| -> Result<ItemDisplayRow, Error> {
 
@@ -464,22 +1052,227 @@ impl backend::Backend for Connection
         Ok( () )
     }
 
+    fn search_items<'a>(
+        &self,
+        query: &str,
+        scope: &SearchScope,
+        before: &Cursor,
+        callback: FnIter<'a, ItemDisplayRow>,
+    ) -> Result<(), Error> {
+        let query = fts_query(query);
+        if query.is_empty() {
+            return Ok(());
+        }
+
+        let (scope_user, scope_filter) = match scope {
+            SearchScope::All => (None, ""),
+            SearchScope::User(user_id) => (Some(user_id.bytes()), "AND i.user_id = :scope_user"),
+            SearchScope::Feed(user_id) => (Some(user_id.bytes()), "
+                AND (
+                    i.user_id = :scope_user
+                    OR i.user_id IN (
+                        SELECT followed_user_id
+                        FROM follow
+                        WHERE source_user_id = :scope_user
+                    )
+                )
+            "),
+        };
+
+        let mut stmt = self.conn.prepare(&format!("
+            SELECT
+                i.user_id
+                , i.signature
+                , i.unix_utc_ms
+                , i.received_utc_ms
+                , i.bytes
+                , p.display_name
+            FROM item_text AS t
+            INNER JOIN item AS i ON (
+                i.user_id = t.user_id
+                AND i.signature = t.signature
+            )
+            LEFT OUTER JOIN profile AS p ON (p.user_id = i.user_id)
+            WHERE item_text MATCH :query
+            AND (i.unix_utc_ms, i.user_id, i.signature) < (:timestamp, :cursor_user, :cursor_signature)
+            {}
+            ORDER BY i.unix_utc_ms DESC, i.user_id DESC, i.signature DESC
+        ", scope_filter))?;
+
+        let (timestamp, cursor_user, cursor_signature) = cursor_params(before);
+        let mut params: Vec<(&str, &dyn rusqlite::ToSql)> = vec![
+            (":query", &query),
+            (":timestamp", &timestamp),
+            (":cursor_user", &cursor_user),
+            (":cursor_signature", &cursor_signature),
+        ];
+        if let Some(user_id) = &scope_user {
+            params.push((":scope_user", user_id));
+        }
+        let mut rows = stmt.query_named(&params)?;
+
+        while let Some(row) = rows.next()? {
+            let item = ItemRow{
+                user: UserID::from_vec(row.get(0)?)?,
+                signature: Signature::from_vec(row.get(1)?)?,
+                timestamp: Timestamp{ unix_utc_ms: row.get(2)? },
+                received: Timestamp{ unix_utc_ms: row.get(3)? },
+                item_bytes: row.get(4)?,
+            };
+
+            let display_name: Option<String> = row.get(5)?;
+            let result = callback(ItemDisplayRow{
+                item,
+                display_name: display_name.filter(|name| !name.trim().is_empty()),
+            })?;
+            if !result { break; }
+        }
+
+        Ok(())
+    }
+
+    fn reply_items<'a>(
+        &self,
+        user_id: &UserID,
+        signature: &Signature,
+        followed_by: Option<&UserID>,
+        before: &Cursor,
+        callback: FnIter<'a, ItemDisplayRow>,
+    ) -> Result<(), Error> {
+        let mut stmt = self.conn.prepare("
+            SELECT
+                i.user_id
+                , i.signature
+                , i.unix_utc_ms
+                , i.received_utc_ms
+                , i.bytes
+                , p.display_name
+                , f.display_name AS follow_display_name
+            FROM reply AS r
+            INNER JOIN item AS i USING (user_id, signature)
+            LEFT OUTER JOIN profile AS p USING (user_id)
+            LEFT OUTER JOIN follow AS f ON (
+                i.user_id = f.followed_user_id
+                AND f.source_user_id = :followed_by
+            )
+            WHERE r.reply_to_user_id = :user_id
+            AND r.reply_to_signature = :signature
+            AND (i.unix_utc_ms, i.user_id, i.signature) < (:timestamp, :cursor_user, :cursor_signature)
+            AND (
+                :followed_by IS NULL
+                OR i.user_id = :followed_by
+                OR i.user_id IN (
+                    SELECT followed_user_id
+                    FROM follow
+                    WHERE source_user_id = :followed_by
+                )
+            )
+            ORDER BY i.unix_utc_ms DESC, i.user_id DESC, i.signature DESC
+        ")?;
+
+        let (timestamp, cursor_user, cursor_signature) = cursor_params(before);
+        let mut rows = stmt.query_named(named_params!{
+            ":user_id": user_id.bytes(),
+            ":signature": signature.bytes(),
+            ":followed_by": followed_by.map(|u| u.bytes()),
+            ":timestamp": timestamp,
+            ":cursor_user": cursor_user,
+            ":cursor_signature": cursor_signature,
+        })?;
+
+        while let Some(row) = rows.next()? {
+            let item = ItemRow{
+                user: UserID::from_vec(row.get(0)?)?,
+                signature: Signature::from_vec(row.get(1)?)?,
+                timestamp: Timestamp{ unix_utc_ms: row.get(2)? },
+                received: Timestamp{ unix_utc_ms: row.get(3)? },
+                item_bytes: row.get(4)?,
+            };
+
+            let display_name: Option<String> = row.get(5)?;
+            let follow_display_name: Option<String> = row.get(6)?;
+            fn not_empty(it: &String) -> bool { !it.trim().is_empty() }
+
+            let result = callback(ItemDisplayRow{
+                item,
+                display_name: follow_display_name.filter(not_empty).or(display_name).filter(not_empty),
+            })?;
+            if !result { break; }
+        }
+
+        Ok(())
+    }
+
+    fn received_items<'a>(
+        &self,
+        user: Option<&UserID>,
+        after: &Cursor,
+        callback: FnIter<'a, ItemRow>,
+    ) -> Result<(), Error> {
+        // Separate queries, so that each can use its own index.
+        let user_filter = match user {
+            Some(_) => "AND user_id = :user_id",
+            None => "",
+        };
+        let mut stmt = self.conn.prepare(&format!("
+            SELECT
+                user_id
+                , signature
+                , unix_utc_ms
+                , received_utc_ms
+                , bytes
+            FROM item
+            WHERE
+                (received_utc_ms, user_id, signature) > (:timestamp, :cursor_user, :cursor_signature)
+                {}
+            ORDER BY received_utc_ms, user_id, signature
+        ", user_filter))?;
+
+        let (timestamp, cursor_user, cursor_signature) = received_cursor_params(after);
+        let mut params = named_params!{
+            ":timestamp": timestamp,
+            ":cursor_user": cursor_user,
+            ":cursor_signature": cursor_signature,
+        }.to_vec();
+        let user_bytes = user.map(|u| u.bytes());
+        if let Some(user_bytes) = &user_bytes {
+            params.push((":user_id", user_bytes));
+        }
+        let mut rows = stmt.query_named(&params)?;
+
+        while let Some(row) = rows.next()? {
+            let item = ItemRow{
+                user: UserID::from_vec(row.get(0)?)?,
+                signature: Signature::from_vec(row.get(1)?)?,
+                timestamp: Timestamp{ unix_utc_ms: row.get(2)? },
+                received: Timestamp{ unix_utc_ms: row.get(3)? },
+                item_bytes: row.get(4)?,
+            };
+            if !callback(item)? { break; }
+        }
+
+        Ok(())
+    }
+
     fn server_user(&self, user: &UserID)
     -> Result<Option<backend::ServerUser>, Error> 
     { 
         let mut stmt = self.conn.prepare("
-            SELECT notes, on_homepage
+            SELECT notes, on_homepage, max_bytes, max_item_size
             FROM server_user
             WHERE user_id = ?
         ")?;
 
         let to_server_user = |row: &Row<'_>| {
             let on_homepage: isize = row.get(1)?;
+            let max_bytes: Option<i64> = row.get(2)?;
              Ok(
                  ServerUser {
                     user: user.clone(),
                     notes: row.get(0)?,
                     on_homepage: on_homepage != 0,
+                    max_bytes: from_max_bytes(max_bytes),
+                    max_item_size: from_max_bytes(row.get(3)?),
                 }
             )
         };
@@ -499,6 +1292,8 @@ impl backend::Backend for Connection
                 user_id
                 , notes
                 , on_homepage
+                , max_bytes
+                , max_item_size
             FROM server_user
             ORDER BY on_homepage, user_id
         ")?;
@@ -510,9 +1305,11 @@ impl backend::Backend for Connection
             let on_homepage = on_homepage != 0;
 
             let user = ServerUser {
-                user: UserID::from_vec(row.get(0)?).compat()?,
+                user: UserID::from_vec(row.get(0)?)?,
                 notes: row.get(1)?,
                 on_homepage,
+                max_bytes: from_max_bytes(row.get(3)?),
+                max_item_size: from_max_bytes(row.get(4)?),
             };
             let more = cb(user)?;
             if !more {break;}
@@ -539,7 +1336,7 @@ impl backend::Backend for Connection
         )?;
 
         if count > 1 {
-            bail!("Found {} matches!? (user_id,signature) should be unique!", count);
+            Err(format_err!("Found {} matches!? (user_id,signature) should be unique!", count))?;
         }
 
         Ok(count > 0)
@@ -577,7 +1374,7 @@ impl backend::Backend for Connection
         };
 
         if rows.next()?.is_some() {
-            bail!("Found multiple matching rows!? (user_id,signature) should be unique!");
+            Err(format_err!("Found multiple matching rows!? (user_id,signature) should be unique!"))?;
         }
 
         Ok(Some(item))
@@ -607,6 +1404,28 @@ impl backend::Backend for Connection
 
         if item.has_profile() {
             update_profile(&tx, row, item)?;
+        } else {
+            save_item_text(&tx, row.user.bytes(), row.signature.bytes(), item)?;
+        }
+
+        if item.has_post() {
+            let mut add_attachment = tx.prepare("
+                INSERT INTO attachment(user_id, signature, name, size, hash)
+                VALUES (?, ?, ?, ?, ?)
+            ")?;
+            for attachment in item.get_post().get_attachments() {
+                add_attachment.execute(params![
+                    row.user.bytes(),
+                    row.signature.bytes(),
+                    attachment.get_name(),
+                    attachment.get_size() as i64,
+                    attachment.get_hash(),
+                ])?;
+            }
+
+            if item.get_post().has_reply_to() {
+                save_reply(&tx, row.user.bytes(), row.signature.bytes(), item.get_post().get_reply_to())?;
+            }
         }
 
         tx.commit().context("committing")?;
@@ -616,8 +1435,8 @@ impl backend::Backend for Connection
     fn add_server_user(&self, server_user: &ServerUser) -> Result<(), Error> {
 
         let stmt = "
-            INSERT INTO server_user(user_id, notes, on_homepage)
-            VALUES (?,?,?)
+            INSERT INTO server_user(user_id, notes, on_homepage, max_bytes, max_item_size)
+            VALUES (?,?,?,?,?)
         ";
 
         let on_homepage = if server_user.on_homepage { 1 } else { 0 };
@@ -625,12 +1444,78 @@ impl backend::Backend for Connection
         self.conn.execute(stmt, params![
             server_user.user.bytes(),
             server_user.notes.as_str(),
-            on_homepage
+            on_homepage,
+            to_max_bytes(server_user.max_bytes),
+            to_max_bytes(server_user.max_item_size),
         ])?;
 
         Ok(())
     }
 
+    fn update_server_user(&self, server_user: &ServerUser) -> Result<(), Error> {
+        let stmt = "
+            UPDATE server_user
+            SET notes = ?, on_homepage = ?, max_bytes = ?, max_item_size = ?
+            WHERE user_id = ?
+        ";
+
+        let on_homepage = if server_user.on_homepage { 1 } else { 0 };
+
+        let updated = self.conn.execute(stmt, params![
+            server_user.notes.as_str(),
+            on_homepage,
+            to_max_bytes(server_user.max_bytes),
+            to_max_bytes(server_user.max_item_size),
+            server_user.user.bytes(),
+        ])?;
+
+        if updated == 0 {
+            return Err(Error::NotFound(format!("{} is not a server user", server_user.user.to_base58())));
+        }
+
+        Ok(())
+    }
+
+    fn remove_server_user(&self, user: &UserID) -> Result<(), Error> {
+        let removed = self.conn.execute(
+            "DELETE FROM server_user WHERE user_id = ?",
+            params![user.bytes()],
+        )?;
+
+        if removed == 0 {
+            return Err(Error::NotFound(format!("{} is not a server user", user.to_base58())));
+        }
+
+        Ok(())
+    }
+
+    fn user_usage(&self, user: &UserID) -> Result<UsageStats, Error> {
+        let mut stmt = self.conn.prepare("
+            SELECT
+                COUNT(*)
+                , COALESCE(SUM(LENGTH(bytes)), 0)
+                , (
+                    SELECT COALESCE(SUM(size), 0)
+                    FROM attachment
+                    WHERE user_id = :user_id
+                    AND hash IN (SELECT hash FROM blob)
+                )
+            FROM item
+            WHERE user_id = :user_id
+        ")?;
+
+        let (item_count, item_bytes, attachment_bytes): (i64, i64, i64) = stmt.query_row_named(
+            named_params!{ ":user_id": user.bytes() },
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+
+        Ok(UsageStats{
+            item_count: item_count as u64,
+            item_bytes: item_bytes as u64,
+            attachment_bytes: attachment_bytes as u64,
+        })
+    }
+
     fn user_profile(&self, user: &UserID) -> Result<Option<ItemRow>, Error> {
 
         // TODO: I'm not crazy about making 2 queries here instead of a join, but it lets me
@@ -659,13 +1544,16 @@ impl backend::Backend for Connection
     fn user_known(&self, user_id: &UserID) -> Result<bool, Error> {
         let mut query = self.conn.prepare("
             SELECT
-                EXISTS(SELECT user_id FROM server_user WHERE user_id = :user_id)
-                OR EXISTS(
-                    SELECT followed_user_id
-                    FROM follow AS f
-                    INNER JOIN server_user AS su ON (f.source_user_id = su.user_id)
-                    WHERE followed_user_id = :user_id
+                (
+                    EXISTS(SELECT user_id FROM server_user WHERE user_id = :user_id)
+                    OR EXISTS(
+                        SELECT followed_user_id
+                        FROM follow AS f
+                        INNER JOIN server_user AS su ON (f.source_user_id = su.user_id)
+                        WHERE followed_user_id = :user_id
+                    )
                 )
+                AND NOT EXISTS(SELECT user_id FROM profile WHERE user_id = :user_id AND revoked)
         ")?;
 
         let mut result = query.query_named(&[
@@ -674,40 +1562,291 @@ impl backend::Backend for Connection
 
         let row = match result.next()? {
             Some(row) => row,
-            None => bail!("Expected at least 1 row from SQLite."),
+            None => Err(format_err!("Expected at least 1 row from SQLite."))?,
         };
 
         Ok(row.get(0)?)
     }
 
+    fn user_revoked(&self, user_id: &UserID) -> Result<bool, Error> {
+        let revoked = self.conn.query_row(
+            "SELECT EXISTS(SELECT user_id FROM profile WHERE user_id = ? AND revoked)",
+            params![user_id.bytes()],
+            |row| row.get(0),
+        )?;
+        Ok(revoked)
+    }
+
     fn quota_check_item(&self, user_id: &UserID, bytes: &[u8], item: &Item) -> Result<Option<QuotaDenyReason>, Error> {
-        
-        if self.server_user(user_id)?.is_some() {
-            // TODO: Implement optional quotas for "server users".
-            // For now, there is no quota for them:
-            return Ok(None);
-        };
+        self.quota_check(user_id, item.timestamp_ms_utc, bytes.len() as u64)
+    }
 
-        // Check those followed by "server users":
-        let mut statement = self.conn.prepare("
-            SELECT
-                f.followed_user_id
-            FROM
-                follow AS f
-                INNER JOIN server_user AS su ON su.user_id = f.source_user_id
-            WHERE
-                f.followed_user_id = ?
+    fn quota_check_attachment(&self, user_id: &UserID, item: &Item, size: u64) -> Result<Option<QuotaDenyReason>, Error> {
+        self.quota_check(user_id, item.timestamp_ms_utc, size)
+    }
+
+    fn save_blob(&self, hash: &[u8], data: &[u8]) -> Result<(), Error> {
+        // Content-addressed, so if it already exists, it's identical:
+        self.conn.execute(
+            "INSERT OR IGNORE INTO blob(hash, data) VALUES (?, ?)",
+            params![hash, data],
+        )?;
+        Ok(())
+    }
+
+    fn blob(&self, hash: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let data = self.conn.query_row(
+            "SELECT data FROM blob WHERE hash = ?",
+            params![hash],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(data)
+    }
+
+    fn blob_exists(&self, hash: &[u8]) -> Result<bool, Error> {
+        let exists = self.conn.query_row(
+            "SELECT EXISTS(SELECT hash FROM blob WHERE hash = ?)",
+            params![hash],
+            |row| row.get(0),
+        )?;
+        Ok(exists)
+    }
+
+    fn sync_server(&self, url: &str) -> Result<Option<backend::SyncServer>, Error> {
+        let server = self.conn.query_row(
+            "
+                SELECT failures, retry_after_utc_ms, last_error
+                FROM sync_server
+                WHERE url = ?
+            ",
+            params![url],
+            |row| {
+                let retry_after: Option<i64> = row.get(1)?;
+                Ok(backend::SyncServer{
+                    url: url.to_string(),
+                    failures: row.get(0)?,
+                    retry_after: retry_after.map(|unix_utc_ms| Timestamp{ unix_utc_ms }),
+                    last_error: row.get(2)?,
+                })
+            },
+        ).optional()?;
+        Ok(server)
+    }
+
+    fn save_sync_server(&self, server: &backend::SyncServer) -> Result<(), Error> {
+        self.conn.execute(
+            "
+                INSERT OR REPLACE INTO sync_server(url, failures, retry_after_utc_ms, last_error)
+                VALUES (?, ?, ?, ?)
+            ",
+            params![
+                server.url.as_str(),
+                server.failures,
+                server.retry_after.map(|t| t.unix_utc_ms),
+                server.last_error.as_deref(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn sync_position(&self, url: &str, user: &UserID) -> Result<Option<Timestamp>, Error> {
+        let received: Option<i64> = self.conn.query_row(
+            "SELECT received_utc_ms FROM sync_user WHERE url = ? AND user_id = ?",
+            params![url, user.bytes()],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(received.map(|unix_utc_ms| Timestamp{ unix_utc_ms }))
+    }
+
+    fn save_sync_position(&self, url: &str, user: &UserID, received: Timestamp) -> Result<(), Error> {
+        self.conn.execute(
+            "
+                INSERT OR REPLACE INTO sync_user(url, user_id, received_utc_ms)
+                VALUES (?, ?, ?)
+            ",
+            params![url, user.bytes(), received.unix_utc_ms],
+        )?;
+        Ok(())
+    }
+
+    fn add_outbox_entry(&self, url: &str, user: &UserID, signature: &Signature) -> Result<(), Error> {
+        self.conn.execute(
+            "
+                INSERT OR IGNORE INTO outbox(url, user_id, signature, attempts, next_attempt_utc_ms)
+                VALUES (?, ?, ?, 0, ?)
+            ",
+            params![url, user.bytes(), signature.bytes(), Timestamp::now().unix_utc_ms],
+        )?;
+        Ok(())
+    }
+
+    fn outbox_entries<'a>(&self, due: Option<Timestamp>, callback: FnIter<'a, backend::OutboxEntry>) -> Result<(), Error> {
+        let mut stmt = self.conn.prepare("
+            SELECT url, user_id, signature, attempts, next_attempt_utc_ms, last_error
+            FROM outbox
+            WHERE :due IS NULL OR next_attempt_utc_ms <= :due
+            -- Entries we've given up on (NULL) sort last:
+            ORDER BY next_attempt_utc_ms IS NULL, next_attempt_utc_ms, url, user_id, signature
         ")?;
-        let mut rows = statement.query(params![user_id.bytes()])?;
-        if rows.next()?.is_some() {
-            // TODO Implement quotas in follows. For now, presence of a follow gives unlimited quota.
-            // TODO: Exclude server users whose profiles/IDs have been revoked.
-            return Ok(None);
+        let mut rows = stmt.query_named(named_params!{
+            ":due": due.map(|t| t.unix_utc_ms),
+        })?;
+
+        while let Some(row) = rows.next()? {
+            let next_attempt: Option<i64> = row.get(4)?;
+            let entry = backend::OutboxEntry{
+                url: row.get(0)?,
+                user: UserID::from_vec(row.get(1)?)?,
+                signature: Signature::from_vec(row.get(2)?)?,
+                attempts: row.get(3)?,
+                next_attempt: next_attempt.map(|unix_utc_ms| Timestamp{ unix_utc_ms }),
+                last_error: row.get(5)?,
+            };
+            if !callback(entry)? { break; }
         }
 
-        // TODO: When "pinning" is implemented, allow posting items which are pinned by server users and their follows.
-        // TODO: I've since decided that "pinning" might be prone to abuse. I should write up my thoughts there.
+        Ok(())
+    }
+
+    fn update_outbox_entry(&self, entry: &backend::OutboxEntry) -> Result<(), Error> {
+        let updated = self.conn.execute(
+            "
+                UPDATE outbox
+                SET attempts = ?, next_attempt_utc_ms = ?, last_error = ?
+                WHERE url = ? AND user_id = ? AND signature = ?
+            ",
+            params![
+                entry.attempts,
+                entry.next_attempt.map(|t| t.unix_utc_ms),
+                entry.last_error.as_deref(),
+                entry.url.as_str(),
+                entry.user.bytes(),
+                entry.signature.bytes(),
+            ],
+        )?;
+        if updated == 0 {
+            return Err(Error::NotFound(format!("{} is not queued for {}", entry.signature.to_base58(), entry.url)));
+        }
+        Ok(())
+    }
+
+    fn remove_outbox_entry(&self, url: &str, user: &UserID, signature: &Signature) -> Result<(), Error> {
+        self.conn.execute(
+            "DELETE FROM outbox WHERE url = ? AND user_id = ? AND signature = ?",
+            params![url, user.bytes(), signature.bytes()],
+        )?;
+        Ok(())
+    }
+
+    fn verify(&mut self, options: &backend::VerifyOptions) -> Result<backend::VerifyReport, Error> {
+        let mut report = backend::VerifyReport::default();
+        let quarantine_fix = if options.quarantine { Some("Moved to quarantine") } else { None };
+        let rebuild_fix = if options.quarantine { Some("Rebuilt from the newest profile") } else { None };
+
+        let mut bad_items: Vec<BadItem> = vec![];
+        let mut newest_profiles = NewestProfiles::new();
+
+        let mut stmt = self.conn.prepare("
+            SELECT rowid, user_id, signature, unix_utc_ms, received_utc_ms, bytes
+            FROM item
+        ")?;
+        let mut rows = stmt.query(NO_PARAMS)?;
+        while let Some(row) = rows.next()? {
+            report.items += 1;
+            let rowid: i64 = row.get(0)?;
+            let user_id: Vec<u8> = row.get(1)?;
+            let signature: Vec<u8> = row.get(2)?;
+            let unix_utc_ms: i64 = row.get(3)?;
+            let received_utc_ms: i64 = row.get(4)?;
+            let bytes: Vec<u8> = row.get(5)?;
+
+            let (user, signature, item) = match verify_item(&user_id, &signature, unix_utc_ms, &bytes) {
+                Ok(verified) => verified,
+                Err(reason) => {
+                    report.problems.push(backend::VerifyProblem{
+                        location: item_location(&user_id, &signature),
+                        description: reason.clone(),
+                        fix: quarantine_fix,
+                    });
+                    bad_items.push(BadItem{ rowid, user_id, signature, reason });
+                    continue;
+                }
+            };
+            check_rules(&user, &signature, &item, &mut report);
+
+            let row = ItemRow{
+                user,
+                signature,
+                timestamp: Timestamp{ unix_utc_ms },
+                received: Timestamp{ unix_utc_ms: received_utc_ms },
+                item_bytes: bytes,
+            };
+            add_profile(&mut newest_profiles, row, item);
+        }
+        drop(rows);
+        drop(stmt);
+
+        // What the profile and follow tables currently say:
+        let mut profile_rows = ProfileRows::new();
+        let mut stmt = self.conn.prepare("SELECT user_id, signature, display_name, revoked FROM profile")?;
+        let mut rows = stmt.query(NO_PARAMS)?;
+        while let Some(row) = rows.next()? {
+            let display_name: Option<String> = row.get(2)?;
+            profile_rows.insert(row.get(0)?, (row.get(1)?, display_name.unwrap_or_default(), row.get(3)?));
+        }
+        drop(rows);
+        drop(stmt);
+
+        let mut follow_rows: HashMap<Vec<u8>, FollowRows> = HashMap::new();
+        let mut stmt = self.conn.prepare("SELECT source_user_id, followed_user_id, display_name, max_bytes FROM follow")?;
+        let mut rows = stmt.query(NO_PARAMS)?;
+        while let Some(row) = rows.next()? {
+            let display_name: Option<String> = row.get(2)?;
+            follow_rows.entry(row.get(0)?).or_default().insert(
+                row.get(1)?,
+                (display_name.unwrap_or_default(), from_max_bytes(row.get(3)?)),
+            );
+        }
+        drop(rows);
+        drop(stmt);
+
+        // Users whose profile data we need to rebuild, and from which profile:
+        let rebuilds = check_profiles(&newest_profiles, &profile_rows, &follow_rows, rebuild_fix, &mut report);
+
+        if !options.quarantine {
+            return Ok(report);
+        }
+
+        let tx = self.conn.savepoint()?;
+        let now = Timestamp::now().unix_utc_ms;
+        for BadItem{ rowid, user_id, signature, reason } in &bad_items {
+            tx.execute("
+                INSERT INTO quarantine(user_id, signature, unix_utc_ms, received_utc_ms, bytes, reason, quarantined_utc_ms)
+                SELECT user_id, signature, unix_utc_ms, received_utc_ms, bytes, ?, ?
+                FROM item
+                WHERE rowid = ?
+            ", params![reason, now, rowid])?;
+            tx.execute("DELETE FROM item WHERE rowid = ?", params![rowid])?;
+
+            // Also remove data derived from it:
+            for table in &["item_text", "attachment", "reply"] {
+                tx.execute(
+                    &format!("DELETE FROM {} WHERE user_id = ? AND signature = ?", table),
+                    params![user_id, signature],
+                )?;
+            }
+        }
+
+        for (user_id, expected) in &rebuilds {
+            unindex_profile(&tx, user_id)?;
+            tx.execute("DELETE FROM profile WHERE user_id = ?", params![user_id])?;
+            tx.execute("DELETE FROM follow WHERE source_user_id = ?", params![user_id])?;
+            if let Some((row, item)) = expected {
+                update_profile(&tx, row, item)?;
+            }
+        }
+        tx.commit()?;
 
-        Ok(Some(QuotaDenyReason::UnknownUser))
+        Ok(report)
     }
 }
\ No newline at end of file
@@ -0,0 +1,171 @@
+use super::*;
+
+/// A SQLite file in the temp directory which is deleted (along with any
+/// backups of it) when dropped.
+struct TempDB {
+    path: String,
+}
+
+impl TempDB {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir()
+            .join(format!("feoblog-test-{}-{}-{}.sqlite3", name, std::process::id(), Timestamp::now().unix_utc_ms))
+            .to_string_lossy()
+            .into_owned();
+        TempDB{ path }
+    }
+
+    fn connection(&self) -> Connection {
+        let factory = Factory::new(self.path.clone());
+        Connection{ conn: factory.pool.get().expect("connection") }
+    }
+}
+
+impl Drop for TempDB {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        let path = std::path::Path::new(&self.path);
+        let prefix = path.file_name().unwrap().to_string_lossy().into_owned();
+        if let Ok(entries) = std::fs::read_dir(path.parent().unwrap()) {
+            for entry in entries.flatten() {
+                if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                    let _ = std::fs::remove_file(entry.path());
+                }
+            }
+        }
+    }
+}
+
+fn add_widget_table(conn: &Connection) -> Result<(), Error> {
+    conn.run("CREATE TABLE widget(name TEXT)")
+}
+
+fn add_widget_color(conn: &Connection) -> Result<(), Error> {
+    conn.run("ALTER TABLE widget ADD COLUMN color TEXT")
+}
+
+fn fail(_conn: &Connection) -> Result<(), Error> {
+    Err(format_err!("Nope.").into())
+}
+
+const TEST_MIGRATIONS: &[Migration] = &[
+    Migration{ from_version: BASE_VERSION, description: "Add widget table", apply: add_widget_table },
+    Migration{ from_version: BASE_VERSION + 1, description: "Add widget colors", apply: add_widget_color },
+];
+
+fn table_exists(conn: &Connection, name: &str) -> bool {
+    let count: u32 = conn.conn.query_row(
+        "SELECT count() FROM sqlite_master WHERE type = 'table' AND name = ?",
+        params![name],
+        |row| row.get(0),
+    ).unwrap();
+    count > 0
+}
+
+#[test]
+fn migrations_are_contiguous() {
+    for (index, migration) in MIGRATIONS.iter().enumerate() {
+        assert_eq!(BASE_VERSION + index as u32, migration.from_version, "{}", migration.description);
+    }
+}
+
+#[test]
+fn init_new_database() {
+    let db = TempDB::new("init");
+    let conn = db.connection();
+
+    let status = conn.status().unwrap();
+    assert_eq!(None, status.version);
+    assert!(status.pending.is_empty());
+
+    let report = conn.init().unwrap();
+    assert_eq!(status.latest_version, report.to_version);
+    assert!(report.backup_file.is_none());
+
+    let status = conn.status().unwrap();
+    assert_eq!(Some(status.latest_version), status.version);
+    assert!(status.pending.is_empty());
+
+    // Can't initialize twice:
+    assert!(conn.init().is_err());
+
+    // Migrating an up-to-date DB is a no-op:
+    let report = conn.migrate(&MigrateOptions::default()).unwrap();
+    assert!(report.applied.is_empty());
+    assert!(report.backup_file.is_none());
+}
+
+#[test]
+fn migrate_stepwise() {
+    let db = TempDB::new("stepwise");
+    let conn = db.connection();
+    conn.setup_new().unwrap();
+
+    let options = MigrateOptions{ dry_run: false, backup: false };
+    let report = conn.migrate_with(&TEST_MIGRATIONS[..1], &options).unwrap();
+    assert_eq!((BASE_VERSION, BASE_VERSION + 1), (report.from_version, report.to_version));
+    assert!(table_exists(&conn, "widget"));
+
+    // Only the remaining migration is applied:
+    let report = conn.migrate_with(TEST_MIGRATIONS, &options).unwrap();
+    assert_eq!(vec!["Add widget colors"], report.applied);
+    assert_eq!(Some(BASE_VERSION + 2), conn.get_version().unwrap());
+
+    // Code that doesn't know about newer versions refuses to touch the DB:
+    assert!(conn.migrate_with(&TEST_MIGRATIONS[..1], &options).is_err());
+}
+
+#[test]
+fn migrate_dry_run() {
+    let db = TempDB::new("dry-run");
+    let conn = db.connection();
+    conn.setup_new().unwrap();
+
+    let options = MigrateOptions{ dry_run: true, backup: true };
+    let report = conn.migrate_with(TEST_MIGRATIONS, &options).unwrap();
+    assert_eq!(BASE_VERSION + 2, report.to_version);
+    assert_eq!(2, report.applied.len());
+    assert!(report.backup_file.is_none());
+
+    assert!(!table_exists(&conn, "widget"));
+    assert_eq!(Some(BASE_VERSION), conn.get_version().unwrap());
+}
+
+#[test]
+fn migrate_failure_rolls_back() {
+    let db = TempDB::new("rollback");
+    let conn = db.connection();
+    conn.setup_new().unwrap();
+
+    let migrations = &[
+        Migration{ from_version: BASE_VERSION, description: "Add widget table", apply: add_widget_table },
+        Migration{ from_version: BASE_VERSION + 1, description: "Fail", apply: fail },
+    ];
+    let options = MigrateOptions{ dry_run: false, backup: false };
+    assert!(conn.migrate_with(migrations, &options).is_err());
+
+    assert!(!table_exists(&conn, "widget"));
+    assert_eq!(Some(BASE_VERSION), conn.get_version().unwrap());
+}
+
+#[test]
+fn migrate_makes_backup() {
+    let db = TempDB::new("backup");
+    let conn = db.connection();
+    conn.setup_new().unwrap();
+
+    let report = conn.migrate_with(TEST_MIGRATIONS, &MigrateOptions::default()).unwrap();
+    let backup_file = report.backup_file.expect("backup file");
+
+    let backup = TempDB{ path: backup_file };
+    let backup_conn = backup.connection();
+    assert_eq!(Some(BASE_VERSION), backup_conn.get_version().unwrap());
+    assert!(!table_exists(&backup_conn, "widget"));
+}
+
+fn with_factory(name: &str, test: fn(&dyn backend::Factory)) {
+    let db = crate::testing::TempDB::new(name);
+    test(&db.factory());
+}
+
+crate::backend::tests::backend_tests!(with_factory);
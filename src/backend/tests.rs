@@ -0,0 +1,820 @@
+//! Tests that every Backend should pass.
+//!
+//! Each backend's tests run these with [`backend_tests!`], which takes a
+//! function that runs a test with a new, initialized database:
+//!
+//! ```ignore
+//! fn with_factory(name: &str, test: fn(&dyn Factory)) { ... }
+//! backend_tests!(with_factory);
+//! ```
+
+use protobuf::Message as _;
+
+use crate::backend::{Backend, Cursor, Error, Factory, FnIter, ItemRow, OutboxEntry, QuotaDenyReason, SearchScope, ServerUser, Signature, SyncServer, Timestamp, UserID, VerifyOptions};
+use crate::protos::Item;
+
+/// Generate a #[test] for each test in this module.
+macro_rules! backend_tests {
+    ($with_factory:path) => {
+        crate::backend::tests::backend_tests!($with_factory:
+            server_user_admin,
+            server_user_quota,
+            follow_quota,
+            attachments,
+            replies,
+            search_items,
+            cursor_pagination,
+            received_items,
+            verify_items,
+            profile_replacement,
+            feed_items,
+            user_known,
+            profile_revocation,
+            sync_state,
+            outbox
+        );
+    };
+    ($with_factory:path: $($test:ident),*) => {
+        $(
+            #[test]
+            fn $test() {
+                $with_factory(stringify!($test), crate::backend::tests::$test);
+            }
+        )*
+    };
+}
+pub(crate) use backend_tests;
+
+fn test_user(n: u8) -> UserID {
+    UserID::from_vec(vec![n; 32]).unwrap()
+}
+
+/// Save an item for `user` with arbitrary (unsigned) bytes.
+fn save_fake_item(conn: &mut dyn Backend, user: &UserID, n: u8, timestamp: i64, len: usize) {
+    let row = ItemRow{
+        user: user.clone(),
+        signature: Signature::from_vec(vec![n; 64]).unwrap(),
+        timestamp: Timestamp{ unix_utc_ms: timestamp },
+        received: Timestamp{ unix_utc_ms: timestamp },
+        item_bytes: vec![0; len],
+    };
+    conn.save_user_item(&row, &Item::new()).unwrap();
+}
+
+pub(crate) fn server_user_admin(factory: &dyn Factory) {
+    let mut conn = factory.open().unwrap();
+
+    let user = test_user(1);
+    let result = conn.update_server_user(&ServerUser{ user: user.clone(), notes: "".into(), on_homepage: true, max_bytes: None, max_item_size: None });
+    assert!(matches!(result, Err(Error::NotFound(_))));
+    assert!(matches!(conn.remove_server_user(&user), Err(Error::NotFound(_))));
+
+    conn.add_server_user(&ServerUser{ user: user.clone(), notes: "first".into(), on_homepage: false, max_bytes: None, max_item_size: None }).unwrap();
+    let result = conn.add_server_user(&ServerUser{ user: user.clone(), notes: "again".into(), on_homepage: false, max_bytes: None, max_item_size: None });
+    assert!(matches!(result, Err(Error::Conflict(_))));
+    conn.update_server_user(&ServerUser{ user: user.clone(), notes: "second".into(), on_homepage: true, max_bytes: Some(1000), max_item_size: Some(500) }).unwrap();
+
+    let found = conn.server_user(&user).unwrap().expect("server user");
+    assert_eq!("second", found.notes);
+    assert!(found.on_homepage);
+    assert_eq!(Some(1000), found.max_bytes);
+    assert_eq!(Some(500), found.max_item_size);
+
+    save_fake_item(conn.as_mut(), &user, 1, 100, 10);
+    save_fake_item(conn.as_mut(), &user, 2, 200, 25);
+    save_fake_item(conn.as_mut(), &test_user(2), 3, 200, 1000);
+    let usage = conn.user_usage(&user).unwrap();
+    assert_eq!((2, 35), (usage.item_count, usage.item_bytes));
+
+    let duplicate = conn.user_item(&user, &Signature::from_vec(vec![1; 64]).unwrap()).unwrap().unwrap();
+    assert!(matches!(conn.save_user_item(&duplicate, &Item::new()), Err(Error::Conflict(_))));
+    assert!(matches!(conn.init(), Err(Error::Conflict(_))));
+
+    conn.remove_server_user(&user).unwrap();
+    assert!(conn.server_user(&user).unwrap().is_none());
+    assert!(!conn.user_known(&user).unwrap());
+
+    // Items are kept:
+    assert_eq!(2, conn.user_usage(&user).unwrap().item_count);
+}
+
+fn item_at(timestamp: i64) -> Item {
+    let mut item = Item::new();
+    item.timestamp_ms_utc = timestamp;
+    item
+}
+
+pub(crate) fn server_user_quota(factory: &dyn Factory) {
+    let mut conn = factory.open().unwrap();
+
+    let user = test_user(1);
+    conn.add_server_user(&ServerUser{ user: user.clone(), notes: "".into(), on_homepage: false, max_bytes: Some(100), max_item_size: None }).unwrap();
+    save_fake_item(conn.as_mut(), &user, 1, 1000, 40);
+    save_fake_item(conn.as_mut(), &user, 2, 2000, 40);
+
+    // Newest items can always fill the quota:
+    assert!(conn.quota_check_item(&user, &[0; 20], &item_at(3000)).unwrap().is_none());
+    assert!(conn.quota_check_item(&user, &[0; 60], &item_at(3000)).unwrap().is_none());
+    assert!(conn.quota_check_item(&user, &[0; 101], &item_at(3000)).unwrap().is_some());
+
+    // Older items compete with newer ones:
+    assert!(conn.quota_check_item(&user, &[0; 60], &item_at(1500)).unwrap().is_none());
+    match conn.quota_check_item(&user, &[0; 61], &item_at(500)).unwrap() {
+        Some(QuotaDenyReason::NewerItemsExceedQuota{ max_bytes, newer_bytes, new_bytes }) => {
+            assert_eq!((100, 80, 61), (max_bytes, newer_bytes, new_bytes));
+        },
+        _ => panic!("Expected NewerItemsExceedQuota"),
+    }
+
+    // Unlimited:
+    conn.update_server_user(&ServerUser{ user: user.clone(), notes: "".into(), on_homepage: false, max_bytes: None, max_item_size: None }).unwrap();
+    assert!(conn.quota_check_item(&user, &[0; 10_000], &item_at(500)).unwrap().is_none());
+}
+
+/// Save a profile for `user` which follows the given users, with quotas.
+fn save_follows(conn: &mut dyn Backend, user: &UserID, timestamp: i64, follows: &[(&UserID, u64)]) {
+    let mut item = item_at(timestamp);
+    for (followed, max_bytes) in follows {
+        let mut follow = crate::protos::Follow::new();
+        follow.mut_user().set_bytes(followed.bytes().to_vec());
+        follow.set_max_bytes(*max_bytes);
+        item.mut_profile().mut_follows().push(follow);
+    }
+
+    let row = ItemRow{
+        user: user.clone(),
+        signature: Signature::from_vec(vec![timestamp as u8; 64]).unwrap(),
+        timestamp: Timestamp{ unix_utc_ms: timestamp },
+        received: Timestamp{ unix_utc_ms: timestamp },
+        item_bytes: item.write_to_bytes().unwrap(),
+    };
+    conn.save_user_item(&row, &item).unwrap();
+}
+
+pub(crate) fn follow_quota(factory: &dyn Factory) {
+    let mut conn = factory.open().unwrap();
+
+    let (alice, bob, carol, dave) = (test_user(1), test_user(2), test_user(3), test_user(4));
+    for user in &[&alice, &bob] {
+        conn.add_server_user(&ServerUser{ user: (*user).clone(), notes: "".into(), on_homepage: false, max_bytes: None, max_item_size: None }).unwrap();
+    }
+
+    save_fake_item(conn.as_mut(), &carol, 10, 1000, 50);
+    assert!(conn.quota_check_item(&carol, &[0; 10], &item_at(2000)).unwrap().is_some());
+
+    // The most generous quota wins:
+    save_follows(conn.as_mut(), &alice, 1, &[(&carol, 60)]);
+    save_follows(conn.as_mut(), &bob, 2, &[(&carol, 100), (&dave, 10)]);
+    assert!(conn.quota_check_item(&carol, &[0; 50], &item_at(500)).unwrap().is_none());
+    assert!(conn.quota_check_item(&carol, &[0; 51], &item_at(500)).unwrap().is_some());
+    assert!(conn.quota_check_item(&dave, &[0; 11], &item_at(500)).unwrap().is_some());
+
+    // A follow without a quota is unlimited:
+    save_follows(conn.as_mut(), &alice, 3, &[(&carol, 0)]);
+    assert!(conn.quota_check_item(&carol, &[0; 10_000], &item_at(500)).unwrap().is_none());
+
+    // Follows by users who aren't server users don't count:
+    save_follows(conn.as_mut(), &carol, 4, &[(&dave, 0)]);
+    assert!(conn.quota_check_item(&dave, &[0; 11], &item_at(500)).unwrap().is_some());
+}
+
+pub(crate) fn attachments(factory: &dyn Factory) {
+    let mut conn = factory.open().unwrap();
+
+    let user = test_user(1);
+    conn.add_server_user(&ServerUser{ user: user.clone(), notes: "".into(), on_homepage: false, max_bytes: Some(1000), max_item_size: None }).unwrap();
+
+    let data = vec![7u8; 50];
+    let hash = multihash::encode(multihash::Hash::SHA2512, &data).unwrap();
+
+    let mut item = item_at(1000);
+    let mut attachment = crate::protos::Attachment::new();
+    attachment.set_name("seven.bin".into());
+    attachment.set_size(data.len() as u64);
+    attachment.set_hash(hash.clone());
+    item.mut_post().mut_attachments().push(attachment);
+
+    let item_bytes = item.write_to_bytes().unwrap();
+    let row = ItemRow{
+        user: user.clone(),
+        signature: Signature::from_vec(vec![1; 64]).unwrap(),
+        timestamp: Timestamp{ unix_utc_ms: 1000 },
+        received: Timestamp{ unix_utc_ms: 1000 },
+        item_bytes: item_bytes.clone(),
+    };
+    conn.save_user_item(&row, &item).unwrap();
+
+    // Attachments don't count against the quota until they're uploaded:
+    assert!(!conn.blob_exists(&hash).unwrap());
+    assert_eq!(0, conn.user_usage(&user).unwrap().attachment_bytes);
+    let room = 1000 - item_bytes.len() as u64;
+    assert!(conn.quota_check_attachment(&user, &item, room).unwrap().is_none());
+
+    conn.save_blob(&hash, &data).unwrap();
+    // Saving the same content again is a no-op:
+    conn.save_blob(&hash, &data).unwrap();
+    assert!(conn.blob_exists(&hash).unwrap());
+    assert_eq!(Some(data.clone()), conn.blob(&hash).unwrap());
+    assert_eq!(None, conn.blob(&[0; 64]).unwrap());
+
+    assert_eq!(50, conn.user_usage(&user).unwrap().attachment_bytes);
+    assert!(conn.quota_check_attachment(&user, &item, room).unwrap().is_some());
+    assert!(conn.quota_check_attachment(&user, &item, room - 50).unwrap().is_none());
+}
+
+/// Save a post by `user` which replies to `reply_to`.
+fn save_reply_item(conn: &mut dyn Backend, user: &UserID, n: u8, timestamp: i64, reply_to: (&UserID, &Signature)) -> Signature {
+    let mut item = item_at(timestamp);
+    let post_ref = item.mut_post().mut_reply_to();
+    post_ref.mut_user_id().set_bytes(reply_to.0.bytes().to_vec());
+    post_ref.mut_signature().set_bytes(reply_to.1.bytes().to_vec());
+
+    let signature = Signature::from_vec(vec![n; 64]).unwrap();
+    let row = ItemRow{
+        user: user.clone(),
+        signature: signature.clone(),
+        timestamp: Timestamp{ unix_utc_ms: timestamp },
+        received: Timestamp{ unix_utc_ms: timestamp },
+        item_bytes: item.write_to_bytes().unwrap(),
+    };
+    conn.save_user_item(&row, &item).unwrap();
+    signature
+}
+
+fn reply_signatures(conn: &dyn Backend, post: (&UserID, &Signature), followed_by: Option<&UserID>) -> Vec<u8> {
+    let mut found = vec![];
+    conn.reply_items(post.0, post.1, followed_by, &Cursor::before(Timestamp{ unix_utc_ms: 10_000 }), &mut |row| {
+        found.push(row.item.signature.bytes()[0]);
+        Ok(true)
+    }).unwrap();
+    found
+}
+
+pub(crate) fn replies(factory: &dyn Factory) {
+    let mut conn = factory.open().unwrap();
+
+    let (alice, bob, carol) = (test_user(1), test_user(2), test_user(3));
+    save_fake_item(conn.as_mut(), &alice, 1, 1000, 10);
+    let post = Signature::from_vec(vec![1; 64]).unwrap();
+    save_follows(conn.as_mut(), &alice, 500, &[(&bob, 0)]);
+
+    save_reply_item(conn.as_mut(), &bob, 2, 2000, (&alice, &post));
+    save_reply_item(conn.as_mut(), &carol, 3, 3000, (&alice, &post));
+    save_reply_item(conn.as_mut(), &alice, 4, 4000, (&alice, &post));
+    // Replies to replies aren't replies to the original post:
+    let bob_reply = Signature::from_vec(vec![2; 64]).unwrap();
+    save_reply_item(conn.as_mut(), &alice, 5, 5000, (&bob, &bob_reply));
+
+    // Newest first:
+    assert_eq!(vec![4, 3, 2], reply_signatures(conn.as_ref(), (&alice, &post), None));
+    // Carol isn't followed by Alice:
+    assert_eq!(vec![4, 2], reply_signatures(conn.as_ref(), (&alice, &post), Some(&alice)));
+    assert_eq!(vec![5], reply_signatures(conn.as_ref(), (&bob, &bob_reply), None));
+}
+
+fn save_post(conn: &mut dyn Backend, user: &UserID, n: u8, timestamp: i64, title: &str, body: &str) {
+    let mut item = item_at(timestamp);
+    item.mut_post().set_title(title.into());
+    item.mut_post().set_body(body.into());
+
+    let row = ItemRow{
+        user: user.clone(),
+        signature: Signature::from_vec(vec![n; 64]).unwrap(),
+        timestamp: Timestamp{ unix_utc_ms: timestamp },
+        received: Timestamp{ unix_utc_ms: timestamp },
+        item_bytes: item.write_to_bytes().unwrap(),
+    };
+    conn.save_user_item(&row, &item).unwrap();
+}
+
+fn save_profile(conn: &mut dyn Backend, user: &UserID, n: u8, timestamp: i64, display_name: &str) {
+    let mut item = item_at(timestamp);
+    item.mut_profile().set_display_name(display_name.into());
+
+    let row = ItemRow{
+        user: user.clone(),
+        signature: Signature::from_vec(vec![n; 64]).unwrap(),
+        timestamp: Timestamp{ unix_utc_ms: timestamp },
+        received: Timestamp{ unix_utc_ms: timestamp },
+        item_bytes: item.write_to_bytes().unwrap(),
+    };
+    conn.save_user_item(&row, &item).unwrap();
+}
+
+fn search(conn: &dyn Backend, query: &str, scope: SearchScope, before: i64) -> Vec<u8> {
+    let mut found = vec![];
+    conn.search_items(query, &scope, &Cursor::before(Timestamp{ unix_utc_ms: before }), &mut |row| {
+        found.push(row.item.signature.bytes()[0]);
+        Ok(true)
+    }).unwrap();
+    found
+}
+
+pub(crate) fn search_items(factory: &dyn Factory) {
+    let mut conn = factory.open().unwrap();
+
+    let (alice, bob, carol) = (test_user(1), test_user(2), test_user(3));
+    save_post(conn.as_mut(), &alice, 1, 1000, "Rust", "Borrow checkers and lifetimes.");
+    save_post(conn.as_mut(), &bob, 2, 2000, "Gardening", "Tomatoes need no borrow checker.");
+    save_post(conn.as_mut(), &carol, 3, 3000, "", "My BORROWED lawnmower.");
+    save_follows(conn.as_mut(), &alice, 4, &[(&bob, 0)]);
+    save_profile(conn.as_mut(), &carol, 5, 5000, "Carol the Gardener");
+
+    // Newest first, case insensitive, all words must match:
+    assert_eq!(vec![2, 1], search(conn.as_ref(), "borrow", SearchScope::All, 10_000));
+    assert_eq!(vec![1], search(conn.as_ref(), "Borrow LIFETIMES", SearchScope::All, 10_000));
+    // Profiles are searchable too:
+    assert_eq!(vec![5], search(conn.as_ref(), "gardener", SearchScope::All, 10_000));
+    assert_eq!(vec![1], search(conn.as_ref(), "borrow", SearchScope::All, 2000));
+
+    // Scopes:
+    assert_eq!(vec![2], search(conn.as_ref(), "borrow", SearchScope::User(bob.clone()), 10_000));
+    assert_eq!(vec![2, 1], search(conn.as_ref(), "borrow", SearchScope::Feed(alice.clone()), 10_000));
+    assert!(search(conn.as_ref(), "borrow", SearchScope::Feed(bob.clone()), 10_000).contains(&2));
+
+    // FTS syntax is treated as plain text:
+    assert!(search(conn.as_ref(), "\"unbalanced OR NEAR(", SearchScope::All, 10_000).is_empty());
+    assert!(search(conn.as_ref(), "   ", SearchScope::All, 10_000).is_empty());
+
+    // Only users' current profiles are searchable:
+    save_profile(conn.as_mut(), &carol, 6, 6000, "Carol the Baker");
+    save_profile(conn.as_mut(), &carol, 7, 5500, "Carol the Plumber");
+    assert!(search(conn.as_ref(), "gardener", SearchScope::All, 10_000).is_empty());
+    assert!(search(conn.as_ref(), "plumber", SearchScope::All, 10_000).is_empty());
+    assert_eq!(vec![6], search(conn.as_ref(), "carol", SearchScope::All, 10_000));
+}
+
+/// Page through a listing `page_size` items at a time, by cursor.
+fn page_through(page_size: usize, list: &dyn Fn(&Cursor, FnIter<ItemRow>)) -> Vec<Vec<u8>> {
+    let mut found = vec![];
+    let mut cursor = Cursor::before(Timestamp{ unix_utc_ms: 10_000 });
+    loop {
+        let mut page = vec![];
+        list(&cursor, &mut |row| {
+            page.push(row);
+            Ok(page.len() < page_size)
+        });
+        match page.last() {
+            None => return found,
+            Some(last) => cursor = last.cursor(),
+        }
+        found.extend(page.into_iter().map(|row| row.signature.bytes().to_vec()));
+    }
+}
+
+pub(crate) fn cursor_pagination(factory: &dyn Factory) {
+    let mut conn = factory.open().unwrap();
+
+    let (alice, bob) = (test_user(1), test_user(2));
+    conn.add_server_user(&ServerUser{ user: alice.clone(), notes: "".into(), on_homepage: true, max_bytes: None, max_item_size: None }).unwrap();
+    conn.add_server_user(&ServerUser{ user: bob.clone(), notes: "".into(), on_homepage: true, max_bytes: None, max_item_size: None }).unwrap();
+    save_follows(conn.as_mut(), &alice, 1, &[(&bob, 0)]);
+
+    // Lots of items that share timestamps:
+    for n in 10..20 {
+        save_fake_item(conn.as_mut(), &alice, n, 1000, 10);
+    }
+    for n in 20..25 {
+        save_fake_item(conn.as_mut(), &bob, n, 1000, 10);
+    }
+    save_fake_item(conn.as_mut(), &bob, 30, 2000, 10);
+    save_fake_item(conn.as_mut(), &alice, 31, 500, 10);
+
+    for page_size in 1..5 {
+        let user_items = page_through(page_size, &|cursor, cb| {
+            conn.user_items(&alice, cursor, cb).unwrap();
+        });
+        // (Including the profile that saved the follows.)
+        assert_eq!(12, user_items.len(), "page size {}", page_size);
+        assert_eq!(vec![31; 64], user_items[10]);
+
+        let homepage = page_through(page_size, &|cursor, cb| {
+            conn.homepage_items(cursor, &mut |row| cb(row.item)).unwrap();
+        });
+        let feed = page_through(page_size, &|cursor, cb| {
+            conn.user_feed_items(&alice, cursor, &mut |row| cb(row.item)).unwrap();
+        });
+        assert_eq!(18, homepage.len(), "page size {}", page_size);
+        assert_eq!(vec![30; 64], homepage[0]);
+        assert_eq!(homepage, feed);
+
+        let mut unique = homepage.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(18, unique.len());
+    }
+
+    // A cursor with no item skips everything at its timestamp:
+    let mut count = 0;
+    conn.user_items(&alice, &Cursor::before(Timestamp{ unix_utc_ms: 1000 }), &mut |_| { count += 1; Ok(true) }).unwrap();
+    assert_eq!(2, count);
+}
+
+/// Save an item with arbitrary bytes, which we received at `received`.
+fn save_received_item(conn: &mut dyn Backend, user: &UserID, n: u8, timestamp: i64, received: i64) {
+    let row = ItemRow{
+        user: user.clone(),
+        signature: Signature::from_vec(vec![n; 64]).unwrap(),
+        timestamp: Timestamp{ unix_utc_ms: timestamp },
+        received: Timestamp{ unix_utc_ms: received },
+        item_bytes: vec![0; 10],
+    };
+    conn.save_user_item(&row, &Item::new()).unwrap();
+}
+
+/// The `n` of each item listed by received_items.
+fn received(conn: &dyn Backend, user: Option<&UserID>, after: &Cursor, page_size: usize) -> Vec<u8> {
+    let mut found = vec![];
+    let mut cursor = after.clone();
+    loop {
+        let mut page = vec![];
+        conn.received_items(user, &cursor, &mut |row| {
+            page.push(row);
+            Ok(page.len() < page_size)
+        }).unwrap();
+        match page.last() {
+            None => return found,
+            Some(last) => cursor = last.received_cursor(),
+        }
+        found.extend(page.iter().map(|row| row.signature.bytes()[0]));
+    }
+}
+
+pub(crate) fn received_items(factory: &dyn Factory) {
+    let mut conn = factory.open().unwrap();
+
+    let (alice, bob) = (test_user(1), test_user(2));
+    save_received_item(conn.as_mut(), &alice, 1, 1000, 1000);
+    save_received_item(conn.as_mut(), &bob, 2, 2000, 2000);
+    // Back-dated, so it wouldn't show up after 2000 in timestamp order:
+    save_received_item(conn.as_mut(), &alice, 3, 500, 3000);
+    save_received_item(conn.as_mut(), &bob, 4, 3000, 3000);
+    save_received_item(conn.as_mut(), &alice, 5, 3000, 3000);
+
+    let at = |ms| Cursor::after(Timestamp{ unix_utc_ms: ms });
+    for page_size in 1..4 {
+        assert_eq!(vec![1, 2, 3, 5, 4], received(conn.as_ref(), None, &at(0), page_size));
+        assert_eq!(vec![3, 5, 4], received(conn.as_ref(), None, &at(2000), page_size));
+        assert_eq!(vec![1, 3, 5], received(conn.as_ref(), Some(&alice), &at(0), page_size));
+        assert_eq!(vec![3, 5], received(conn.as_ref(), Some(&alice), &at(1000), page_size));
+        assert_eq!(vec![4], received(conn.as_ref(), Some(&bob), &at(2000), page_size));
+    }
+    assert!(received(conn.as_ref(), None, &at(3000), 10).is_empty());
+}
+
+fn save_signed(conn: &mut dyn Backend, keys: &crate::client::Keys, item: &Item) -> ItemRow {
+    let (signature, item_bytes) = keys.sign(item).unwrap();
+    let row = ItemRow{
+        user: keys.user.clone(),
+        signature,
+        timestamp: Timestamp{ unix_utc_ms: item.timestamp_ms_utc },
+        received: Timestamp{ unix_utc_ms: item.timestamp_ms_utc },
+        item_bytes,
+    };
+    conn.save_user_item(&row, item).unwrap();
+    row
+}
+
+fn profile_following(timestamp: i64, name: &str, followed: &UserID) -> Item {
+    let mut item = item_at(timestamp);
+    let profile = item.mut_profile();
+    profile.set_display_name(name.into());
+    let mut follow = crate::protos::Follow::new();
+    follow.mut_user().set_bytes(followed.bytes().to_vec());
+    profile.mut_follows().push(follow);
+    item
+}
+
+fn count_items(conn: &dyn Backend) -> usize {
+    let mut count = 0;
+    conn.received_items(None, &Cursor::after(Timestamp{ unix_utc_ms: 0 }), &mut |_| {
+        count += 1;
+        Ok(true)
+    }).unwrap();
+    count
+}
+
+pub(crate) fn verify_items(factory: &dyn Factory) {
+    let mut conn = factory.open().unwrap();
+
+    let alice = crate::client::Keys::generate();
+    let (bob, carol) = (test_user(2), test_user(3));
+    let mut post = item_at(100);
+    post.mut_post().set_title("Hello".into());
+    save_signed(conn.as_mut(), &alice, &post);
+    save_signed(conn.as_mut(), &alice, &profile_following(100, "Alice", &bob));
+    let good_profile = save_signed(conn.as_mut(), &alice, &profile_following(200, "Alice", &carol));
+
+    // Intact, but breaks a rule that may be newer than the item:
+    let mut long_title = item_at(150);
+    long_title.mut_post().set_title("x".repeat(1000));
+    let item_bytes = long_title.write_to_bytes().unwrap();
+    conn.save_user_item(&ItemRow{
+        user: alice.user.clone(),
+        signature: alice.sign_bytes(&item_bytes),
+        timestamp: Timestamp{ unix_utc_ms: 150 },
+        received: Timestamp{ unix_utc_ms: 150 },
+        item_bytes,
+    }, &long_title).unwrap();
+
+    let report = conn.verify(&VerifyOptions::default()).unwrap();
+    assert_eq!(4, report.items);
+    assert!(report.problems.is_empty(), "{:?}", report.problems);
+    assert_eq!(1, report.warnings.len(), "{:?}", report.warnings);
+    assert!(report.warnings[0].description.contains("title"), "{:?}", report.warnings);
+
+    // Unsigned:
+    save_fake_item(conn.as_mut(), &test_user(4), 1, 100, 10);
+
+    // Signed, but stored with the wrong timestamp:
+    let mut row = alice.sign(&item_at(300)).map(|(signature, item_bytes)| ItemRow{
+        user: alice.user.clone(),
+        signature,
+        timestamp: Timestamp{ unix_utc_ms: 1 },
+        received: Timestamp{ unix_utc_ms: 300 },
+        item_bytes,
+    }).unwrap();
+    conn.save_user_item(&row, &item_at(300)).unwrap();
+
+    // A newer, but forged, profile replaces the valid one's metadata:
+    let forged = profile_following(400, "Mallory", &bob);
+    row.signature = Signature::from_vec(vec![9; 64]).unwrap();
+    row.timestamp = Timestamp{ unix_utc_ms: 400 };
+    row.item_bytes = forged.write_to_bytes().unwrap();
+    conn.save_user_item(&row, &forged).unwrap();
+
+    let report = conn.verify(&VerifyOptions::default()).unwrap();
+    assert_eq!(7, report.items);
+    assert_eq!(4, report.problems.len(), "{:?}", report.problems);
+    assert!(report.problems.iter().all(|p| p.fix.is_none()));
+    let timestamp_problem = report.problems.iter().find(|p| p.description.contains("timestamp")).unwrap();
+    assert!(timestamp_problem.description.contains("(1)"), "{}", timestamp_problem.description);
+    let profile_problem = report.problems.iter().find(|p| p.location.ends_with("/profile/")).unwrap();
+    assert_eq!(format!("/u/{}/profile/", alice.user.to_base58()), profile_problem.location);
+
+    // Nothing changed yet:
+    assert_eq!(7, count_items(conn.as_ref()));
+
+    let report = conn.verify(&VerifyOptions{ quarantine: true }).unwrap();
+    assert_eq!(4, report.problems.len());
+    assert!(report.problems.iter().all(|p| p.fix.is_some()));
+    // Warnings aren't quarantined:
+    assert_eq!(4, count_items(conn.as_ref()));
+    assert_eq!(1, report.warnings.len());
+
+    let profile = conn.user_profile(&alice.user).unwrap().unwrap();
+    assert_eq!(good_profile.signature.bytes(), profile.signature.bytes());
+    // Follows come from the good profile again:
+    conn.add_server_user(&ServerUser{ user: alice.user.clone(), notes: "".into(), on_homepage: false, max_bytes: None, max_item_size: None }).unwrap();
+    assert!(conn.user_known(&carol).unwrap());
+    assert!(!conn.user_known(&bob).unwrap());
+
+    let report = conn.verify(&VerifyOptions::default()).unwrap();
+    assert!(report.problems.is_empty(), "{:?}", report.problems);
+}
+
+/// Save an item for `user`, with a fake signature.
+fn save_unsigned(conn: &mut dyn Backend, user: &UserID, n: u8, item: &Item) -> Signature {
+    let signature = Signature::from_vec(vec![n; 64]).unwrap();
+    let row = ItemRow{
+        user: user.clone(),
+        signature: signature.clone(),
+        timestamp: Timestamp{ unix_utc_ms: item.timestamp_ms_utc },
+        received: Timestamp{ unix_utc_ms: item.timestamp_ms_utc },
+        item_bytes: item.write_to_bytes().unwrap(),
+    };
+    conn.save_user_item(&row, item).unwrap();
+    signature
+}
+
+/// The `n` and display name of each item in a user's feed.
+fn feed(conn: &dyn Backend, user: &UserID) -> Vec<(u8, Option<String>)> {
+    let mut found = vec![];
+    conn.user_feed_items(user, &Cursor::before(Timestamp{ unix_utc_ms: 10_000 }), &mut |row| {
+        found.push((row.item.signature.bytes()[0], row.display_name));
+        Ok(true)
+    }).unwrap();
+    found
+}
+
+pub(crate) fn profile_replacement(factory: &dyn Factory) {
+    let mut conn = factory.open().unwrap();
+
+    let (alice, bob, carol) = (test_user(1), test_user(2), test_user(3));
+    save_fake_item(conn.as_mut(), &bob, 20, 1000, 10);
+    save_fake_item(conn.as_mut(), &carol, 30, 1000, 10);
+
+    let newer = save_unsigned(conn.as_mut(), &alice, 2, &profile_following(200, "Alice v2", &bob));
+    // Saving an older profile keeps the newer one's metadata:
+    save_unsigned(conn.as_mut(), &alice, 1, &profile_following(100, "Alice v1", &carol));
+    assert_eq!(newer.bytes(), conn.user_profile(&alice).unwrap().unwrap().signature.bytes());
+    let names: Vec<_> = feed(conn.as_ref(), &alice).into_iter().map(|(n, _)| n).collect();
+    assert_eq!(vec![20, 2, 1], names);
+
+    // A newer profile replaces all of the follows:
+    let newest = save_unsigned(conn.as_mut(), &alice, 3, &profile_following(300, "", &carol));
+    assert_eq!(newest.bytes(), conn.user_profile(&alice).unwrap().unwrap().signature.bytes());
+    let names: Vec<_> = feed(conn.as_ref(), &alice).into_iter().map(|(n, _)| n).collect();
+    assert_eq!(vec![30, 3, 2, 1], names);
+
+    // Empty display names aren't displayed:
+    conn.add_server_user(&ServerUser{ user: alice.clone(), notes: "".into(), on_homepage: true, max_bytes: None, max_item_size: None }).unwrap();
+    let mut display_names = vec![];
+    conn.homepage_items(&Cursor::before(Timestamp{ unix_utc_ms: 10_000 }), &mut |row| {
+        display_names.push(row.display_name);
+        Ok(true)
+    }).unwrap();
+    assert_eq!(vec![None, None, None], display_names);
+
+    assert!(conn.user_profile(&bob).unwrap().is_none());
+}
+
+pub(crate) fn feed_items(factory: &dyn Factory) {
+    let mut conn = factory.open().unwrap();
+
+    let (alice, bob, carol, dave) = (test_user(1), test_user(2), test_user(3), test_user(4));
+    let mut profile = item_at(1);
+    profile.mut_profile().set_display_name("Alice".into());
+    for (user, display_name) in &[(&bob, "Bobby"), (&carol, "  ")] {
+        let mut follow = crate::protos::Follow::new();
+        follow.mut_user().set_bytes(user.bytes().to_vec());
+        follow.set_display_name(display_name.to_string());
+        profile.mut_profile().mut_follows().push(follow);
+    }
+    save_unsigned(conn.as_mut(), &alice, 1, &profile);
+    save_unsigned(conn.as_mut(), &bob, 2, &profile_following(2, "Bob", &dave));
+    save_unsigned(conn.as_mut(), &carol, 3, &profile_following(3, "Carol", &dave));
+
+    save_fake_item(conn.as_mut(), &alice, 10, 1000, 10);
+    save_fake_item(conn.as_mut(), &bob, 20, 2000, 10);
+    save_fake_item(conn.as_mut(), &carol, 30, 3000, 10);
+    save_fake_item(conn.as_mut(), &dave, 40, 4000, 10);
+
+    // The user's own items, and those of users they follow. Names that the
+    // user gave to follows take precedence, unless they're empty:
+    let some = |name: &str| Some(name.to_string());
+    assert_eq!(
+        vec![
+            (30, some("Carol")),
+            (20, some("Bobby")),
+            (10, some("Alice")),
+            (3, some("Carol")),
+            (2, some("Bobby")),
+            (1, some("Alice")),
+        ],
+        feed(conn.as_ref(), &alice),
+    );
+
+    // Following isn't mutual:
+    let bob_feed: Vec<_> = feed(conn.as_ref(), &bob).into_iter().map(|(n, _)| n).collect();
+    assert_eq!(vec![40, 20, 2], bob_feed);
+
+    // Users without a profile only see their own items:
+    assert_eq!(vec![(40, None)], feed(conn.as_ref(), &dave));
+}
+
+pub(crate) fn user_known(factory: &dyn Factory) {
+    let mut conn = factory.open().unwrap();
+
+    let (alice, bob, carol) = (test_user(1), test_user(2), test_user(3));
+    assert!(!conn.user_known(&alice).unwrap());
+
+    // Follows only count if they're by server users:
+    save_unsigned(conn.as_mut(), &alice, 1, &profile_following(100, "Alice", &bob));
+    assert!(!conn.user_known(&bob).unwrap());
+
+    conn.add_server_user(&ServerUser{ user: alice.clone(), notes: "".into(), on_homepage: false, max_bytes: None, max_item_size: None }).unwrap();
+    assert!(conn.user_known(&alice).unwrap());
+    assert!(conn.user_known(&bob).unwrap());
+    assert!(!conn.user_known(&carol).unwrap());
+
+    save_unsigned(conn.as_mut(), &alice, 2, &profile_following(200, "Alice", &carol));
+    assert!(!conn.user_known(&bob).unwrap());
+    assert!(conn.user_known(&carol).unwrap());
+
+    conn.remove_server_user(&alice).unwrap();
+    assert!(!conn.user_known(&alice).unwrap());
+    assert!(!conn.user_known(&carol).unwrap());
+}
+
+pub(crate) fn profile_revocation(factory: &dyn Factory) {
+    let mut conn = factory.open().unwrap();
+
+    let alice = crate::client::Keys::generate();
+    let (bob, carol) = (test_user(2), test_user(3));
+    conn.add_server_user(&ServerUser{ user: alice.user.clone(), notes: "".into(), on_homepage: false, max_bytes: None, max_item_size: None }).unwrap();
+    save_signed(conn.as_mut(), &alice, &profile_following(200, "Alice", &bob));
+    assert!(conn.user_known(&bob).unwrap());
+    assert!(!conn.user_revoked(&alice.user).unwrap());
+
+    // Revocations replace newer profiles, and their follows are ignored:
+    let mut revocation = profile_following(100, "Alice (revoked)", &carol);
+    revocation.mut_profile().set_revoked(true);
+    let revocation = save_signed(conn.as_mut(), &alice, &revocation);
+    assert_eq!(revocation.signature.bytes(), conn.user_profile(&alice.user).unwrap().unwrap().signature.bytes());
+    assert!(conn.user_revoked(&alice.user).unwrap());
+    assert!(!conn.user_known(&alice.user).unwrap());
+    assert!(!conn.user_known(&bob).unwrap());
+    assert!(!conn.user_known(&carol).unwrap());
+    assert!(matches!(
+        conn.quota_check_item(&alice.user, &[0; 10], &item_at(300)).unwrap(),
+        Some(QuotaDenyReason::ProfileRevoked)
+    ));
+
+    // ... and are never replaced:
+    save_signed(conn.as_mut(), &alice, &profile_following(300, "Alice again", &bob));
+    assert_eq!(revocation.signature.bytes(), conn.user_profile(&alice.user).unwrap().unwrap().signature.bytes());
+    assert!(!conn.user_known(&bob).unwrap());
+
+    let report = conn.verify(&VerifyOptions::default()).unwrap();
+    assert!(report.problems.is_empty(), "{:?}", report.problems);
+}
+
+pub(crate) fn sync_state(factory: &dyn Factory) {
+    let conn = factory.open().unwrap();
+
+    let url = "https://blog.example.com";
+    assert!(conn.sync_server(url).unwrap().is_none());
+
+    conn.save_sync_server(&SyncServer{
+        url: url.into(),
+        failures: 2,
+        retry_after: Some(Timestamp{ unix_utc_ms: 5000 }),
+        last_error: Some("Timed out".into()),
+    }).unwrap();
+    let server = conn.sync_server(url).unwrap().unwrap();
+    assert_eq!(2, server.failures);
+    assert_eq!(Some(5000), server.retry_after.map(|t| t.unix_utc_ms));
+    assert_eq!(Some("Timed out".to_string()), server.last_error);
+
+    conn.save_sync_server(&SyncServer{ url: url.into(), failures: 0, retry_after: None, last_error: None }).unwrap();
+    let server = conn.sync_server(url).unwrap().unwrap();
+    assert_eq!((0, None, None), (server.failures, server.retry_after.map(|t| t.unix_utc_ms), server.last_error));
+
+    let (alice, bob) = (test_user(1), test_user(2));
+    let other_url = "https://other.example.com";
+    assert!(conn.sync_position(url, &alice).unwrap().is_none());
+    conn.save_sync_position(url, &alice, Timestamp{ unix_utc_ms: 100 }).unwrap();
+    conn.save_sync_position(url, &alice, Timestamp{ unix_utc_ms: 200 }).unwrap();
+    conn.save_sync_position(other_url, &alice, Timestamp{ unix_utc_ms: 300 }).unwrap();
+    assert_eq!(Some(200), conn.sync_position(url, &alice).unwrap().map(|t| t.unix_utc_ms));
+    assert_eq!(Some(300), conn.sync_position(other_url, &alice).unwrap().map(|t| t.unix_utc_ms));
+    assert!(conn.sync_position(url, &bob).unwrap().is_none());
+}
+
+fn outbox_entries(conn: &dyn Backend, due: Option<i64>) -> Vec<OutboxEntry> {
+    let mut entries = vec![];
+    conn.outbox_entries(due.map(|unix_utc_ms| Timestamp{ unix_utc_ms }), &mut |entry| {
+        entries.push(entry);
+        Ok(true)
+    }).unwrap();
+    entries
+}
+
+pub(crate) fn outbox(factory: &dyn Factory) {
+    let conn = factory.open().unwrap();
+
+    let alice = test_user(1);
+    let (first, second) = (Signature::from_vec(vec![1; 64]).unwrap(), Signature::from_vec(vec![2; 64]).unwrap());
+    let (url, other_url) = ("https://a.example.com", "https://b.example.com");
+    let start = Timestamp::now().unix_utc_ms;
+    conn.add_outbox_entry(url, &alice, &first).unwrap();
+    conn.add_outbox_entry(url, &alice, &second).unwrap();
+    conn.add_outbox_entry(other_url, &alice, &first).unwrap();
+
+    let mut entries = outbox_entries(conn.as_ref(), None);
+    assert_eq!(3, entries.len());
+    assert!(entries.iter().all(|e| e.attempts == 0 && e.last_error.is_none()));
+    assert!(entries.iter().all(|e| e.next_attempt.unwrap().unix_utc_ms >= start));
+    assert!(outbox_entries(conn.as_ref(), Some(start - 1)).is_empty());
+
+    // Already queued entries are left alone:
+    entries[0].attempts = 1;
+    entries[0].next_attempt = Some(Timestamp{ unix_utc_ms: start + 60_000 });
+    entries[0].last_error = Some("Oops".into());
+    conn.update_outbox_entry(&entries[0]).unwrap();
+    conn.add_outbox_entry(&entries[0].url, &alice, &entries[0].signature).unwrap();
+
+    // Give up on another:
+    entries[1].attempts = 10;
+    entries[1].next_attempt = None;
+    conn.update_outbox_entry(&entries[1]).unwrap();
+
+    // Due first, and given up last:
+    let listed = outbox_entries(conn.as_ref(), None);
+    let order: Vec<_> = listed.iter().map(|e| e.attempts).collect();
+    assert_eq!(vec![0, 1, 10], order);
+    assert_eq!(Some("Oops".to_string()), listed[1].last_error);
+
+    let due = outbox_entries(conn.as_ref(), Some(start + 1000));
+    assert_eq!(1, due.len());
+    assert_eq!(entries[2].url, due[0].url);
+    assert_eq!(entries[2].signature.bytes(), due[0].signature.bytes());
+
+    conn.remove_outbox_entry(&entries[2].url, &alice, &entries[2].signature).unwrap();
+    assert_eq!(2, outbox_entries(conn.as_ref(), None).len());
+
+    let mut missing = entries[2].clone();
+    missing.attempts = 1;
+    assert!(matches!(conn.update_outbox_entry(&missing), Err(Error::NotFound(_))));
+}
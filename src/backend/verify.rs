@@ -0,0 +1,171 @@
+//! Checks shared by backends' implementations of [`Backend::verify`](super::Backend::verify).
+//!
+//! Backends read their own tables. These functions decide what's wrong with them.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use protobuf::Message as _;
+
+use crate::backend::{ItemRow, Signature, UserID, VerifyProblem, VerifyReport};
+use crate::protos::{Item, ProtoValid};
+
+/// A profile item that passed [`verify_item`].
+pub(super) type ProfileItem = (ItemRow, Item);
+
+/// The newest valid profile(s) for each user, by user_id.
+/// There may be more than one if they have the same timestamp.
+/// (Revocations count as newer than any other profile.)
+pub(super) type NewestProfiles = HashMap<Vec<u8>, Vec<ProfileItem>>;
+
+/// What the profile table says: user_id -> (signature, display_name, revoked)
+pub(super) type ProfileRows = HashMap<Vec<u8>, (Vec<u8>, String, bool)>;
+
+/// A user's follows: followed_user_id -> (display_name, max_bytes)
+pub(super) type FollowRows = BTreeMap<Vec<u8>, (String, Option<u64>)>;
+
+/// Check that an item row is authentic and intact.
+///
+/// This doesn't apply [`ProtoValid`]'s rules. They may be stricter now than
+/// when we accepted the item. (See: [`check_rules`])
+pub(super) fn verify_item(user_id: &[u8], signature: &[u8], unix_utc_ms: i64, bytes: &[u8]) -> Result<(UserID, Signature, Item), String> {
+    let user = UserID::from_vec(user_id.to_vec()).map_err(|_| "Invalid user ID".to_string())?;
+    let signature = Signature::from_vec(signature.to_vec()).map_err(|_| "Invalid signature".to_string())?;
+    if !signature.is_valid(&user, bytes) {
+        return Err("The signature doesn't match the item's bytes".into());
+    }
+
+    let mut item = Item::new();
+    item.merge_from_bytes(bytes).map_err(|e| format!("Invalid protobuf: {}", e))?;
+
+    if item.get_timestamp_ms_utc() != unix_utc_ms {
+        return Err(format!(
+            "The stored timestamp ({}) doesn't match the signed timestamp ({})",
+            unix_utc_ms,
+            item.get_timestamp_ms_utc(),
+        ));
+    }
+
+    Ok((user, signature, item))
+}
+
+/// Warn about a verified item that doesn't meet the current validation rules.
+/// It's still authentic, so we don't quarantine it.
+pub(super) fn check_rules(user: &UserID, signature: &Signature, item: &Item, report: &mut VerifyReport) {
+    if let Err(error) = item.validate() {
+        report.warnings.push(VerifyProblem{
+            location: item_location(user.bytes(), signature.bytes()),
+            description: error.to_string(),
+            fix: None,
+        });
+    }
+}
+
+/// Where to find an item. (Even if its IDs are invalid.)
+pub(super) fn item_location(user_id: &[u8], signature: &[u8]) -> String {
+    format!("/u/{}/i/{}/", bs58::encode(user_id).into_string(), bs58::encode(signature).into_string())
+}
+
+/// Convert a Follow's max_bytes, where 0 means "unlimited".
+pub(super) fn max_bytes(value: u64) -> Option<u64> {
+    Some(value as i64).filter(|bytes| *bytes > 0).map(|bytes| bytes as u64)
+}
+
+/// Keep track of a verified item, if it's one of its user's newest profiles.
+pub(super) fn add_profile(newest_profiles: &mut NewestProfiles, row: ItemRow, item: Item) {
+    if !item.has_profile() { return; }
+
+    let newest = newest_profiles.entry(row.user.bytes().to_vec()).or_default();
+    let key = |item: &Item| (item.get_profile().get_revoked(), item.get_timestamp_ms_utc());
+    match newest.first() {
+        Some((_, newest_item)) if key(newest_item) > key(&item) => return,
+        Some((_, newest_item)) if key(newest_item) < key(&item) => newest.clear(),
+        _ => {},
+    }
+    newest.push((row, item));
+}
+
+/// Compare the profile and follow tables to users' newest profiles.
+///
+/// Adds any problems to `report`, and returns the users whose profile data
+/// needs to be rebuilt, along with the profile to rebuild it from. (None if
+/// they have no valid profile, so their profile data should be removed.)
+pub(super) fn check_profiles<'a>(
+    newest_profiles: &'a NewestProfiles,
+    profile_rows: &ProfileRows,
+    follow_rows: &HashMap<Vec<u8>, FollowRows>,
+    fix: Option<&'static str>,
+    report: &mut VerifyReport,
+) -> Vec<(Vec<u8>, Option<&'a ProfileItem>)> {
+    let mut users: BTreeSet<&Vec<u8>> = newest_profiles.keys().collect();
+    users.extend(profile_rows.keys());
+    users.extend(follow_rows.keys());
+
+    let mut rebuilds = vec![];
+    for user_id in users {
+        let profile_row = profile_rows.get(user_id);
+        let no_follows = BTreeMap::new();
+        let follows = follow_rows.get(user_id).unwrap_or(&no_follows);
+
+        let mut problems = vec![];
+        let newest = newest_profiles.get(user_id);
+        let expected = match newest {
+            None => {
+                if profile_row.is_some() {
+                    problems.push("The profile table refers to a missing or invalid profile.".to_string());
+                }
+                if !follows.is_empty() {
+                    problems.push("The follow table lists follows, but the user has no valid profile.".to_string());
+                }
+                None
+            },
+            Some(newest) => {
+                let matching = profile_row.and_then(|(signature, _, _)| {
+                    newest.iter().find(|(row, _)| row.signature.bytes() == signature.as_slice())
+                });
+                let expected = match matching {
+                    Some(expected) => expected,
+                    None => {
+                        problems.push("The profile table doesn't refer to the newest profile.".to_string());
+                        &newest[0]
+                    },
+                };
+                let profile = expected.1.get_profile();
+
+                if let Some((_, display_name, revoked)) = profile_row {
+                    if display_name != profile.get_display_name() {
+                        problems.push("The profile table's display name doesn't match the profile.".to_string());
+                    }
+                    if *revoked != profile.get_revoked() {
+                        problems.push("The profile table doesn't match whether the profile is a revocation.".to_string());
+                    }
+                }
+
+                // Like saving a profile, later duplicates replace earlier ones.
+                // Follows' max_bytes are stored as signed integers, where 0 means unlimited.
+                // Revoked users don't follow anyone.
+                let expected_follows: FollowRows = profile.get_follows().iter()
+                    .filter(|_| !profile.get_revoked())
+                    .map(|follow| (
+                        follow.get_user().get_bytes().to_vec(),
+                        (follow.get_display_name().to_string(), max_bytes(follow.get_max_bytes())),
+                    ))
+                    .collect();
+                if &expected_follows != follows {
+                    problems.push("The follow table doesn't match the profile's follows.".to_string());
+                }
+
+                Some(expected)
+            },
+        };
+
+        if problems.is_empty() { continue; }
+        report.problems.push(VerifyProblem{
+            location: format!("/u/{}/profile/", bs58::encode(user_id).into_string()),
+            description: problems.join(" "),
+            fix,
+        });
+        rebuilds.push((user_id.clone(), expected));
+    }
+
+    rebuilds
+}
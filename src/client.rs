@@ -0,0 +1,316 @@
+//! A client for FeoBlog servers.
+//!
+//! Fetches and uploads Items using the URLs documented in
+//! `docs/url_layout.md`. Items are verified against their signatures before
+//! they're returned, so callers don't need to trust the server.
+
+// The CLI doesn't use all of this API (yet).
+#![allow(dead_code)]
+
+use std::path::Path;
+
+use actix_web::client::Client as HttpClient;
+use actix_web::error::PayloadError;
+use actix_web::http::StatusCode;
+use failure::{Error, bail, format_err};
+use protobuf::Message;
+use sodiumoxide::crypto::sign;
+
+use crate::backend::{Signature, Timestamp, UserID};
+use crate::protos::{Item, ItemList, Profile, ProtoValid};
+use crate::server::MAX_ITEM_SIZE;
+use crate::sync::REQUEST_TIMEOUT;
+
+/// The largest ItemList we'll accept from a server.
+const MAX_LIST_SIZE: usize = 1024 * 1024 * 10;
+
+/// A signing key pair, as stored in a key file.
+pub(crate) struct Keys {
+    pub user: UserID,
+    secret: sign::SecretKey,
+    seed: sign::Seed,
+}
+
+impl Keys {
+    pub fn generate() -> Self {
+        // Like the web client, we only keep the first half of the secret key.
+        // The rest can be derived from it.
+        let (_, secret) = sign::gen_keypair();
+        let seed = sign::Seed::from_slice(&secret.as_ref()[..sign::SEEDBYTES]).expect("seed");
+        Self::from_seed(seed)
+    }
+
+    fn from_seed(seed: sign::Seed) -> Self {
+        let (public, secret) = sign::keypair_from_seed(&seed);
+        Keys {
+            // Public keys are always the right length:
+            user: UserID::from_vec(public.as_ref().to_vec()).expect("public key"),
+            secret,
+            seed,
+        }
+    }
+
+    /// Parse a private key in the same format the web client uses:
+    /// the base58check-encoded 32-byte seed.
+    pub fn from_private_key(value: &str) -> Result<Self, Error> {
+        let bytes = bs58::decode(value.trim()).with_check(None).into_vec()
+            .map_err(|e| format_err!("Invalid private key: {:?}", e))?;
+        let seed = sign::Seed::from_slice(&bytes)
+            .ok_or_else(|| format_err!("Invalid private key: expected {} bytes, got {}", sign::SEEDBYTES, bytes.len()))?;
+        Ok(Self::from_seed(seed))
+    }
+
+    pub fn to_private_key(&self) -> String {
+        bs58::encode(self.seed.as_ref()).with_check().into_string()
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format_err!("Couldn't read key file {}: {}", path.display(), e))?;
+        Self::from_private_key(&contents)
+    }
+
+    /// Write the private key to a new file, which only the current user can read.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        use std::io::Write;
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(path)
+            .map_err(|e| format_err!("Couldn't create key file {}: {}", path.display(), e))?;
+        writeln!(file, "{}", self.to_private_key())?;
+        Ok(())
+    }
+
+    /// Validate and serialize an item, and sign its bytes.
+    pub fn sign(&self, item: &Item) -> Result<(Signature, Vec<u8>), Error> {
+        item.validate()?;
+        let bytes = item.write_to_bytes()?;
+        Ok((self.sign_bytes(&bytes), bytes))
+    }
+
+    /// Sign bytes without checking that they're a valid Item.
+    pub fn sign_bytes(&self, bytes: &[u8]) -> Signature {
+        let signature = sign::sign_detached(bytes, &self.secret);
+        Signature::from_vec(signature.as_ref().to_vec()).expect("signature")
+    }
+}
+
+/// A new Item, timestamped now, in the local time zone.
+pub(crate) fn new_item() -> Item {
+    let offset = time::UtcOffset::try_current_local_offset().unwrap_or(time::UtcOffset::UTC);
+    let mut item = Item::new();
+    item.set_timestamp_ms_utc(Timestamp::now().unix_utc_ms);
+    item.set_utc_offset_minutes(offset.as_minutes().into());
+    item
+}
+
+/// An Item whose signature we've checked.
+pub(crate) struct SignedItem {
+    pub signature: Signature,
+    pub item: Item,
+}
+
+/// What a server did with an uploaded item.
+#[derive(Debug, PartialEq)]
+pub(crate) enum PutResult {
+    Created,
+    /// The server already had the item.
+    AlreadyExists,
+}
+
+/// A server sent more bytes than we were willing to read.
+#[derive(Debug)]
+pub(crate) struct TooLarge {
+    pub url: String,
+    pub max_size: usize,
+}
+
+impl std::fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is larger than {} bytes", self.url, self.max_size)
+    }
+}
+
+impl std::error::Error for TooLarge {}
+
+pub(crate) struct Client {
+    base_url: String,
+    http: HttpClient,
+    max_item_size: usize,
+}
+
+impl Client {
+    /// A client for the server at `base_url`. ex: `https://blog.example.com`
+    pub fn new(base_url: &str) -> Self {
+        Client {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            http: HttpClient::builder().timeout(REQUEST_TIMEOUT).finish(),
+            max_item_size: MAX_ITEM_SIZE,
+        }
+    }
+
+    /// Fetch items up to `max_item_size` bytes, instead of [`MAX_ITEM_SIZE`].
+    /// (Servers may let some users post larger items.)
+    pub fn with_max_item_size(mut self, max_item_size: usize) -> Self {
+        self.max_item_size = max_item_size;
+        self
+    }
+
+    pub fn item_url(&self, user: &UserID, signature: &Signature) -> String {
+        format!("{}/u/{}/i/{}/", self.base_url, user.to_base58(), signature.to_base58())
+    }
+
+    /// Fetch an item. Returns None if the server doesn't have it.
+    pub async fn get_item(&self, user: &UserID, signature: &Signature) -> Result<Option<Item>, Error> {
+        let bytes = match self.get_item_bytes(user, signature, self.max_item_size).await? {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+        Ok(Some(parse_verified(user, signature, &bytes)?))
+    }
+
+    /// Fetch an item's bytes, without checking them.
+    /// Returns None if the server doesn't have it, or a [`TooLarge`] error if
+    /// it's larger than `max_size`.
+    pub async fn get_item_bytes(&self, user: &UserID, signature: &Signature, max_size: usize) -> Result<Option<Vec<u8>>, Error> {
+        let url = format!("{}proto3", self.item_url(user, signature));
+        Ok(self.get(&url, max_size).await?.map(|(bytes, _)| bytes))
+    }
+
+    /// Upload an item that has already been signed.
+    pub async fn put_item(&self, user: &UserID, signature: &Signature, bytes: Vec<u8>) -> Result<PutResult, Error> {
+        let url = format!("{}proto3", self.item_url(user, signature));
+        // awc's errors aren't Sync, so can't be failure::Errors.
+        let mut response = self.http.put(&url).send_body(bytes).await
+            .map_err(|e| format_err!("Error sending to {}: {}", url, e))?;
+
+        match response.status() {
+            StatusCode::CREATED => Ok(PutResult::Created),
+            StatusCode::ACCEPTED => Ok(PutResult::AlreadyExists),
+            status => {
+                let body = response.body().limit(1024).await.unwrap_or_default();
+                bail!("{} from {}: {}", status, url, String::from_utf8_lossy(&body).trim());
+            },
+        }
+    }
+
+    /// Sign an item and upload it.
+    pub async fn publish(&self, keys: &Keys, item: &Item) -> Result<Signature, Error> {
+        let (signature, bytes) = keys.sign(item)?;
+        self.put_item(&keys.user, &signature, bytes).await?;
+        Ok(signature)
+    }
+
+    /// Fetch a user's latest profile. Returns None if the server doesn't have one.
+    pub async fn profile(&self, user: &UserID) -> Result<Option<SignedItem>, Error> {
+        let url = format!("{}/u/{}/profile/proto3", self.base_url, user.to_base58());
+        let (bytes, signature) = match self.get(&url, self.max_item_size).await? {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        let signature = signature
+            .ok_or_else(|| format_err!("{} did not include a signature header", url))?;
+        let signature = Signature::from_base58(&signature)
+            .map_err(|_| format_err!("{} returned an invalid signature header", url))?;
+
+        let item = parse_verified(user, &signature, &bytes)?;
+        if !item.has_profile() {
+            bail!("{} returned an item that isn't a profile", url);
+        }
+        Ok(Some(SignedItem{ signature, item }))
+    }
+
+    /// Publish a new version of a user's profile, based on their latest one.
+    /// Starts from an empty profile if the server doesn't have one yet.
+    pub async fn update_profile<F>(&self, keys: &Keys, edit: F) -> Result<Signature, Error>
+    where F: FnOnce(&mut Profile) -> Result<(), Error>
+    {
+        let mut profile = match self.profile(&keys.user).await? {
+            Some(mut signed) => signed.item.take_profile(),
+            None => Profile::new(),
+        };
+        edit(&mut profile)?;
+
+        let mut item = new_item();
+        item.set_profile(profile);
+        self.publish(keys, &item).await
+    }
+
+    /// List the items on the server's home page.
+    pub async fn homepage_items(&self, cursor: Option<&str>) -> Result<ItemList, Error> {
+        self.item_list(format!("{}/homepage/proto3", self.base_url), cursor).await
+    }
+
+    /// List all of a user's items.
+    pub async fn user_items(&self, user: &UserID, cursor: Option<&str>) -> Result<ItemList, Error> {
+        self.item_list(format!("{}/u/{}/proto3", self.base_url, user.to_base58()), cursor).await
+    }
+
+    /// List items from the users that `user` follows.
+    pub async fn feed_items(&self, user: &UserID, cursor: Option<&str>) -> Result<ItemList, Error> {
+        self.item_list(format!("{}/u/{}/feed/proto3", self.base_url, user.to_base58()), cursor).await
+    }
+
+    /// List a user's items in the order the server received them, starting
+    /// after `received_after`. Pass a `page` (ex: `cursor=...`) to continue.
+    pub async fn received_items(&self, user: &UserID, received_after: i64, page: Option<&str>) -> Result<ItemList, Error> {
+        let mut url = format!("{}/u/{}/proto3?received_after={}", self.base_url, user.to_base58(), received_after);
+        if let Some(page) = page {
+            url = format!("{}&{}", url, page);
+        }
+        self.item_list(url, None).await
+    }
+
+    /// Get one page of an ItemList. Pass the previous page's `next_cursor` to continue.
+    async fn item_list(&self, mut url: String, cursor: Option<&str>) -> Result<ItemList, Error> {
+        if let Some(cursor) = cursor {
+            url = format!("{}?cursor={}", url, cursor);
+        }
+        let (bytes, _) = self.get(&url, MAX_LIST_SIZE).await?
+            .ok_or_else(|| format_err!("Not found: {}", url))?;
+        Ok(ItemList::parse_from_bytes(&bytes)?)
+    }
+
+    /// GET the body of a URL, and its `signature` header.
+    /// Returns None if it's not found.
+    async fn get(&self, url: &str, max_size: usize) -> Result<Option<(Vec<u8>, Option<String>)>, Error> {
+        let mut response = self.http.get(url).send().await
+            .map_err(|e| format_err!("Error fetching {}: {}", url, e))?;
+
+        match response.status() {
+            StatusCode::OK => {},
+            StatusCode::NOT_FOUND => return Ok(None),
+            status => bail!("Error fetching {}: {}", url, status),
+        }
+
+        let signature = response.headers().get("signature")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+        let body = response.body().limit(max_size).await
+            .map_err(|e| match e {
+                PayloadError::Overflow => TooLarge{ url: url.to_string(), max_size }.into(),
+                e => format_err!("Error reading {}: {}", url, e),
+            })?;
+        Ok(Some((body.to_vec(), signature)))
+    }
+}
+
+/// Parse an item, after checking that `user` signed it.
+fn parse_verified(user: &UserID, signature: &Signature, bytes: &[u8]) -> Result<Item, Error> {
+    if !signature.is_valid(user, bytes) {
+        bail!("Invalid signature {} for user {}", signature.to_base58(), user.to_base58());
+    }
+    let item = Item::parse_from_bytes(bytes)?;
+    item.validate()?;
+    Ok(item)
+}
+
+#[cfg(test)]
+mod tests;
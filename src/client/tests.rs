@@ -0,0 +1,100 @@
+use super::*;
+
+use actix_web::App;
+
+use crate::protos::Follow;
+use crate::server::routes;
+use crate::testing::{TempDB, add_server_user, app_data};
+
+#[test]
+fn private_key_round_trip() {
+    let keys = Keys::generate();
+    let private_key = keys.to_private_key();
+    let parsed = Keys::from_private_key(&format!("{}\n", private_key)).unwrap();
+    assert_eq!(keys.user.to_base58(), parsed.user.to_base58());
+
+    // A typo fails the checksum, instead of silently making a different key:
+    let mut typo = private_key.into_bytes();
+    typo[5] = if typo[5] == b'a' { b'b' } else { b'a' };
+    assert!(Keys::from_private_key(&String::from_utf8(typo).unwrap()).is_err());
+
+    // A user ID isn't a private key:
+    assert!(Keys::from_private_key(&keys.user.to_base58()).is_err());
+}
+
+#[test]
+fn signed_items_verify() {
+    let keys = Keys::generate();
+    let mut item = new_item();
+    item.mut_post().set_body("Hello".into());
+    let (signature, bytes) = keys.sign(&item).unwrap();
+    assert!(signature.is_valid(&keys.user, &bytes));
+    assert!(parse_verified(&keys.user, &signature, &bytes).is_ok());
+
+    let other = Keys::generate();
+    assert!(parse_verified(&other.user, &signature, &bytes).is_err());
+
+    // We don't sign invalid items:
+    let mut item = Item::new();
+    item.mut_post().set_body("No timestamp".into());
+    assert!(keys.sign(&item).is_err());
+}
+
+#[test]
+fn publish_and_fetch() {
+    actix_web::rt::System::new("test").block_on(async {
+        let db = TempDB::new("client");
+        let factory = db.factory();
+        let server_factory = factory.clone();
+        let server = actix_web::test::start(move || {
+            App::new()
+                .data(app_data(server_factory.clone()))
+                .configure(routes)
+        });
+        let client = Client::new(&format!("http://{}/", server.addr()));
+
+        let (alice, bob) = (Keys::generate(), Keys::generate());
+        add_server_user(&factory, &alice.user);
+
+        let mut item = new_item();
+        item.mut_post().set_title("Hello".into());
+        let signature = client.publish(&alice, &item).await.unwrap();
+        let fetched = client.get_item(&alice.user, &signature).await.unwrap().unwrap();
+        assert_eq!("Hello", fetched.get_post().get_title());
+
+        let (_, bytes) = alice.sign(&item).unwrap();
+        assert_eq!(PutResult::AlreadyExists, client.put_item(&alice.user, &signature, bytes).await.unwrap());
+
+        let list = client.user_items(&alice.user, None).await.unwrap();
+        assert_eq!(1, list.get_items().len());
+
+        // Bob isn't a user on this server:
+        assert!(client.publish(&bob, &item).await.is_err());
+        assert!(client.get_item(&bob.user, &signature).await.unwrap().is_none());
+
+        assert!(client.profile(&alice.user).await.unwrap().is_none());
+        client.update_profile(&alice, |profile| {
+            profile.set_display_name("Alice".into());
+            let mut follow = Follow::new();
+            follow.mut_user().set_bytes(bob.user.bytes().to_vec());
+            profile.mut_follows().push(follow);
+            Ok(())
+        }).await.unwrap();
+
+        // Edits start from the latest profile:
+        client.update_profile(&alice, |profile| {
+            profile.set_about("About me".into());
+            Ok(())
+        }).await.unwrap();
+        let signed = client.profile(&alice.user).await.unwrap().unwrap();
+        let profile = signed.item.get_profile();
+        assert_eq!("Alice", profile.get_display_name());
+        assert_eq!("About me", profile.get_about());
+        assert_eq!(1, profile.get_follows().len());
+
+        // Now that Alice follows Bob, he can post here:
+        client.publish(&bob, &item).await.unwrap();
+        let feed = client.feed_items(&alice.user, None).await.unwrap();
+        assert_eq!(4, feed.get_items().len());
+    });
+}
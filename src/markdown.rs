@@ -1,3 +1,5 @@
+use pulldown_cmark::{Event, LinkType, Tag};
+
 pub(crate) trait ToHTML {
     /// Convert this markdown to a safe subset of HTML.
     fn md_to_html(&self) -> String;
@@ -6,18 +8,179 @@ pub(crate) trait ToHTML {
 impl ToHTML for str {
     fn md_to_html(&self) -> String {
         let parser = pulldown_cmark::Parser::new(self);
-        use pulldown_cmark::Event::*; 
-
-        // TODO: Fix unsafe links like javascript:. see commonmark JS library.
-        let parser = parser.map(|event| match event {
-            Html(value) => Code(value),
-            InlineHtml(value) => Text(value),
-            x => x,
-        });
-
         let mut html = String::new();
-        pulldown_cmark::html::push_html(&mut html, parser);
+        pulldown_cmark::html::push_html(&mut html, Sanitizer::new(parser));
         html
     }
 }
 
+/// URL schemes that may be used in links.
+const LINK_SCHEMES: &[&str] = &["http", "https", "mailto"];
+
+/// URL schemes that may be used for images.
+/// Notably, this excludes `data:`, which can hide all sorts of things.
+const IMAGE_SCHEMES: &[&str] = &["http", "https"];
+
+/// What we decided to do with a link.
+enum LinkAction {
+    /// Render it as usual.
+    Keep,
+    /// Render it with `rel="nofollow noopener"`.
+    External,
+    /// Render only its contents.
+    Drop,
+}
+
+/// Filters markdown events so that they only produce safe HTML.
+///
+/// * Raw HTML is displayed as text.
+/// * Links and images may only use whitelisted URL schemes. Those that don't
+///   are replaced with their text.
+/// * Links to other sites get `rel="nofollow noopener"`.
+struct Sanitizer<'a, I: Iterator<Item=Event<'a>>> {
+    events: I,
+
+    /// Actions for the links we're currently inside of.
+    links: Vec<LinkAction>,
+
+    /// Whether we kept each image we're currently inside of.
+    images: Vec<bool>,
+}
+
+impl<'a, I: Iterator<Item=Event<'a>>> Sanitizer<'a, I> {
+    fn new(events: I) -> Self {
+        Sanitizer {
+            events,
+            links: vec![],
+            images: vec![],
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item=Event<'a>>> Iterator for Sanitizer<'a, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        use Event::*;
+
+        loop {
+            let event = match self.events.next()? {
+                Html(value) => Code(value),
+                InlineHtml(value) => Text(value),
+
+                // pulldown_cmark validates email autolinks, and adds its own `mailto:`.
+                event @ Start(Tag::Link(LinkType::Email, _, _)) => {
+                    self.links.push(LinkAction::Keep);
+                    event
+                },
+                Start(Tag::Link(link_type, url, title)) => {
+                    match check_url(&url, LINK_SCHEMES) {
+                        UrlKind::Unsafe => {
+                            self.links.push(LinkAction::Drop);
+                            continue;
+                        },
+                        UrlKind::Relative => {
+                            self.links.push(LinkAction::Keep);
+                            Start(Tag::Link(link_type, url, title))
+                        },
+                        UrlKind::External => {
+                            self.links.push(LinkAction::External);
+                            let mut html = format!("<a href=\"{}\"", escape_attribute(&url));
+                            if !title.is_empty() {
+                                html.push_str(&format!(" title=\"{}\"", escape_attribute(&title)));
+                            }
+                            html.push_str(" rel=\"nofollow noopener\">");
+                            Html(html.into())
+                        },
+                    }
+                },
+                End(Tag::Link(link_type, url, title)) => {
+                    match self.links.pop() {
+                        Some(LinkAction::Drop) => continue,
+                        Some(LinkAction::External) => Html("</a>".into()),
+                        Some(LinkAction::Keep) | None => End(Tag::Link(link_type, url, title)),
+                    }
+                },
+
+                Start(Tag::Image(link_type, url, title)) => {
+                    let keep = check_url(&url, IMAGE_SCHEMES) != UrlKind::Unsafe;
+                    self.images.push(keep);
+                    if !keep {
+                        // The image's alt text will be rendered as plain text.
+                        continue;
+                    }
+                    Start(Tag::Image(link_type, url, title))
+                },
+                End(Tag::Image(link_type, url, title)) => {
+                    if !self.images.pop().unwrap_or(true) {
+                        continue;
+                    }
+                    End(Tag::Image(link_type, url, title))
+                },
+
+                event => event,
+            };
+
+            return Some(event);
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+enum UrlKind {
+    /// A URL on this site. (No scheme or host.)
+    Relative,
+    /// A URL with an allowed scheme, or a different host.
+    External,
+    /// A URL we refuse to render.
+    Unsafe,
+}
+
+fn check_url(url: &str, schemes: &[&str]) -> UrlKind {
+    // Browsers ignore leading whitespace/control characters, and tabs and
+    // newlines anywhere, so `java\tscript:` is still `javascript:`.
+    let url: String = url
+        .trim_start_matches(|c: char| c <= ' ')
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect();
+
+    let scheme_end = url.find([':', '/', '\\', '?', '#']);
+    if let Some(index) = scheme_end {
+        if url[index..].starts_with(':') {
+            let scheme = url[..index].to_ascii_lowercase();
+            return if schemes.contains(&scheme.as_str()) {
+                UrlKind::External
+            } else {
+                UrlKind::Unsafe
+            };
+        }
+    }
+
+    // Browsers treat "\" like "/", so "\\example.com" is a protocol-relative URL:
+    let slashes = url.chars().take(2).filter(|c| matches!(c, '/' | '\\')).count();
+    if slashes == 2 {
+        return UrlKind::External;
+    }
+
+    UrlKind::Relative
+}
+
+/// Escape a value for use inside a double-quoted HTML attribute.
+fn escape_attribute(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,137 @@
+use super::*;
+
+/// Markdown that tries to sneak script into the rendered HTML.
+const XSS_CORPUS: &[&str] = &[
+    "[click](javascript:alert(1))",
+    "[click](JaVaScRiPt:alert(1))",
+    "[click]( javascript:alert(1))",
+    "[click](<java\tscript:alert(1)>)",
+    "[click](java&#x09;script:alert(1))",
+    "[click](&#106;avascript:alert(1))",
+    "[click](&#x6A;avascript&#x3A;alert(1))",
+    "[click](javascript&colon;alert(1))",
+    "[click](vbscript:msgbox(1))",
+    "[click](data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==)",
+    "[click](DATA:text/html,<script>alert(1)</script>)",
+    "<javascript:alert(1)>",
+    "[click][ref]\n\n[ref]: javascript:alert(1)",
+    "[click]\n\n[click]: javascript:alert(1)",
+    "![img](javascript:alert(1))",
+    "![img](data:image/svg+xml;base64,PHN2ZyBvbmxvYWQ9YWxlcnQoMSk+)",
+    "![img](data:image/png;base64,iVBORw0KGgo=)",
+    "[![img](javascript:alert(1))](javascript:alert(2))",
+    "[click](http://example.com \"title\\\" onmouseover=\\\"alert(1)\")",
+    "[click](http://example.com/\"onmouseover=\"alert(1))",
+    "<script>alert(1)</script>",
+    "<img src=x onerror=alert(1)>",
+    "text <a href=\"javascript:alert(1)\">click</a> text",
+    "<iframe src=\"javascript:alert(1)\"></iframe>",
+];
+
+/// Things that should never appear in HTML rendered from user content.
+const FORBIDDEN: &[&str] = &[
+    "<script",
+    "<img src=x",
+    "<iframe",
+    "<a href=\"javascript",
+    "href=\"javascript",
+    "href=\"java",
+    "href=\"vbscript",
+    "href=\"data:",
+    "href=\"DATA:",
+    "src=\"javascript",
+    "src=\"data:",
+    "\" onmouseover=\"",
+    "\"onmouseover=\"",
+];
+
+#[test]
+fn xss_corpus() {
+    for markdown in XSS_CORPUS {
+        let html = markdown.md_to_html();
+        let lower = html.to_lowercase();
+        for forbidden in FORBIDDEN {
+            assert!(
+                !lower.contains(&forbidden.to_lowercase()),
+                "Rendering {:?} produced {:?}, which contains {:?}", markdown, html, forbidden
+            );
+        }
+    }
+}
+
+#[test]
+fn unsafe_links_keep_text() {
+    assert_eq!("<p>click</p>\n", "[click](javascript:alert(1))".md_to_html());
+    assert_eq!("<p>an image</p>\n", "![an image](data:image/png;base64,iVBORw0KGgo=)".md_to_html());
+}
+
+#[test]
+fn safe_links() {
+    // Relative links are left alone:
+    assert_eq!(
+        "<p><a href=\"files/photo.jpg\">photo</a></p>\n",
+        "[photo](files/photo.jpg)".md_to_html()
+    );
+    assert_eq!(
+        "<p><a href=\"/u/abc/\">me</a></p>\n",
+        "[me](/u/abc/)".md_to_html()
+    );
+    assert_eq!(
+        "<p><img src=\"files/photo.jpg\" alt=\"photo\" /></p>\n",
+        "![photo](files/photo.jpg)".md_to_html()
+    );
+
+    // External links aren't endorsed, and can't reach back to us:
+    assert_eq!(
+        "<p><a href=\"https://example.com/?a=1&amp;b=2\" rel=\"nofollow noopener\">site</a></p>\n",
+        "[site](https://example.com/?a=1&b=2)".md_to_html()
+    );
+    assert_eq!(
+        "<p><a href=\"//example.com/\" title=\"A &quot;site&quot;\" rel=\"nofollow noopener\">site</a></p>\n",
+        "[site](//example.com/ \"A \\\"site\\\"\")".md_to_html()
+    );
+    assert_eq!(
+        "<p><a href=\"http://example.com\" rel=\"nofollow noopener\">http://example.com</a></p>\n",
+        "<http://example.com>".md_to_html()
+    );
+    assert_eq!(
+        "<p><a href=\"mailto:me@example.com\" rel=\"nofollow noopener\">mail me</a></p>\n",
+        "[mail me](mailto:me@example.com)".md_to_html()
+    );
+    assert_eq!(
+        "<p><a href=\"mailto:me@example.com\">me@example.com</a></p>\n",
+        "<me@example.com>".md_to_html()
+    );
+    assert_eq!(
+        "<p><img src=\"https://example.com/a.png\" alt=\"a\" /></p>\n",
+        "![a](https://example.com/a.png)".md_to_html()
+    );
+}
+
+#[test]
+fn url_kinds() {
+    let cases = &[
+        ("", UrlKind::Relative),
+        ("foo", UrlKind::Relative),
+        ("foo/bar:baz", UrlKind::Relative),
+        ("?q=a:b", UrlKind::Relative),
+        ("#a:b", UrlKind::Relative),
+        ("/", UrlKind::Relative),
+        ("//example.com", UrlKind::External),
+        ("\\\\example.com", UrlKind::External),
+        ("/\\example.com", UrlKind::External),
+        ("HTTPS://example.com", UrlKind::External),
+        ("mailto:a@b.c", UrlKind::External),
+        ("javascript:x", UrlKind::Unsafe),
+        ("\u{1}javascript:x", UrlKind::Unsafe),
+        ("jav\nascript:x", UrlKind::Unsafe),
+        ("data:text/plain,hi", UrlKind::Unsafe),
+        ("ftp://example.com", UrlKind::Unsafe),
+    ];
+
+    for (url, kind) in cases {
+        assert_eq!(*kind, check_url(url, LINK_SCHEMES), "URL: {:?}", url);
+    }
+
+    assert_eq!(UrlKind::Unsafe, check_url("mailto:a@b.c", IMAGE_SCHEMES));
+}
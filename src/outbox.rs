@@ -0,0 +1,183 @@
+//! Sends items to the other servers listed in their authors' profiles.
+//!
+//! When we accept an item from a server user, we queue it for each server in
+//! their latest profile. We then PUT it to those servers, retrying with
+//! exponential backoff, until it's accepted or we give up.
+//!
+//! `serve` sends items from a single task, so that it doesn't send the same
+//! item twice at once. Uploads [`Wakeup`] that task instead of sending items
+//! themselves.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use failure::Error;
+use futures::StreamExt as _;
+use futures::channel::mpsc;
+use futures::future::{Either, select};
+
+use crate::backend::{self, Backend, OutboxEntry, Signature, Timestamp, UserID};
+use crate::backend::nonblocking::AsyncBackend;
+use crate::client::Client;
+use crate::sync::{backoff_ms, server_urls, user_profile};
+
+/// Give up on sending an item after this many failed attempts. (About 2 days.)
+pub(crate) const MAX_ATTEMPTS: u32 = 12;
+
+/// How often `serve` checks for items that are due to be retried.
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Queue an item to be sent to the other servers in its author's profile.
+/// `own_url` is this server's public URL, if known, which we skip.
+/// Returns how many servers it was queued for.
+pub(crate) fn enqueue(backend: &dyn Backend, user: &UserID, signature: &Signature, own_url: Option<&str>) -> Result<usize, Error> {
+    let profile = match user_profile(backend, user)? {
+        Some(profile) => profile,
+        None => return Ok(0),
+    };
+
+    let own_url = own_url.map(|url| url.trim_end_matches('/'));
+    let mut queued = 0;
+    for url in server_urls(&profile) {
+        if Some(url.as_str()) == own_url { continue; }
+        backend.add_outbox_entry(&url, user, signature)?;
+        queued += 1;
+    }
+
+    Ok(queued)
+}
+
+/// The result of trying to send one item.
+pub(crate) struct Delivery {
+    /// The entry, updated after this attempt.
+    pub entry: OutboxEntry,
+
+    /// Why the attempt failed, if it did.
+    pub error: Option<String>,
+}
+
+/// Try to send all items that are due.
+pub(crate) async fn deliver(backend: &AsyncBackend) -> Result<Vec<Delivery>, Error> {
+    let due = backend.run(|backend| {
+        let mut due = vec![];
+        backend.outbox_entries(Some(Timestamp::now()), &mut |entry| {
+            due.push(entry);
+            Ok(true)
+        })?;
+        Ok(due)
+    }).await?;
+    if due.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut clients: HashMap<String, Client> = HashMap::new();
+
+    // If a server fails, don't hammer it with the rest of its items.
+    // They fail (and back off) along with the first one.
+    let mut failed_servers: Vec<(String, String)> = vec![];
+
+    let mut deliveries = vec![];
+    for mut entry in due {
+        let earlier_failure = failed_servers.iter()
+            .find(|(url, _)| *url == entry.url)
+            .map(|(_, error)| error.clone());
+        let result = match earlier_failure {
+            Some(error) => Err(error),
+            None => {
+                let client = clients.entry(entry.url.clone()).or_insert_with(|| Client::new(&entry.url));
+                send(client, backend, &entry).await.map_err(|e| e.to_string())
+            },
+        };
+
+        let error = match result {
+            Ok(()) => {
+                let sent = entry.clone();
+                backend.run(move |backend| backend.remove_outbox_entry(&sent.url, &sent.user, &sent.signature)).await?;
+                None
+            },
+            Err(error) => {
+                entry.attempts += 1;
+                entry.next_attempt = if entry.attempts >= MAX_ATTEMPTS {
+                    None
+                } else {
+                    Some(Timestamp{ unix_utc_ms: Timestamp::now().unix_utc_ms + backoff_ms(entry.attempts) })
+                };
+                entry.last_error = Some(error.clone());
+                let failed = entry.clone();
+                backend.run(move |backend| match backend.update_outbox_entry(&failed) {
+                    // Something else (ex: `feoblog outbox retry`) already sent it:
+                    Err(backend::Error::NotFound(_)) => Ok(()),
+                    result => result,
+                }).await?;
+                if !failed_servers.iter().any(|(url, _)| *url == entry.url) {
+                    failed_servers.push((entry.url.clone(), error.clone()));
+                }
+                Some(error)
+            },
+        };
+        deliveries.push(Delivery{ entry, error });
+    }
+
+    Ok(deliveries)
+}
+
+/// Wakes up [`deliver_periodically`] to send newly queued items.
+#[derive(Clone)]
+pub(crate) struct Wakeup(mpsc::UnboundedSender<()>);
+
+impl Wakeup {
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<()>) {
+        let (sender, receiver) = mpsc::unbounded();
+        (Wakeup(sender), receiver)
+    }
+
+    pub fn wake(&self) {
+        // Nothing to wake if the delivery task isn't running. (ex: in tests)
+        let _ = self.0.unbounded_send(());
+    }
+}
+
+/// Send queued items every so often, and when woken up, for as long as the
+/// server runs.
+pub(crate) async fn deliver_periodically(backend: AsyncBackend, mut wakeups: mpsc::UnboundedReceiver<()>) {
+    loop {
+        match deliver(&backend).await {
+            Err(error) => println!("Outbox error: {}", error),
+            Ok(deliveries) => print_failures(&deliveries),
+        }
+
+        let delay = actix_web::rt::time::delay_for(RETRY_INTERVAL);
+        if let Either::Left((None, _)) = select(wakeups.next(), delay).await {
+            // Every Wakeup was dropped, so the server has stopped.
+            return;
+        }
+        // That delivery sends everything that's due, so one wakeup will do:
+        while wakeups.try_recv().is_ok() {}
+    }
+}
+
+pub(crate) fn print_failures(deliveries: &[Delivery]) {
+    for delivery in deliveries {
+        if let Some(error) = &delivery.error {
+            let entry = &delivery.entry;
+            println!("Outbox: Couldn't send {} to {}: {}", entry.signature.to_base58(), entry.url, error);
+        }
+    }
+}
+
+/// PUT one item to a server.
+async fn send(client: &Client, backend: &AsyncBackend, entry: &OutboxEntry) -> Result<(), Error> {
+    let (user, signature) = (entry.user.clone(), entry.signature.clone());
+    let row = match backend.run(move |backend| backend.user_item(&user, &signature)).await? {
+        Some(row) => row,
+        // Nothing left to send:
+        None => return Ok(()),
+    };
+
+    // Either way (Created, or AlreadyExists) the server has it now:
+    client.put_item(&entry.user, &entry.signature, row.item_bytes).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
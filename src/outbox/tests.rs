@@ -0,0 +1,103 @@
+use super::*;
+
+use actix_web::App;
+
+use crate::backend::Factory;
+use crate::client::Keys;
+use crate::server::routes;
+use crate::testing::{TempDB, add_server_user, app_data, async_backend, post, profile, save, sign_row};
+
+fn entries(factory: &dyn Factory) -> Vec<OutboxEntry> {
+    let mut entries = vec![];
+    factory.open().unwrap().outbox_entries(None, &mut |entry| {
+        entries.push(entry);
+        Ok(true)
+    }).unwrap();
+    entries
+}
+
+#[test]
+fn deliver_to_other_servers() {
+    actix_web::rt::System::new("test").block_on(async {
+        let remote_db = TempDB::new("outbox-remote");
+        let remote = remote_db.factory();
+        let server_factory = remote.clone();
+        let server = actix_web::test::start(move || {
+            App::new()
+                .data(app_data(server_factory.clone()))
+                .configure(routes)
+        });
+        let remote_url = format!("http://{}", server.addr());
+
+        let local_db = TempDB::new("outbox-local");
+        let local = local_db.factory();
+        let own_url = "http://local.example.com";
+
+        let alice = Keys::generate();
+        add_server_user(&local, &alice.user);
+        add_server_user(&remote, &alice.user);
+        save(&local, &sign_row(&alice, &profile(1, &[&format!("{}/", own_url), &remote_url], &[]), 1000));
+
+        let row = sign_row(&alice, &post(100, "Hello"), 1000);
+        save(&local, &row);
+        let queued = enqueue(local.open().unwrap().as_ref(), &alice.user, &row.signature, Some(&format!("{}/", own_url))).unwrap();
+        assert_eq!(1, queued, "We shouldn't send items to ourselves");
+
+        let deliveries = deliver(&async_backend(local.clone())).await.unwrap();
+        assert_eq!(1, deliveries.len());
+        assert_eq!(None, deliveries[0].error);
+        assert!(remote.open().unwrap().user_item_exists(&alice.user, &row.signature).unwrap());
+        assert!(entries(&local).is_empty());
+
+        // Items the other server already has are done too:
+        enqueue(local.open().unwrap().as_ref(), &alice.user, &row.signature, Some(own_url)).unwrap();
+        let deliveries = deliver(&async_backend(local.clone())).await.unwrap();
+        assert_eq!(None, deliveries[0].error);
+        assert!(entries(&local).is_empty());
+    });
+}
+
+#[test]
+fn failures_back_off_then_give_up() {
+    actix_web::rt::System::new("test").block_on(async {
+        let db = TempDB::new("outbox-backoff");
+        let factory = db.factory();
+
+        let bob = Keys::generate();
+        // Nothing should be listening on port 1:
+        let url = "http://127.0.0.1:1";
+        save(&factory, &sign_row(&bob, &profile(1, &[url], &[]), 1000));
+        let first = sign_row(&bob, &post(100, "one"), 1000);
+        let second = sign_row(&bob, &post(200, "two"), 1000);
+        for row in &[&first, &second] {
+            save(&factory, row);
+            enqueue(factory.open().unwrap().as_ref(), &bob.user, &row.signature, None).unwrap();
+        }
+
+        let deliveries = deliver(&async_backend(factory.clone())).await.unwrap();
+        assert_eq!(2, deliveries.len());
+        assert!(deliveries.iter().all(|d| d.error.is_some()));
+        for entry in entries(&factory) {
+            assert_eq!(1, entry.attempts);
+            assert!(entry.next_attempt.unwrap().unix_utc_ms > Timestamp::now().unix_utc_ms);
+            assert!(entry.last_error.is_some());
+        }
+
+        // Not due yet:
+        assert!(deliver(&async_backend(factory.clone())).await.unwrap().is_empty());
+
+        let backend = factory.open().unwrap();
+        for mut entry in entries(&factory) {
+            entry.attempts = MAX_ATTEMPTS - 1;
+            entry.next_attempt = Some(Timestamp::now());
+            backend.update_outbox_entry(&entry).unwrap();
+        }
+        deliver(&async_backend(factory.clone())).await.unwrap();
+        let entries = entries(&factory);
+        assert_eq!(2, entries.len());
+        for entry in entries {
+            assert_eq!(MAX_ATTEMPTS, entry.attempts);
+            assert_eq!(None, entry.next_attempt.map(|t| t.unix_utc_ms));
+        }
+    });
+}
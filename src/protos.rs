@@ -1,10 +1,24 @@
 use std::borrow::Cow; 
+use std::collections::HashSet;
 use std::error::Error;
 use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use protobuf::Message;
 
 mod feoblog;
 pub use feoblog::*;
 
+/// Titles longer than this (in bytes) are rejected.
+pub(crate) const MAX_TITLE_BYTES: usize = 256;
+
+/// Items' UTC offsets must be within this many minutes. (24 hours)
+const MAX_UTC_OFFSET_MINUTES: i32 = 24 * 60;
+
+/// By default, how far in the future we'll accept an Item's timestamp.
+/// Clients' clocks may not quite agree with ours.
+pub(crate) const DEFAULT_MAX_CLOCK_DRIFT: Duration = Duration::from_secs(5 * 60);
+
 /// Since proto3 does not allow specifying required fields, we must do that
 /// in our own validation here.
 pub(crate) trait ProtoValid {
@@ -33,7 +47,18 @@ impl ProtoValid for Item {
             );
         }
 
-        // TODO: Validations for specific item types.
+        // (abs() would overflow for i32::MIN.)
+        if !(-MAX_UTC_OFFSET_MINUTES..=MAX_UTC_OFFSET_MINUTES).contains(&self.utc_offset_minutes) {
+            return Some(
+                format!("utc_offset_minutes must be within +/- {}", MAX_UTC_OFFSET_MINUTES).into()
+            );
+        }
+
+        let err = unknown_fields_error(self, "Item");
+        if err.is_some() {
+            return err;
+        }
+
         if self.has_profile() {
             let err = self.get_profile().get_error();
             if err.is_some() {
@@ -41,16 +66,129 @@ impl ProtoValid for Item {
             }
         }
 
+        if self.has_post() {
+            let err = self.get_post().get_error();
+            if err.is_some() {
+                return err;
+            }
+        }
+
+        None
+    }
+}
+
+impl ProtoValid for Post {
+    fn get_error(&self) -> Option<Cow<'static, str>> {
+        let err = unknown_fields_error(self, "Post");
+        if err.is_some() {
+            return err;
+        }
+
+        if self.get_title().len() > MAX_TITLE_BYTES {
+            return Some(format!("Post.title must be <= {} bytes", MAX_TITLE_BYTES).into());
+        }
+
+        let mut names = HashSet::new();
+        for attachment in self.get_attachments() {
+            let err = attachment.get_error();
+            if err.is_some() {
+                return err;
+            }
+
+            if !names.insert(attachment.get_name()) {
+                return Some(format!("Duplicate attachment name: {}", attachment.get_name()).into());
+            }
+        }
+
+        if self.has_reply_to() {
+            let err = self.get_reply_to().get_error();
+            if err.is_some() {
+                return err;
+            }
+        }
+
+        None
+    }
+}
+
+impl ProtoValid for ItemRef {
+    fn get_error(&self) -> Option<Cow<'static, str>> {
+        let err = unknown_fields_error(self, "ItemRef")
+            .or_else(|| unknown_fields_error(self.get_user_id(), "ItemRef.user_id"))
+            .or_else(|| unknown_fields_error(self.get_signature(), "ItemRef.signature"));
+        if err.is_some() {
+            return err;
+        }
+
+        if self.get_user_id().get_bytes().len() != 32 {
+            return Some("ItemRef.user_id must be 32 bytes".into());
+        }
+        if self.get_signature().get_bytes().len() != 64 {
+            return Some("ItemRef.signature must be 64 bytes".into());
+        }
+
+        None
+    }
+}
+
+impl ProtoValid for Attachment {
+    fn get_error(&self) -> Option<Cow<'static, str>> {
+        let err = unknown_fields_error(self, "Attachment");
+        if err.is_some() {
+            return err;
+        }
+
+        let name = self.get_name();
+        if name.is_empty() {
+            return Some("Attachment.name is required".into());
+        }
+        if name.starts_with('.') || name.contains('/') {
+            return Some("Attachment.name must not start with \".\" or contain \"/\"".into());
+        }
+
+        match multihash::decode(self.get_hash()) {
+            Err(_) => return Some("Attachment.hash must be a valid multihash".into()),
+            Ok(hash) if hash.alg == multihash::Hash::SHA1 => {
+                return Some("Attachment.hash must not use SHA-1".into());
+            },
+            Ok(hash) => {
+                // Make sure we can verify files when they're uploaded:
+                if multihash::encode(hash.alg, &[]).is_err() {
+                    return Some(format!("Unsupported Attachment.hash type: {}", hash.alg.name()).into());
+                }
+            },
+        }
+
         None
     }
 }
 
 impl ProtoValid for Profile {
     fn get_error(&self) -> Option<Cow<'static, str>> {
+        let err = unknown_fields_error(self, "Profile");
+        if err.is_some() {
+            return err;
+        }
+
+        for server in self.get_servers() {
+            let err = server.get_error();
+            if err.is_some() {
+                return err;
+            }
+        }
 
+        let mut followed = HashSet::new();
         for follow in self.get_follows() {
-            if follow.get_user().get_bytes().len() != 32 {
-                return Some("UserID.bytes must be 32 bytes".into())
+            let err = follow.get_error();
+            if err.is_some() {
+                return err;
+            }
+
+            if !followed.insert(follow.get_user().get_bytes()) {
+                return Some(format!(
+                    "Duplicate follow: {}",
+                    bs58::encode(follow.get_user().get_bytes()).into_string(),
+                ).into());
             }
         }
 
@@ -58,6 +196,75 @@ impl ProtoValid for Profile {
     }
 }
 
+impl ProtoValid for Server {
+    fn get_error(&self) -> Option<Cow<'static, str>> {
+        let err = unknown_fields_error(self, "Server");
+        if err.is_some() {
+            return err;
+        }
+
+        let url = self.get_url();
+        let host = match url.strip_prefix("https://").or_else(|| url.strip_prefix("http://")) {
+            Some(host) => host,
+            None => return Some(format!("Server.url must start with https:// or http://: {:?}", url).into()),
+        };
+
+        // Subpaths aren't supported, but a trailing slash is OK:
+        let host = host.strip_suffix('/').unwrap_or(host);
+        if host.is_empty() || host.contains(|c: char| c == '/' || c == '?' || c == '#' || c.is_whitespace()) {
+            return Some(format!("Server.url must be a server's base URL, with no path: {:?}", url).into());
+        }
+
+        None
+    }
+}
+
+impl ProtoValid for Follow {
+    fn get_error(&self) -> Option<Cow<'static, str>> {
+        let err = unknown_fields_error(self, "Follow")
+            .or_else(|| unknown_fields_error(self.get_user(), "Follow.user"));
+        if err.is_some() {
+            return err;
+        }
+
+        if self.get_user().get_bytes().len() != 32 {
+            return Some("UserID.bytes must be 32 bytes".into())
+        }
+
+        None
+    }
+}
+
+/// We can't validate fields we don't know about, so don't accept them.
+/// (ex: from a newer version of feoblog.proto.)
+fn unknown_fields_error(message: &dyn Message, name: &str) -> Option<Cow<'static, str>> {
+    let fields: Vec<String> = message.get_unknown_fields().iter()
+        .map(|(number, _)| number.to_string())
+        .collect();
+    if fields.is_empty() {
+        return None;
+    }
+    Some(format!("{} has unknown fields: {}", name, fields.join(", ")).into())
+}
+
+/// Servers shouldn't accept Items from the future. Allows timestamps up to
+/// `max_clock_drift` after `now_ms_utc`.
+///
+/// This isn't part of [`ProtoValid`], because it depends on when the Item is
+/// checked. (ex: Stored items were checked when they were received.)
+pub(crate) fn validate_timestamp(item: &Item, now_ms_utc: i64, max_clock_drift: Duration) -> Result<(), ValidationError> {
+    let latest = now_ms_utc.saturating_add(max_clock_drift.as_millis() as i64);
+    if item.get_timestamp_ms_utc() > latest {
+        return Err(ValidationError{
+            message: format!(
+                "Timestamp is {} ms in the future",
+                item.get_timestamp_ms_utc() - now_ms_utc,
+            ).into(),
+        });
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 pub(crate) struct ValidationError {
     message: Cow<'static, str>,
@@ -69,4 +276,7 @@ impl Display for ValidationError {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> { 
         write!(f, "Protobuf validation error: {}", self.message)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,148 @@
+use super::*;
+
+const NOW: i64 = 1_600_000_000_000;
+
+fn post() -> Item {
+    let mut item = Item::new();
+    item.set_timestamp_ms_utc(NOW);
+    item.mut_post().set_title("Hello".into());
+    item
+}
+
+fn profile() -> Item {
+    let mut item = Item::new();
+    item.set_timestamp_ms_utc(NOW);
+    item.mut_profile().set_display_name("Alice".into());
+    item
+}
+
+fn follow(n: u8) -> Follow {
+    let mut follow = Follow::new();
+    follow.mut_user().set_bytes(vec![n; 32]);
+    follow
+}
+
+fn server(url: &str) -> Server {
+    let mut server = Server::new();
+    server.set_url(url.into());
+    server
+}
+
+fn reply_to(item: &mut Item) -> &mut ItemRef {
+    let reply_to = item.mut_post().mut_reply_to();
+    reply_to.mut_user_id().set_bytes(vec![1; 32]);
+    reply_to.mut_signature().set_bytes(vec![1; 64]);
+    reply_to
+}
+
+/// (description, item, expected error. None if it should be valid.)
+type Case = (&'static str, Item, Option<&'static str>);
+
+fn case(description: &'static str, mut item: Item, change: impl FnOnce(&mut Item), error: Option<&'static str>) -> Case {
+    change(&mut item);
+    (description, item, error)
+}
+
+#[test]
+fn item_validation() {
+    let cases: Vec<Case> = vec![
+        case("post", post(), |_| {}, None),
+        case("profile", profile(), |_| {}, None),
+        case("no timestamp", post(), |i| i.set_timestamp_ms_utc(0), Some("Timestamp is required")),
+
+        case("offset -24h", post(), |i| i.set_utc_offset_minutes(-24 * 60), None),
+        case("offset +24h", post(), |i| i.set_utc_offset_minutes(24 * 60), None),
+        case("offset < -24h", post(), |i| i.set_utc_offset_minutes(-24 * 60 - 1), Some("utc_offset_minutes")),
+        case("offset > +24h", post(), |i| i.set_utc_offset_minutes(24 * 60 + 1), Some("utc_offset_minutes")),
+        case("offset i32::MIN", post(), |i| i.set_utc_offset_minutes(i32::MIN), Some("utc_offset_minutes")),
+        case("offset i32::MAX", post(), |i| i.set_utc_offset_minutes(i32::MAX), Some("utc_offset_minutes")),
+
+        case("256 byte title", post(), |i| i.mut_post().set_title("é".repeat(128)), None),
+        case("257 byte title", post(), |i| i.mut_post().set_title(format!("{}a", "é".repeat(128))), Some("Post.title")),
+
+        case("server", profile(), |i| i.mut_profile().mut_servers().push(server("https://feo.example.com")), None),
+        case("server with slash", profile(), |i| i.mut_profile().mut_servers().push(server("https://feo.example.com/")), None),
+        case("server with port", profile(), |i| i.mut_profile().mut_servers().push(server("http://feo.example.com:8080/")), None),
+        case("server with path", profile(), |i| i.mut_profile().mut_servers().push(server("https://feo.example.com/some/subpath/")), Some("no path")),
+        case("server with query", profile(), |i| i.mut_profile().mut_servers().push(server("https://feo.example.com?x=1")), Some("no path")),
+        case("server without host", profile(), |i| i.mut_profile().mut_servers().push(server("https://")), Some("no path")),
+        case("server with space", profile(), |i| i.mut_profile().mut_servers().push(server("https://feo.example.com ")), Some("no path")),
+        case("server without scheme", profile(), |i| i.mut_profile().mut_servers().push(server("feo.example.com")), Some("https://")),
+        case("ftp server", profile(), |i| i.mut_profile().mut_servers().push(server("ftp://feo.example.com")), Some("https://")),
+        case("empty server", profile(), |i| i.mut_profile().mut_servers().push(server("")), Some("https://")),
+
+        case("follows", profile(), |i| i.mut_profile().mut_follows().extend(vec![follow(1), follow(2)]), None),
+        case("duplicate follows", profile(), |i| i.mut_profile().mut_follows().extend(vec![follow(1), follow(2), follow(1)]), Some("Duplicate follow")),
+        case("short follow", profile(), |i| i.mut_profile().mut_follows().push(Follow::new()), Some("32 bytes")),
+
+        case("reply", post(), |i| { reply_to(i); }, None),
+        case("short reply_to", post(), |i| reply_to(i).mut_signature().set_bytes(vec![1; 63]), Some("64 bytes")),
+
+        case("unknown Item field", post(), |i| i.mut_unknown_fields().add_varint(100, 1), Some("Item has unknown fields: 100")),
+        case("unknown Post field", post(), |i| i.mut_post().mut_unknown_fields().add_varint(100, 1), Some("Post has unknown")),
+        case("unknown Profile field", profile(), |i| i.mut_profile().mut_unknown_fields().add_varint(100, 1), Some("Profile has unknown")),
+        case("unknown Follow field", profile(), |i| {
+            let mut follow = follow(1);
+            follow.mut_unknown_fields().add_varint(100, 1);
+            i.mut_profile().mut_follows().push(follow);
+        }, Some("Follow has unknown")),
+        case("unknown Follow.user field", profile(), |i| {
+            let mut follow = follow(1);
+            follow.mut_user().mut_unknown_fields().add_varint(100, 1);
+            i.mut_profile().mut_follows().push(follow);
+        }, Some("Follow.user has unknown")),
+        case("unknown Server field", profile(), |i| {
+            let mut server = server("https://feo.example.com");
+            server.mut_unknown_fields().add_varint(100, 1);
+            i.mut_profile().mut_servers().push(server);
+        }, Some("Server has unknown")),
+        case("unknown ItemRef field", post(), |i| reply_to(i).mut_unknown_fields().add_varint(100, 1), Some("ItemRef has unknown")),
+        case("unknown ItemRef.signature field", post(), |i| {
+            reply_to(i).mut_signature().mut_unknown_fields().add_varint(100, 1);
+        }, Some("ItemRef.signature has unknown")),
+        case("unknown Attachment field", post(), |i| {
+            let mut attachment = Attachment::new();
+            attachment.set_name("a.txt".into());
+            attachment.set_hash(multihash::encode(multihash::Hash::SHA2512, b"").unwrap());
+            attachment.mut_unknown_fields().add_varint(100, 1);
+            i.mut_post().mut_attachments().push(attachment);
+        }, Some("Attachment has unknown")),
+    ];
+
+    for (description, item, error) in cases {
+        // Unknown fields must survive a round trip through bytes to be useful:
+        let bytes = item.write_to_bytes().unwrap();
+        let item = Item::parse_from_bytes(&bytes).unwrap();
+
+        match (item.validate(), error) {
+            (Ok(()), None) => {},
+            (Ok(()), Some(expected)) => panic!("{}: expected error {:?}", description, expected),
+            (Err(error), None) => panic!("{}: unexpected error: {}", description, error),
+            (Err(error), Some(expected)) => assert!(
+                error.to_string().contains(expected),
+                "{}: expected error {:?}, got: {}", description, expected, error,
+            ),
+        }
+    }
+}
+
+#[test]
+fn timestamp_validation() {
+    let second = Duration::from_secs(1);
+    let cases = vec![
+        // (description, timestamp, max_clock_drift, valid)
+        ("past", NOW - 1000, Duration::from_secs(0), true),
+        ("now", NOW, Duration::from_secs(0), true),
+        ("future, without drift", NOW + 1, Duration::from_secs(0), false),
+        ("within drift", NOW + 1000, second, true),
+        ("beyond drift", NOW + 1001, second, false),
+        ("within default drift", NOW + 60_000, DEFAULT_MAX_CLOCK_DRIFT, true),
+        ("far future", i64::MAX, DEFAULT_MAX_CLOCK_DRIFT, false),
+    ];
+
+    for (description, timestamp, max_clock_drift, valid) in cases {
+        let mut item = post();
+        item.set_timestamp_ms_utc(timestamp);
+        assert_eq!(valid, validate_timestamp(&item, NOW, max_clock_drift).is_ok(), "{}", description);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{borrow::Cow, fmt, fmt::Write, marker::PhantomData, net::TcpListener};
+use std::{borrow::Cow, fmt, fmt::Write, marker::PhantomData, net::TcpListener, sync::Arc};
 
 // TODO: This module is getting long.
 // Split it out into parts:
@@ -26,9 +26,9 @@ use actix_web::web::{
 };
 use actix_web::{App, HttpServer, Responder};
 use askama::Template;
-use failure::{bail, ResultExt, format_err};
+use failure::{bail, ResultExt};
 use rust_embed::RustEmbed;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use actix_web::http::StatusCode;
 use async_trait::async_trait;
@@ -36,29 +36,66 @@ use async_trait::async_trait;
 use protobuf::Message;
 
 use crate::{ServeCommand, backend::ItemDisplayRow, protos::{ItemList, ItemListEntry, ItemType, Item_oneof_item_type}};
-use crate::backend::{self, Backend, Factory, UserID, Signature, ItemRow, Timestamp};
-use crate::protos::{Item, Post, ProtoValid};
+use crate::backend::{self, Backend, Cursor, Factory, UserID, Signature, ItemRow, Timestamp, MigrateOptions, SearchScope};
+use crate::backend::nonblocking::AsyncBackend;
+use crate::protos::{Attachment, Item, Post, ProtoValid, validate_timestamp};
+use crate::outbox;
 
+mod feeds;
 mod filters;
+#[cfg(test)]
+mod tests;
 
 
 pub(crate) fn serve(command: ServeCommand) -> Result<(), failure::Error> {
 
     env_logger::init();
 
-    let ServeCommand{open, shared_options: options, mut binds} = command;
+    let ServeCommand{open, shared_options: options, mut binds, init, sync_interval, max_clock_drift, max_item_size, url} = command;
+    let max_clock_drift = std::time::Duration::from_secs(max_clock_drift);
 
-    // TODO: Error if the file doesn't exist, and make a separate 'init' command.
-    let factory = backend::sqlite::Factory::new(options.sqlite_file.clone());
-    // For now, this creates one if it doesn't exist already:
-    factory.open()?.setup().context("Error setting up DB")?;
-    
+    // Opening a missing SQLite file creates it, so check first, in case of typos:
+    if !init && !options.db_exists() {
+        bail!(
+            "Database file \"{}\" does not exist. Create it with `feoblog db init`, or pass --init.",
+            options.sqlite_file
+        );
+    }
+
+    let factory: Arc<dyn Factory> = options.factory()?.into();
+    let backend = factory.open()?;
+    if backend.status()?.version.is_none() {
+        if !init {
+            bail!(
+                "Database \"{}\" is not initialized. Run `feoblog db init`, or pass --init.",
+                options.db_name()
+            );
+        }
+        backend.init().context("Error initializing DB")?;
+    }
 
+    let report = backend.migrate(&MigrateOptions::default()).context("Error migrating DB")?;
+    drop(backend);
+    if !report.applied.is_empty() {
+        println!("Migrated DB from version {} to {}", report.from_version, report.to_version);
+        if let Some(backup_file) = report.backup_file {
+            println!("Backed up previous version to: {}", backup_file);
+        }
+    }
+
+
+    let sync_factory = factory.clone();
+    let sync_url = url.clone();
+    let (outbox, wakeups) = outbox::Wakeup::new();
     let app_factory = move || {
         let mut app = App::new()
             .wrap(actix_web::middleware::Logger::default())
             .data(AppData{
-                backend_factory: Box::new(factory.clone()),
+                backend: AsyncBackend::new(factory.clone()),
+                max_clock_drift,
+                max_item_size,
+                public_url: url.clone(),
+                outbox: outbox.clone(),
             })
             .configure(routes)
         ;
@@ -96,6 +133,13 @@ pub(crate) fn serve(command: ServeCommand) -> Result<(), failure::Error> {
     }
  
     let mut system = actix_web::rt::System::new("web server");
+    actix_web::rt::spawn(outbox::deliver_periodically(AsyncBackend::new(sync_factory.clone()), wakeups));
+    if let Some(minutes) = sync_interval {
+        println!("Syncing from remote servers every {} minutes", minutes);
+        let interval = std::time::Duration::from_secs(minutes * 60);
+        let options = crate::sync::SyncOptions{ max_clock_drift, max_item_size, own_url: sync_url, ..Default::default() };
+        actix_web::rt::spawn(crate::sync::sync_periodically(AsyncBackend::new(sync_factory), interval, options));
+    }
     system.block_on(server.run())?;
    
     Ok(())
@@ -126,14 +170,41 @@ fn open_socket(bind: &str) -> Result<TcpListener, failure::Error> {
 // This is so that we have typesafe access to AppData fields, because actix
 // Data<Foo> can fail at runtime if you delete a Foo and don't clean up after
 // yourself.
-struct AppData {
-    backend_factory: Box<dyn backend::Factory>,
+pub(crate) struct AppData {
+    pub(crate) backend: AsyncBackend,
+
+    /// How far in the future we'll accept items' timestamps.
+    pub(crate) max_clock_drift: std::time::Duration,
+
+    /// The largest Item we'll accept from users who don't have their own limit.
+    pub(crate) max_item_size: usize,
+
+    /// The URL that other servers know this one by, if configured.
+    /// (See: `feoblog serve --url`)
+    pub(crate) public_url: Option<String>,
+
+    /// Sends our users' new items to other servers.
+    pub(crate) outbox: outbox::Wakeup,
 }
 
-fn routes(cfg: &mut web::ServiceConfig) {
+pub(crate) fn routes(cfg: &mut web::ServiceConfig) {
     cfg
         .route("/", get().to(view_homepage))
         .route("/homepage/proto3", get().to(homepage_item_list))
+        .route("/atom.xml", get().to(feeds::homepage_atom))
+        .route("/rss.xml", get().to(feeds::homepage_rss))
+        .route("/search", get().to(view_search))
+        .service(
+            web::resource("/search/proto3")
+            .route(get().to(search_item_list))
+            .wrap(cors_ok_headers())
+        )
+
+        .service(
+            web::resource("/items/received/proto3")
+            .route(get().to(received_item_list))
+            .wrap(cors_ok_headers())
+        )
 
         .route("/u/{user_id}/", get().to(get_user_items))
         .service(
@@ -141,6 +212,8 @@ fn routes(cfg: &mut web::ServiceConfig) {
             .route(get().to(user_item_list))
             .wrap(cors_ok_headers())
         )
+        .route("/u/{user_id}/atom.xml", get().to(feeds::user_atom))
+        .route("/u/{user_id}/rss.xml", get().to(feeds::user_rss))
 
         .route("/u/{userID}/i/{signature}/", get().to(show_item))
         .service(
@@ -150,6 +223,18 @@ fn routes(cfg: &mut web::ServiceConfig) {
             .route(route().method(Method::OPTIONS).to(cors_preflight_allow))
             .wrap(cors_ok_headers())
         )
+        .service(
+            web::resource("/u/{userID}/i/{signature}/files/{name}")
+            .route(get().to(get_attachment))
+            .route(put().to(put_attachment))
+            .route(route().method(Method::OPTIONS).to(cors_preflight_allow))
+            .wrap(cors_ok_headers())
+        )
+        .service(
+            web::resource("/u/{userID}/i/{signature}/replies/proto3")
+            .route(get().to(reply_item_list))
+            .wrap(cors_ok_headers())
+        )
 
         .route("/u/{user_id}/profile/", get().to(show_profile))
         .service(
@@ -159,6 +244,8 @@ fn routes(cfg: &mut web::ServiceConfig) {
         )
         .route("/u/{user_id}/feed/", get().to(get_user_feed))
         .route("/u/{user_id}/feed/proto3", get().to(feed_item_list))
+        .route("/u/{user_id}/feed/atom.xml", get().to(feeds::user_feed_atom))
+        .route("/u/{user_id}/feed/rss.xml", get().to(feeds::user_feed_rss))
 
     ;
     statics(cfg);
@@ -257,32 +344,26 @@ async fn view_homepage(
 
     let mut items = Vec::with_capacity(max_items);
     let mut has_more = false;
-    let mut item_callback = |row: ItemDisplayRow| {        
+    let mut rows = data.backend.homepage_items(pagination.cursor());
+    while let Some(row) = rows.next().await {
+        let row = row?;
         let mut item = Item::new();
         item.merge_from_bytes(&row.item.item_bytes)?;
 
         if !display_by_default(&item) {
-            // continue:
-            return Ok(true);
+            continue;
         }
 
         if items.len() >= max_items {
             has_more = true;
-            return Ok(false);
+            break;
         }
 
         items.push(IndexPageItem{row, item});
-        Ok(true)
-    };
-
-    let max_time = pagination.before
-        .map(|t| Timestamp{ unix_utc_ms: t})
-        .unwrap_or_else(|| Timestamp::now());
-    let backend = data.backend_factory.open().compat()?;
-    backend.homepage_items(max_time, &mut item_callback).compat()?;
+    }
 
     let display_message = if items.is_empty() {
-        if pagination.before.is_none() {
+        if pagination.is_first_page() {
             Some("Nothing to display".into())
         } else {
             Some("No more items to display.".into())
@@ -296,13 +377,17 @@ async fn view_homepage(
         Nav::Link{
             text: "Client".into(),
             href: "/client/".into(),
-        }
+        },
+        Nav::Link{
+            text: "Search".into(),
+            href: "/search".into(),
+        },
     ];
 
     if has_more {
         if let Some(page_item) = items.last() {
-            let timestamp = page_item.item.timestamp_ms_utc;
-            let mut href = format!("/?before={}", timestamp);
+            let cursor = page_item.row.item.cursor();
+            let mut href = format!("/?cursor={}", cursor.to_base58());
             if pagination.count.is_some() {
                 write!(&mut href, "&count={}", max_items)?;
             }
@@ -318,20 +403,22 @@ async fn view_homepage(
         items,
         display_message,
         show_authors: true,
+        alternates: feeds::alternates("/", "FeoBlog"),
     })
 }
 
-fn item_to_entry(item: &Item, user_id: &UserID, signature: &Signature) -> ItemListEntry {
+pub(crate) fn item_to_entry(item: &Item, row: &ItemRow) -> ItemListEntry {
     let mut entry = ItemListEntry::new();
     entry.set_timestamp_ms_utc(item.timestamp_ms_utc);
+    entry.set_received_utc_ms(row.received.unix_utc_ms);
     entry.set_signature({
         let mut sig = crate::protos::Signature::new();
-        sig.set_bytes(signature.bytes().into());
+        sig.set_bytes(row.signature.bytes().into());
         sig
     });
     entry.set_user_id({
         let mut uid = crate::protos::UserID::new();
-        uid.set_bytes(user_id.bytes().into());
+        uid.set_bytes(row.user.bytes().into());
         uid
     });
     entry.set_item_type(
@@ -356,7 +443,7 @@ async fn homepage_item_list(
         |row: ItemDisplayRow| -> Result<ItemListEntry,failure::Error> {
             let mut item = Item::new();
             item.merge_from_bytes(&row.item.item_bytes)?;
-            Ok(item_to_entry(&item, &row.item.user, &row.item.signature))
+            Ok(item_to_entry(&item, &row.item))
         }, 
         |entry: &ItemListEntry| { 
             entry.get_item_type() == ItemType::POST
@@ -365,12 +452,9 @@ async fn homepage_item_list(
     // We're only holding ItemListEntries in memory, so we can up this limit and save some round trips.
     paginator.max_items = 1000;
 
-    let backend = data.backend_factory.open().compat()?;
-    backend.homepage_items(paginator.before(), &mut paginator.callback()).compat()?;
+    paginator.collect(data.backend.homepage_items(paginator.before())).await.compat()?;
 
-    let mut list = ItemList::new();
-    list.no_more_items = !paginator.has_more;
-    list.items = protobuf::RepeatedField::from(paginator.items);
+    let list = paginator.into_item_list();
     Ok(
         proto_ok().body(list.write_to_bytes()?)
     )
@@ -421,7 +505,7 @@ async fn feed_item_list(
         |row: ItemDisplayRow| -> Result<ItemListEntry,failure::Error> {
             let mut item = Item::new();
             item.merge_from_bytes(&row.item.item_bytes)?;
-            Ok(item_to_entry(&item, &row.item.user, &row.item.signature))
+            Ok(item_to_entry(&item, &row.item))
         }, 
         |_: &ItemListEntry| { true } // include all items
     );
@@ -429,33 +513,47 @@ async fn feed_item_list(
     // save some round trips.
     paginator.max_items = 1000;
 
-    let backend = data.backend_factory.open().compat()?;
-
     // Note: user_feed_items is doing a little bit of extra work to fetch
     // display_name, which we then throw away. We *could* make a more efficient
     // version that we use for just this case, but eh, reuse is nice.
-    backend.user_feed_items(&user_id, paginator.before(), &mut paginator.callback()).compat()?;
+    paginator.collect(data.backend.user_feed_items(user_id, paginator.before())).await.compat()?;
 
-    let mut list = ItemList::new();
-    list.no_more_items = !paginator.has_more;
-    list.items = protobuf::RepeatedField::from(paginator.items);
+    let list = paginator.into_item_list();
     Ok(
         proto_ok()
         .body(list.write_to_bytes()?)
     )
 }
 
+/// Extra query parameters for `/u/{userID}/proto3`.
+#[derive(Deserialize)]
+struct UserItemListParams {
+    /// If present, list items in the order we received them, starting after
+    /// this time. A `cursor` then continues that list.
+    received_after: Option<i64>,
+}
+
 async fn user_item_list(
     data: Data<AppData>,
     Path((user_id,)): Path<(UserID,)>,
     Query(pagination): Query<Pagination>,
+    Query(params): Query<UserItemListParams>,
 ) -> Result<HttpResponse, Error> {
+    if let Some(after) = params.received_after {
+        let pagination = ReceivedPagination{
+            after: Some(after),
+            cursor: pagination.cursor,
+            count: pagination.count,
+        };
+        return received_items(&data, Some(user_id), pagination).await;
+    }
+
     let mut paginator = Paginator::new(
         pagination,
         |row: ItemRow| -> Result<ItemListEntry,failure::Error> {
             let mut item = Item::new();
             item.merge_from_bytes(&row.item_bytes)?;
-            Ok(item_to_entry(&item, &row.user, &row.signature))
+            Ok(item_to_entry(&item, &row))
         }, 
         |_| { true } // include all items
     );
@@ -463,16 +561,199 @@ async fn user_item_list(
     // save some round trips.
     paginator.max_items = 1000;
 
-    let backend = data.backend_factory.open().compat()?;
+    paginator.collect(data.backend.user_items(user_id, paginator.before())).await.compat()?;
 
-    // Note: user_feed_items is doing a little bit of extra work to fetch
-    // display_name, which we then throw away. We *could* make a more efficient
-    // version that we use for just this case, but eh, reuse is nice.
-    backend.user_items(&user_id, paginator.before(), &mut paginator.callback()).compat()?;
+    let list = paginator.into_item_list();
+    Ok(
+        proto_ok()
+        .body(list.write_to_bytes()?)
+    )
+}
 
-    let mut list = ItemList::new();
-    list.no_more_items = !paginator.has_more;
-    list.items = protobuf::RepeatedField::from(paginator.items);
+/// `/items/received/proto3?after=...`
+async fn received_item_list(
+    data: Data<AppData>,
+    Query(pagination): Query<ReceivedPagination>,
+) -> Result<HttpResponse, Error> {
+    received_items(&data, None, pagination).await
+}
+
+/// Lists items in the order this server received them, optionally just for one user.
+async fn received_items(
+    data: &AppData,
+    user_id: Option<UserID>,
+    pagination: ReceivedPagination,
+) -> Result<HttpResponse, Error> {
+    let mut paginator = Paginator::new(
+        pagination.into_pagination(),
+        |ReceivedRow(row)| -> Result<ItemListEntry,failure::Error> {
+            let mut item = Item::new();
+            item.merge_from_bytes(&row.item_bytes)?;
+            Ok(item_to_entry(&item, &row))
+        }, 
+        |_| { true } // include all items
+    );
+    paginator.max_items = 1000;
+
+    let rows = data.backend.received_items(user_id, paginator.before());
+    paginator.collect(rows.map(|row| row.map(ReceivedRow))).await.compat()?;
+
+    let list = paginator.into_item_list();
+    Ok(
+        proto_ok()
+        .body(list.write_to_bytes()?)
+    )
+}
+
+/// Lists all replies to an item that this server knows about.
+async fn reply_item_list(
+    data: Data<AppData>,
+    Path((user_id, signature)): Path<(UserID, Signature)>,
+    Query(pagination): Query<Pagination>,
+) -> Result<HttpResponse, Error> {
+    let mut paginator = Paginator::new(
+        pagination,
+        |row: ItemDisplayRow| -> Result<ItemListEntry,failure::Error> {
+            let mut item = Item::new();
+            item.merge_from_bytes(&row.item.item_bytes)?;
+            Ok(item_to_entry(&item, &row.item))
+        }, 
+        |_: &ItemListEntry| { true } // include all items
+    );
+    paginator.max_items = 1000;
+
+    paginator.collect(data.backend.reply_items(user_id, signature, None, paginator.before())).await.compat()?;
+
+    let list = paginator.into_item_list();
+    Ok(
+        proto_ok()
+        .body(list.write_to_bytes()?)
+    )
+}
+
+/// Query parameters for `/search` and `/search/proto3`.
+#[derive(Deserialize)]
+struct SearchParams {
+    /// The text to search for.
+    q: Option<String>,
+
+    /// Only search this user's items.
+    user: Option<UserID>,
+
+    /// Only search items in this user's feed.
+    feed: Option<UserID>,
+}
+
+impl SearchParams {
+    fn scope(&self) -> Result<SearchScope, Error> {
+        Ok(match (&self.user, &self.feed) {
+            (None, None) => SearchScope::All,
+            (Some(user_id), None) => SearchScope::User(user_id.clone()),
+            (None, Some(user_id)) => SearchScope::Feed(user_id.clone()),
+            (Some(_), Some(_)) => return Err(backend::Error::Validation("Specify only one of `user` or `feed`.".into()).into()),
+        })
+    }
+
+    fn query(&self) -> &str {
+        self.q.as_deref().unwrap_or("").trim()
+    }
+
+    /// Query parameters to reproduce this search, for links to more results.
+    fn to_query_string(&self) -> String {
+        let mut query = format!("q={}", filters::path_segment(self.query()).expect("path_segment shouldn't fail"));
+        if let Some(user_id) = &self.user {
+            write!(query, "&user={}", user_id.to_base58()).expect("write! to a string shouldn't panic.");
+        }
+        if let Some(user_id) = &self.feed {
+            write!(query, "&feed={}", user_id.to_base58()).expect("write! to a string shouldn't panic.");
+        }
+        query
+    }
+}
+
+/// `/search?q=...`
+async fn view_search(
+    data: Data<AppData>,
+    Query(search): Query<SearchParams>,
+    Query(pagination): Query<Pagination>,
+) -> Result<impl Responder, Error> {
+    let scope = search.scope()?;
+    let mut paginator = Paginator::new(
+        pagination,
+        |row: ItemDisplayRow| -> Result<IndexPageItem,failure::Error> {
+            let mut item = Item::new();
+            item.merge_from_bytes(&row.item.item_bytes)?;
+            Ok(IndexPageItem{row, item})
+        }, 
+        |page_item: &IndexPageItem| { 
+            // Users' current profiles are searchable too:
+            display_by_default(&page_item.item) || page_item.item.has_profile()
+        }
+    );
+
+    let query = search.query();
+    let mut display_message = None;
+    if !query.is_empty() {
+        let rows = data.backend.search_items(query.to_string(), scope, paginator.before());
+        paginator.collect(rows).await.compat()?;
+        display_message = paginator.message();
+    }
+
+    let mut nav = vec![
+        Nav::Text("Search".into()),
+        Nav::Link {
+            text: "Home".into(),
+            href: "/".into()
+        },
+    ];
+    if let Some(more) = paginator.more_items_link("") {
+        // more_items_link starts a query string, so append ours to it:
+        nav.push(Nav::Link{
+            text: "More".into(),
+            href: format!("/search{}&{}", more, search.to_query_string()),
+        });
+    }
+
+    Ok(SearchPage {
+        nav,
+        query: query.to_string(),
+        user: search.user.as_ref().map(|u| u.to_base58()),
+        feed: search.feed.as_ref().map(|u| u.to_base58()),
+        items: paginator.items,
+        display_message,
+    })
+}
+
+/// `/search/proto3?q=...`
+async fn search_item_list(
+    data: Data<AppData>,
+    Query(search): Query<SearchParams>,
+    Query(pagination): Query<Pagination>,
+) -> Result<HttpResponse, Error> {
+    let scope = search.scope()?;
+    let query = search.query();
+    if query.is_empty() {
+        return Ok(
+            HttpResponse::BadRequest()
+            .content_type(PLAINTEXT)
+            .body("A search query (`q`) is required.")
+        );
+    }
+
+    let mut paginator = Paginator::new(
+        pagination,
+        |row: ItemDisplayRow| -> Result<ItemListEntry,failure::Error> {
+            let mut item = Item::new();
+            item.merge_from_bytes(&row.item.item_bytes)?;
+            Ok(item_to_entry(&item, &row.item))
+        }, 
+        |_: &ItemListEntry| { true } // include all items
+    );
+    paginator.max_items = 1000;
+
+    paginator.collect(data.backend.search_items(query.to_string(), scope, paginator.before())).await.compat()?;
+
+    let list = paginator.into_item_list();
     Ok(
         proto_ok()
         .body(list.write_to_bytes()?)
@@ -484,10 +765,77 @@ pub(crate) struct Pagination {
     /// Time before which to show posts. Default is now.
     before: Option<i64>,
 
+    /// Continue a list from where a previous page left off.
+    /// Takes precedence over `before`.
+    cursor: Option<Cursor>,
+
     /// Limit how many posts appear on a page.
     count: Option<usize>,
 }
 
+impl Pagination {
+    /// Where to start listing items.
+    fn cursor(&self) -> Cursor {
+        match &self.cursor {
+            Some(cursor) => cursor.clone(),
+            None => Cursor::before(
+                self.before.map(|t| Timestamp{ unix_utc_ms: t}).unwrap_or_else(|| Timestamp::now())
+            ),
+        }
+    }
+
+    fn is_first_page(&self) -> bool {
+        self.before.is_none() && self.cursor.is_none()
+    }
+}
+
+/// Query parameters for lists of items in the order this server received them.
+#[derive(Deserialize)]
+pub(crate) struct ReceivedPagination {
+    /// Time after which to show items. Default is the beginning of time.
+    after: Option<i64>,
+
+    /// Continue a list from where a previous page left off.
+    /// Takes precedence over `after`.
+    cursor: Option<Cursor>,
+
+    /// Limit how many items appear on a page.
+    count: Option<usize>,
+}
+
+impl ReceivedPagination {
+    /// A Pagination that a Paginator of [`ReceivedRow`]s can use.
+    /// Its cursor is where to start listing, in received order.
+    fn into_pagination(self) -> Pagination {
+        let after = Timestamp{ unix_utc_ms: self.after.unwrap_or(0) };
+        Pagination {
+            before: None,
+            cursor: Some(self.cursor.unwrap_or_else(|| Cursor::after(after))),
+            count: self.count,
+        }
+    }
+}
+
+/// Rows from the Backend which know their position in a list.
+pub(crate) trait HasCursor {
+    fn cursor(&self) -> Cursor;
+}
+
+impl HasCursor for ItemRow {
+    fn cursor(&self) -> Cursor { ItemRow::cursor(self) }
+}
+
+impl HasCursor for ItemDisplayRow {
+    fn cursor(&self) -> Cursor { self.item.cursor() }
+}
+
+/// An ItemRow in a list sorted by when we received items.
+struct ReceivedRow(ItemRow);
+
+impl HasCursor for ReceivedRow {
+    fn cursor(&self) -> Cursor { self.0.received_cursor() }
+}
+
 /// Works with the callbacks in Backend to provide pagination.
 pub(crate) struct Paginator<T, In, E, Mapper, Filter>
 where 
@@ -499,6 +847,9 @@ where
     pub params: Pagination,
     pub max_items: usize,
 
+    /// The position of the last item in `items`.
+    last_cursor: Option<Cursor>,
+
     mapper: Mapper,
     filter: Filter,
 
@@ -508,12 +859,14 @@ where
 
 impl<T, In, E, Mapper, Filter> Paginator<T, In, E, Mapper, Filter>
 where 
+    In: HasCursor,
     Mapper: Fn(In) -> Result<T,E>,
     Filter: Fn(&T) -> bool,
 {
     fn accept(&mut self, input: In) -> Result<bool, E> {
         let max_len = self.params.count.map(|c| bound(c, 1, self.max_items)).unwrap_or(self.max_items);
         
+        let cursor = input.cursor();
         let item = (self.mapper)(input)?;
         if !(self.filter)(&item) {
             return Ok(true); // continue
@@ -525,11 +878,22 @@ where
         }
 
         self.items.push(item);
+        self.last_cursor = Some(cursor);
         return Ok(true)
     }
 
-    fn callback<'a>(&'a mut self) -> impl FnMut(In) -> Result<bool, E> + 'a {
-        move |input| self.accept(input)
+    /// Accept rows from a Backend listing until the page is full.
+    async fn collect<S>(&mut self, mut rows: S) -> Result<(), E>
+    where
+        S: Stream<Item=Result<In, backend::Error>> + Unpin,
+        E: From<backend::Error>,
+    {
+        while let Some(row) = rows.next().await {
+            if !self.accept(row?)? {
+                break;
+            }
+        }
+        Ok(())
     }
 
     /// Creates a new paginator for collecting results from a Backend.
@@ -542,6 +906,7 @@ where
             // Seems like a reasonable sane default for things that have to hold Item in memory:
             max_items: 100,
             has_more: false,
+            last_cursor: None,
             mapper,
             filter,
             _in: PhantomData,
@@ -552,7 +917,7 @@ where
     /// An optional message about there being nothing/no more to display.
     fn message(&self) -> Option<String> {
         if self.items.is_empty() {
-            if self.params.before.is_none() {
+            if self.params.is_first_page() {
                 Some("Nothing to display".into())
             } else {
                 Some("No more items to display.".into())
@@ -562,25 +927,20 @@ where
         }
     }
 
-    /// The time before which we should query for items.
-    fn before(&self) -> Timestamp {
-        self.params.before.map(|t| Timestamp{ unix_utc_ms: t}).unwrap_or_else(|| Timestamp::now())
+    /// The position after which we should query for items.
+    fn before(&self) -> Cursor {
+        self.params.cursor()
     }
-}
 
-impl<In, E, Mapper, Filter> Paginator<IndexPageItem, In, E, Mapper, Filter>
-where 
-    Mapper: Fn(In) -> Result<IndexPageItem,E>,
-    Filter: Fn(&IndexPageItem) -> bool,
-{
-   fn more_items_link(&self, base_url: &str) -> Option<String> {
+    /// Where the next page of results starts, if there is one.
+    fn next_cursor(&self) -> Option<Cursor> {
         if !self.has_more { return None; }
-        let last = match self.items.last() {
-            None => return None, // Shouldn't happen, if has_more.
-            Some(last) => last,
-        };
+        self.last_cursor.clone()
+    }
 
-        let mut url = format!("{}?before={}", base_url, last.item.timestamp_ms_utc);
+    fn more_items_link(&self, base_url: &str) -> Option<String> {
+        let cursor = self.next_cursor()?;
+        let mut url = format!("{}?cursor={}", base_url, cursor.to_base58());
         if let Some(count) = self.params.count {
             write!(url, "&count={}", count).expect("write! to a string shouldn't panic.");
         }
@@ -589,6 +949,23 @@ where
     }
 }
 
+impl<In, E, Mapper, Filter> Paginator<ItemListEntry, In, E, Mapper, Filter>
+where 
+    In: HasCursor,
+    Mapper: Fn(In) -> Result<ItemListEntry,E>,
+    Filter: Fn(&ItemListEntry) -> bool,
+{
+    fn into_item_list(self) -> ItemList {
+        let mut list = ItemList::new();
+        list.no_more_items = !self.has_more;
+        if let Some(cursor) = self.next_cursor() {
+            list.next_cursor = cursor.to_base58();
+        }
+        list.items = protobuf::RepeatedField::from(self.items);
+        list
+    }
+}
+
 async fn get_user_feed(
     data: Data<AppData>,
     Path((user_id,)): Path<(UserID,)>,
@@ -606,14 +983,14 @@ async fn get_user_feed(
         }
     );
 
-    let max_time = paginator.params.before
-        .map(|t| Timestamp{ unix_utc_ms: t})
-        .unwrap_or_else(|| Timestamp::now());
-    let backend = data.backend_factory.open().compat()?;
-    backend.user_feed_items(&user_id, max_time, &mut paginator.callback()).compat()?;
+    paginator.collect(data.backend.user_feed_items(user_id.clone(), paginator.before())).await.compat()?;
 
     let mut nav = vec![
         Nav::Text("User Feed".into()),
+        Nav::Link{
+            text: "Search".into(),
+            href: format!("/search?feed={}", user_id.to_base58()),
+        },
     ];
     paginator.more_items_link("").into_iter().for_each(|href| {
         let href = format!("/u/{}/feed/{}", user_id.to_base58(), href);
@@ -625,56 +1002,76 @@ async fn get_user_feed(
         display_message: paginator.message(),
         items: paginator.items,
         show_authors: true,
+        alternates: feeds::alternates(&format!("/u/{}/feed/", user_id.to_base58()), "User Feed"),
     })
 }
 
+/// Query parameters for `/u/{userID}/`.
+#[derive(Deserialize)]
+struct UserItemsParams {
+    /// Show all of the user's items, not just those we display by default.
+    /// ex: `?show_all=1`
+    show_all: Option<String>,
+}
+
+impl UserItemsParams {
+    fn show_all(&self) -> bool {
+        match self.show_all.as_deref() {
+            None | Some("") | Some("0") | Some("false") => false,
+            Some(_) => true,
+        }
+    }
+}
+
 /// Display a single user's posts/etc.
 /// `/u/{userID}/`
 async fn get_user_items(
     data: Data<AppData>,
-    path: Path<(UserID,)>
+    path: Path<(UserID,)>,
+    Query(pagination): Query<Pagination>,
+    Query(params): Query<UserItemsParams>,
 ) -> Result<impl Responder, Error> {
-    let max_items = 10;
-    let mut items = Vec::with_capacity(max_items);
-
-    let mut collect_items = |row: ItemRow| -> Result<bool, failure::Error>{
-        let mut item = Item::new();
-        item.merge_from_bytes(&row.item_bytes)?;
-
-        // TODO: Option: show_all=1.
-        if display_by_default(&item) {
-            items.push(IndexPageItem{ 
+    let show_all = params.show_all();
+    let mut paginator = Paginator::new(
+        pagination,
+        |row: ItemRow| -> Result<IndexPageItem, failure::Error> {
+            let mut item = Item::new();
+            item.merge_from_bytes(&row.item_bytes)?;
+            Ok(IndexPageItem{ 
                 row: ItemDisplayRow{
                     item: row,
                     // We don't display the user's name on their own page.
                     display_name: None,
                 },
                 item 
-            });
+            })
+        },
+        |page_item: &IndexPageItem| {
+            show_all || display_by_default(&page_item.item)
         }
-
-        Ok(items.len() < max_items)
-    };
-
-    // TODO: Support pagination.
-    let max_time = Timestamp::now();
+    );
 
     let (user,) = path.into_inner();
-    let backend = data.backend_factory.open().compat()?;
-    backend.user_items(&user, max_time, &mut collect_items).compat()?;
+    paginator.collect(data.backend.user_items(user.clone(), paginator.before())).await.compat()?;
 
     
     let mut nav = vec![];
-    let profile = backend.user_profile(&user).compat()?;
+    let mut display_name = String::new();
+    let profile = {
+        let user = user.clone();
+        data.backend.run(move |backend| backend.user_profile(&user)).await?
+    };
     if let Some(row) = profile {
         let mut item = Item::new();
         item.merge_from_bytes(&row.item_bytes)?;
 
+        display_name = item.get_profile().display_name.clone();
         nav.push(
-            Nav::Text(item.get_profile().display_name.clone())
+            Nav::Text(display_name.clone())
         )
     }
 
+    let base_url = format!("/u/{}/", user.to_base58());
     nav.extend(vec![
         Nav::Link{
             text: "Profile".into(),
@@ -684,29 +1081,58 @@ async fn get_user_items(
             text: "Feed".into(),
             href: format!("/u/{}/feed/", user.to_base58()),
         },
+        Nav::Link{
+            text: "Search".into(),
+            href: format!("/search?user={}", user.to_base58()),
+        },
+        if show_all {
+            Nav::Link{
+                text: "Show Posts".into(),
+                href: base_url.clone(),
+            }
+        } else {
+            Nav::Link{
+                text: "Show All".into(),
+                href: format!("{}?show_all=1", base_url),
+            }
+        },
         Nav::Link{
             text: "Home".into(),
             href: "/".into()
         },
     ]);
 
+    if let Some(mut href) = paginator.more_items_link(&base_url) {
+        if show_all {
+            href.push_str("&show_all=1");
+        }
+        nav.push(Nav::Link{href, text: "More".into()});
+    }
+
     Ok(IndexPage{
         nav,
-        items,
+        display_message: paginator.message(),
+        items: paginator.items,
         show_authors: false,
-        display_message: None,
+        alternates: feeds::alternates(&base_url, &feed_title(&display_name, &user)),
     })
 }
 
-const MAX_ITEM_SIZE: usize = 1024 * 32; 
+/// The largest Item we accept by default. (See: `feoblog serve --max-item-size`)
+/// Also the largest Item we'll fetch from other servers.
+pub(crate) const MAX_ITEM_SIZE: usize = 1024 * 32;
 const PLAINTEXT: &'static str = "text/plain; charset=utf-8";
 
 /// Accepts a proto3 Item
 /// Returns 201 if the PUT was successful.
 /// Returns 202 if the item already exists.
-/// Returns ??? if the user lacks permission to post.
-/// Returns ??? if the signature is not valid.
-/// Returns a text body message w/ OK/Error message.
+/// Returns 507 if saving the item would exceed the user's quota.
+/// Returns 403 if the user lacks permission to post. (ex: their ID was revoked.)
+/// Returns 400 if the signature or Item is not valid.
+/// Returns 413 if the Item is larger than the user's (or server's) limit.
+/// Returns a text body message w/ OK message, or a JSON error. (See: [`Error`])
+///
+/// The body may use chunked transfer encoding instead of a Content-Length.
 async fn put_item(
     data: Data<AppData>,
     path: Path<(String, String,)>,
@@ -715,79 +1141,81 @@ async fn put_item(
 ) -> Result<HttpResponse, Error> 
 {
     let (user_path, sig_path) = path.into_inner();
-    let user = UserID::from_base58(user_path.as_str()).context("decoding user ID").compat()?;
-    let signature = Signature::from_base58(sig_path.as_str()).context("decoding signature").compat()?;
-
-    let length = match req.headers().get("content-length") {
-        Some(length) => length,
-        None => {
-            return Ok(
-                HttpResponse::LengthRequired()
-                .content_type(PLAINTEXT)
-                .body("Must include length header.".to_string())
-                // ... so that we can reject things that are too large outright.
-            );
-        }
+    let user = UserID::from_base58(user_path.as_str())
+        .map_err(|e| backend::Error::Validation(format!("Invalid user ID: {}", e)))?;
+    let signature = Signature::from_base58(sig_path.as_str())
+        .map_err(|e| backend::Error::Validation(format!("Invalid signature: {}", e)))?;
+
+    // Until we've read the body, responses must close the connection.
+    // Otherwise, the client may send its next request on a connection that
+    // still has the unread body in it.
+    let length: Option<usize> = match req.headers().get("content-length") {
+        None => None, // Chunked. We'll check the size as we read it.
+        Some(length) => match length.to_str().ok().and_then(|length| length.parse().ok()) {
+            Some(length) => Some(length),
+            None => {
+                return Ok(
+                    HttpResponse::BadRequest()
+                    .force_close()
+                    .content_type(PLAINTEXT)
+                    .body("Error parsing Length header.".to_string())
+                );
+            },
+        },
     };
 
-    let length: usize = match length.to_str()?.parse() {
-        Ok(length) => length,
-        Err(_) => {
-            return Ok(
-                HttpResponse::BadRequest()
-                .content_type(PLAINTEXT)
-                .body("Error parsing Length header.".to_string())
-            );
-        },
+    let (exists, revoked, known, user_max_item_size) = {
+        let (user, signature) = (user.clone(), signature.clone());
+        data.backend.run(move |backend| {
+            let max_item_size = backend.server_user(&user)?.and_then(|user| user.max_item_size);
+            Ok((
+                backend.user_item_exists(&user, &signature)?,
+                backend.user_revoked(&user)?,
+                backend.user_known(&user)?,
+                max_item_size,
+            ))
+        }).await?
     };
+    let max_item_size = user_max_item_size.map(|size| size as usize).unwrap_or(data.max_item_size);
 
-    if length > MAX_ITEM_SIZE {
-        return Ok(
-            HttpResponse::PayloadTooLarge()
-            .content_type(PLAINTEXT)
-            .body(format!("Item must be <= {} bytes", MAX_ITEM_SIZE))
-        );
+    // If the content already exists, do nothing.
+    if exists {
+        return Ok(item_exists());
     }
 
-    let mut backend = data.backend_factory.open().compat()?;
-
-    // If the content already exists, do nothing.
-    if backend.user_item_exists(&user, &signature).compat()? {
-        return Ok(
-            HttpResponse::Accepted()
-            .content_type(PLAINTEXT)
-            .body("Item already exists")
-        );
+    if revoked {
+        return Err(backend::Error::Quota(backend::QuotaDenyReason::ProfileRevoked).into());
     }
 
-    if !backend.user_known(&user).compat()? {
+    if !known {
         return Ok(
             HttpResponse::Forbidden()
+            .force_close()
             .content_type(PLAINTEXT)
             .body("Unknown user ID".to_string())
         )
     }
-    
-    let mut bytes: Vec<u8> = Vec::with_capacity(length);
-    while let Some(chunk) = body.next().await {
-        let chunk = chunk.context("Error parsing chunk").compat()?;
-        bytes.extend_from_slice(&chunk);
+
+    // Reject things that are too large outright, if we can:
+    let length = length.unwrap_or(0);
+    if length > max_item_size {
+        return Ok(item_too_large(max_item_size));
     }
 
+    let bytes = match read_body(&mut body, length, max_item_size).await? {
+        Some(bytes) => bytes,
+        None => return Ok(item_too_large(max_item_size)),
+    };
+
     if !signature.is_valid(&user, &bytes) {
-        Err(format_err!("Invalid signature").compat())?;
+        return Err(backend::Error::Validation("Invalid signature".into()).into());
     }
 
     let mut item: Item = Item::new();
-    item.merge_from_bytes(&bytes)?;
-    item.validate()?;
-
-    if let Some(deny_reason) = backend.quota_check_item(&user, &bytes, &item).compat()? {
-        return Ok(
-            HttpResponse::InsufficientStorage()
-            .body(format!("{}", deny_reason))
-        )
-    }
+    item.merge_from_bytes(&bytes)
+        .map_err(|e| backend::Error::Validation(format!("Invalid Item: {}", e)))?;
+    item.validate().map_err(backend::Error::from)?;
+    validate_timestamp(&item, Timestamp::now().unix_utc_ms, data.max_clock_drift).map_err(backend::Error::from)?;
 
     let message = format!("OK. Received {} bytes.", bytes.len());
     
@@ -799,7 +1227,33 @@ async fn put_item(
         item_bytes: bytes,
     };
 
-    backend.save_user_item(&row, &item).context("Error saving user item").compat()?;
+    let own_url = data.public_url.clone();
+    let queued = data.backend.run(move |backend| {
+        if let Some(deny_reason) = backend.quota_check_item(&row.user, &row.item_bytes, &item)? {
+            return Err(backend::Error::Quota(deny_reason));
+        }
+
+        match backend.save_user_item(&row, &item) {
+            // Another upload of the same item got there first:
+            Err(backend::Error::Conflict(_)) => return Ok(None),
+            result => result?,
+        }
+
+        // Forward our own users' items to the other servers in their profiles:
+        let mut queued = 0;
+        if backend.server_user(&row.user)?.is_some() {
+            queued = outbox::enqueue(backend, &row.user, &row.signature, own_url.as_deref())?;
+        }
+        Ok(Some(queued))
+    }).await?;
+
+    let queued = match queued {
+        Some(queued) => queued,
+        None => return Ok(item_exists()),
+    };
+    if queued > 0 {
+        data.outbox.wake();
+    }
 
     let response = HttpResponse::Created()
         .content_type(PLAINTEXT)
@@ -808,6 +1262,201 @@ async fn put_item(
     Ok(response)
 }
 
+fn item_exists() -> HttpResponse {
+    HttpResponse::Accepted()
+    .force_close()
+    .content_type(PLAINTEXT)
+    .body("Item already exists")
+}
+
+fn item_too_large(max_item_size: usize) -> HttpResponse {
+    HttpResponse::PayloadTooLarge()
+    .force_close()
+    .content_type(PLAINTEXT)
+    .body(format!("Item must be <= {} bytes", max_item_size))
+}
+
+/// Read a request body, if it's at most `max_bytes` long.
+///
+/// Stops reading, and returns None, as soon as the body grows larger than
+/// that, whatever its Content-Length said.
+async fn read_body(body: &mut Payload, capacity: usize, max_bytes: usize) -> Result<Option<Vec<u8>>, Error> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(capacity);
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.context("Error parsing chunk").compat()?;
+        if bytes.len() + chunk.len() > max_bytes {
+            return Ok(None);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(bytes))
+}
+
+const MAX_ATTACHMENT_SIZE: u64 = 1024 * 1024 * 10;
+
+/// Accepts the contents of a file attached to an Item.
+/// The Item must be uploaded first.
+/// Returns 201 if the PUT was successful.
+/// Returns 202 if the file already exists.
+/// Returns 400 if the file doesn't match the size and hash in the Item.
+/// Returns 404 if the Item doesn't exist, or doesn't have this attachment.
+/// Returns 507 if saving the file would exceed the user's quota.
+async fn put_attachment(
+    data: Data<AppData>,
+    Path((user_id, signature, name)): Path<(UserID, Signature, String)>,
+    mut body: Payload,
+) -> Result<HttpResponse, Error> 
+{
+    let found = {
+        let (user_id, signature) = (user_id.clone(), signature.clone());
+        data.backend.run(move |backend| {
+            let (item, attachment) = match find_attachment(backend, &user_id, &signature, &name)? {
+                Some(found) => found,
+                None => return Ok(None),
+            };
+            let exists = backend.blob_exists(attachment.get_hash())?;
+            Ok(Some((item, attachment, exists)))
+        }).await?
+    };
+
+    let (item, attachment, exists) = match found {
+        Some(found) => found,
+        None => {
+            return Ok(
+                HttpResponse::NotFound()
+                .content_type(PLAINTEXT)
+                .body("No such item or attachment")
+            );
+        }
+    };
+
+    if exists {
+        return Ok(
+            HttpResponse::Accepted()
+            .content_type(PLAINTEXT)
+            .body("File already exists")
+        );
+    }
+
+    let size = attachment.get_size();
+    if size > MAX_ATTACHMENT_SIZE {
+        return Ok(
+            HttpResponse::PayloadTooLarge()
+            .content_type(PLAINTEXT)
+            .body(format!("Attachments must be <= {} bytes", MAX_ATTACHMENT_SIZE))
+        );
+    }
+
+    data.backend.run(move |backend| {
+        match backend.quota_check_attachment(&user_id, &item, size)? {
+            Some(deny_reason) => Err(backend::Error::Quota(deny_reason)),
+            None => Ok(()),
+        }
+    }).await?;
+
+    let mut bytes: Vec<u8> = Vec::with_capacity(size as usize);
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.context("Error parsing chunk").compat()?;
+        if (bytes.len() + chunk.len()) as u64 > size {
+            return Ok(
+                HttpResponse::BadRequest()
+                .content_type(PLAINTEXT)
+                .body(format!("File is larger than the expected {} bytes", size))
+            );
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    if bytes.len() as u64 != size {
+        return Ok(
+            HttpResponse::BadRequest()
+            .content_type(PLAINTEXT)
+            .body(format!("Expected {} bytes, but received {}", size, bytes.len()))
+        );
+    }
+
+    // Item validation already checked that we support this hash type:
+    let hash = multihash::decode(attachment.get_hash())?;
+    if multihash::encode(hash.alg, &bytes)? != attachment.get_hash() {
+        return Ok(
+            HttpResponse::BadRequest()
+            .content_type(PLAINTEXT)
+            .body("File does not match its hash")
+        );
+    }
+
+    let message = format!("OK. Received {} bytes.", bytes.len());
+    data.backend.run(move |backend| {
+        Ok(backend.save_blob(attachment.get_hash(), &bytes).context("Error saving file")?)
+    }).await?;
+
+    Ok(
+        HttpResponse::Created()
+        .content_type(PLAINTEXT)
+        .body(message)
+    )
+}
+
+/// Get a file attached to an Item.
+///
+/// `/u/{userID}/i/{sig}/files/{name}`
+async fn get_attachment(
+    data: Data<AppData>,
+    Path((user_id, signature, name)): Path<(UserID, Signature, String)>,
+) -> Result<HttpResponse, Error> {
+    let found = {
+        let name = name.clone();
+        data.backend.run(move |backend| {
+            let (_, attachment) = match find_attachment(backend, &user_id, &signature, &name)? {
+                Some(found) => found,
+                None => return Ok(None),
+            };
+            Ok(Some(backend.blob(attachment.get_hash())?))
+        }).await?
+    };
+
+    let bytes = match found {
+        None => return Ok(HttpResponse::NotFound().body("No such item or attachment")),
+        Some(None) => return Ok(HttpResponse::NotFound().body("File has not been uploaded")),
+        Some(Some(bytes)) => bytes,
+    };
+
+    let mime_type = format!("{}", mime_guess::from_path(&name).first_or_octet_stream());
+    Ok(
+        HttpResponse::Ok()
+        .content_type(mime_type)
+        // Files are user content. Don't let browsers run scripts in them on our origin:
+        .header("Content-Security-Policy", "sandbox")
+        .header("X-Content-Type-Options", "nosniff")
+        // Like Items, attachments are immutable:
+        .header("Cache-Control", "public, max-age=31536000, immutable")
+        .body(bytes)
+    )
+}
+
+/// Find an Item, and the attachment named `name` within it.
+fn find_attachment(
+    backend: &dyn Backend,
+    user_id: &UserID,
+    signature: &Signature,
+    name: &str,
+) -> Result<Option<(Item, Attachment)>, failure::Error> {
+    let row = match backend.user_item(user_id, signature)? {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+
+    let mut item = Item::new();
+    item.merge_from_bytes(&row.item_bytes)?;
+
+    let attachment = item.get_post()
+        .get_attachments()
+        .iter()
+        .find(|a| a.get_name() == name)
+        .cloned();
+
+    Ok(attachment.map(|a| (item, a)))
+}
 
 async fn show_item(
     data: Data<AppData>,
@@ -816,8 +1465,12 @@ async fn show_item(
 ) -> Result<HttpResponse, Error> {
 
     let (user_id, signature) = path.into_inner();
-    let backend = data.backend_factory.open().compat()?;
-    let row = backend.user_item(&user_id, &signature).compat()?;
+    let (row, profile) = {
+        let (user_id, signature) = (user_id.clone(), signature.clone());
+        data.backend.run(move |backend| {
+            Ok((backend.user_item(&user_id, &signature)?, backend.user_profile(&user_id)?))
+        }).await?
+    };
     let row = match row {
         Some(row) => row,
         None => { 
@@ -835,21 +1488,51 @@ async fn show_item(
     let mut item = Item::new();
     item.merge_from_bytes(row.item_bytes.as_slice())?;
 
-    let row = backend.user_profile(&user_id).compat()?;
-    let display_name = {
+    let profile = {
         let mut item = Item::new();
-        if let Some(row) = row {
+        if let Some(row) = profile {
             item.merge_from_bytes(row.item_bytes.as_slice())?;
         }
         item
-    }.get_profile().display_name.clone();
+    };
+    let display_name = profile.get_profile().display_name.clone();
+    let revoked = profile.get_profile().get_revoked();
     
     use crate::protos::Item_oneof_item_type as ItemType;
     match item.item_type {
         None => Ok(HttpResponse::InternalServerError().body("No known item type provided.")),
         Some(ItemType::profile(p)) => Ok(HttpResponse::Ok().body("Profile update.")),
         Some(ItemType::post(p)) => {
+            // Only show replies from people the author follows, so that
+            // strangers can't use the author's page as a platform:
+            let max_replies = 100;
+            let mut replies = Vec::new();
+            let mut rows = data.backend.reply_items(user_id.clone(), signature.clone(), Some(user_id.clone()), Cursor::before(Timestamp::now()));
+            while let Some(row) = rows.next().await {
+                let row = row?;
+                let mut item = Item::new();
+                item.merge_from_bytes(&row.item.item_bytes)?;
+                if display_by_default(&item) {
+                    replies.push(IndexPageItem{row, item});
+                }
+                if replies.len() >= max_replies {
+                    break;
+                }
+            }
+            // Show the conversation in chronological order:
+            replies.reverse();
+
+            let reply_to_href = if p.has_reply_to() {
+                let reply_to = p.get_reply_to();
+                let user_id = UserID::from_vec(reply_to.get_user_id().get_bytes().into()).compat()?;
+                let signature = Signature::from_vec(reply_to.get_signature().get_bytes().into()).compat()?;
+                Some(format!("/u/{}/i/{}/", user_id.to_base58(), signature.to_base58()))
+            } else {
+                None
+            };
+
             let page = PostPage {
+                alternates: feeds::alternates(&format!("/u/{}/", user_id.to_base58()), &feed_title(&display_name, &user_id)),
                 nav: vec![
                     Nav::Text(display_name.clone()),
                     Nav::Link {
@@ -866,8 +1549,12 @@ async fn show_item(
                 signature,
                 text: p.body,
                 title: p.title,
+                attachments: p.attachments.into_vec(),
+                reply_to_href,
+                replies,
                 timestamp_utc_ms: item.timestamp_ms_utc,
                 utc_offset_minutes: item.utc_offset_minutes,
+                revoked,
             };
 
             Ok(page.respond_to(&req).await?)
@@ -897,8 +1584,7 @@ async fn get_item(
     // TODO: Limit items we return to "known users", in case we unfollowed someone due to sketchy content.
 
     let (user_id, signature) = path.into_inner();
-    let backend = data.backend_factory.open().compat()?;
-    let item = backend.user_item(&user_id, &signature).compat()?;
+    let item = data.backend.run(move |backend| backend.user_item(&user_id, &signature)).await?;
     let item = match item {
         Some(item) => item,
         None => { 
@@ -929,8 +1615,7 @@ async fn get_profile_item(
     Path((user_id,)): Path<(UserID,)>,
 ) -> Result<HttpResponse, Error> {
     
-    let backend = data.backend_factory.open().compat()?;
-    let item = backend.user_profile(&user_id,).compat()?;
+    let item = data.backend.run(move |backend| backend.user_profile(&user_id)).await?;
     let item = match item {
         Some(item) => item,
         None => { 
@@ -965,9 +1650,10 @@ async fn show_profile(
 ) -> Result<HttpResponse, Error> 
 {
     let (user_id,) = path.into_inner();
-    let backend = data.backend_factory.open().compat()?;
-
-    let row = backend.user_profile(&user_id).compat()?;
+    let row = {
+        let user_id = user_id.clone();
+        data.backend.run(move |backend| backend.user_profile(&user_id)).await?
+    };
 
     let row = match row {
         Some(r) => r,
@@ -990,6 +1676,7 @@ async fn show_profile(
 
     let timestamp_utc_ms = item.timestamp_ms_utc;
     let utc_offset_minutes = item.utc_offset_minutes;
+    let revoked = item.get_profile().get_revoked();
     let text = std::mem::take(&mut item.mut_profile().about);
 
     let follows = std::mem::take(&mut item.get_profile()).follows.to_vec();
@@ -1004,6 +1691,7 @@ async fn show_profile(
 
     let page = ProfilePage{
         nav,
+        alternates: feeds::alternates(&format!("/u/{}/", user_id.to_base58()), &feed_title(&display_name, &user_id)),
         text,
         display_name,
         follows,
@@ -1011,6 +1699,7 @@ async fn show_profile(
         utc_offset_minutes,
         user_id: row.user,
         signature: row.signature,
+        revoked,
     };
 
     Ok(page.respond_to(&req).await?)
@@ -1034,12 +1723,30 @@ struct IndexPage {
 
     /// Should we show author info w/ links to their profiles?
     show_authors: bool,
+
+    /// Atom/RSS feeds for this page.
+    alternates: Vec<feeds::Alternate>,
+}
+
+#[derive(Template)]
+#[template(path = "search.html")]
+struct SearchPage {
+    nav: Vec<Nav>,
+    query: String,
+
+    /// base58 user IDs the search is scoped to, if any.
+    user: Option<String>,
+    feed: Option<String>,
+
+    items: Vec<IndexPageItem>,
+    display_message: Option<String>,
 }
 
 #[derive(Template)]
 #[template(path = "profile.html")]
 struct ProfilePage {
     nav: Vec<Nav>,
+    alternates: Vec<feeds::Alternate>,
     user_id: UserID,
     signature: Signature,
     display_name: String,
@@ -1047,21 +1754,33 @@ struct ProfilePage {
     follows: Vec<ProfileFollow>,
     timestamp_utc_ms: i64,
     utc_offset_minutes: i32,
+
+    /// Is this profile a revocation of the user ID?
+    revoked: bool,
 }
 
 #[derive(Template)]
 #[template(path = "post.html")]
 struct PostPage {
     nav: Vec<Nav>,
+    alternates: Vec<feeds::Alternate>,
     user_id: UserID,
     signature: Signature,
     display_name: String,
     text: String,
     title: String,
+    attachments: Vec<Attachment>,
     timestamp_utc_ms: i64,
     utc_offset_minutes: i32,
 
-    // TODO: Include comments from people this user follows.
+    /// Links to the item this post replies to, if any.
+    reply_to_href: Option<String>,
+
+    /// Replies from the author and people they follow, oldest first.
+    replies: Vec<IndexPageItem>,
+
+    /// Has the author's user ID been revoked?
+    revoked: bool,
 }
 
 struct ProfileFollow {
@@ -1095,6 +1814,16 @@ impl IndexPageItem {
 
 
 
+/// The title for a user's feeds: their display name if they have one.
+fn feed_title(display_name: &str, user_id: &UserID) -> String {
+    let display_name = display_name.trim();
+    if display_name.is_empty() {
+        user_id.to_base58()
+    } else {
+        display_name.to_string()
+    }
+}
+
 fn display_by_default(item: &Item) -> bool {
     let item_type = match &item.item_type {
         // Don't display items we can't find a type for. (newer than this server knows about):
@@ -1120,18 +1849,83 @@ enum Nav {
 
 
 /// A type implementing ResponseError that can hold any kind of std::error::Error.
+///
+/// A [`backend::Error`] (even one wrapped in a `failure::Error`) gets the status
+/// code for its kind. Anything else is a 500, whose details aren't sent to the
+/// client.
 #[derive(Debug)]
 struct Error {
     inner: Box<dyn std::error::Error + 'static>
 }
 
+impl Error {
+    fn backend_error(&self) -> Option<&backend::Error> {
+        if let Some(error) = self.inner.downcast_ref::<backend::Error>() {
+            return Some(error);
+        }
+        let compat = self.inner.downcast_ref::<failure::Compat<failure::Error>>()?;
+        compat.get_ref().downcast_ref::<backend::Error>()
+    }
+
+    /// A message for the client. Storage and internal errors may include
+    /// details of our database or filesystem, so those are only logged.
+    fn message(&self) -> String {
+        match self.backend_error() {
+            Some(backend::Error::Storage(_)) | None => {
+                println!("Error: {}", self);
+                "Internal server error".into()
+            },
+            Some(_) => self.to_string(),
+        }
+    }
+
+    /// A machine-readable name for the kind of error.
+    fn code(&self) -> &'static str {
+        match self.backend_error() {
+            Some(backend::Error::NotFound(_)) => "not_found",
+            Some(backend::Error::Conflict(_)) => "conflict",
+            Some(backend::Error::Validation(_)) => "validation",
+            Some(backend::Error::Quota(_)) => "quota",
+            Some(backend::Error::Storage(_)) => "storage",
+            None => "internal",
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> std::result::Result<(), fmt::Error> { 
         self.inner.fmt(formatter)
     }
 }
 
-impl actix_web::error::ResponseError for Error {}
+/// The body of an error response.
+#[derive(Serialize)]
+struct ErrorBody {
+    error: &'static str,
+    message: String,
+}
+
+impl actix_web::error::ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self.backend_error() {
+            Some(backend::Error::NotFound(_)) => StatusCode::NOT_FOUND,
+            Some(backend::Error::Conflict(_)) => StatusCode::CONFLICT,
+            Some(backend::Error::Validation(_)) => StatusCode::BAD_REQUEST,
+            Some(backend::Error::Quota(backend::QuotaDenyReason::NewerItemsExceedQuota{..})) => StatusCode::INSUFFICIENT_STORAGE,
+            Some(backend::Error::Quota(_)) => StatusCode::FORBIDDEN,
+            Some(backend::Error::Storage(_)) | None => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        // Handlers (ex: put_item) may fail before reading the request body, so
+        // close the connection, like their other early responses do.
+        HttpResponse::build(self.status_code()).force_close().json(ErrorBody{
+            error: self.code(),
+            message: self.message(),
+        })
+    }
+}
 
 impl <E> From<E> for Error
 where E: std::error::Error + 'static
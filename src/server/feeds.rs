@@ -0,0 +1,245 @@
+//! Atom and RSS feeds, so that people can follow blogs with ordinary feed readers.
+
+use actix_web::{HttpRequest, HttpResponse, web::{Data, Path}};
+use askama::Template;
+use failure::ResultExt;
+use futures::{Stream, StreamExt as _};
+use protobuf::Message as _;
+
+use crate::backend::{self, Cursor, ItemDisplayRow, ItemRow, Timestamp, UserID};
+use crate::markdown::ToHTML;
+use crate::protos::Item;
+
+use super::{AppData, Error, IndexPageItem, display_by_default, feed_title};
+
+/// How many items to include in a feed.
+const FEED_ITEMS: usize = 20;
+
+/// A `<link rel="alternate">` that lets browsers and feed readers discover feeds.
+pub(super) struct Alternate {
+    pub mime_type: &'static str,
+    pub title: String,
+    pub href: String,
+}
+
+/// Alternate links for the feeds at `{path}atom.xml` and `{path}rss.xml`.
+pub(super) fn alternates(path: &str, title: &str) -> Vec<Alternate> {
+    vec![
+        Alternate {
+            mime_type: ATOM,
+            title: format!("{} (Atom)", title),
+            href: format!("{}atom.xml", path),
+        },
+        Alternate {
+            mime_type: RSS,
+            title: format!("{} (RSS)", title),
+            href: format!("{}rss.xml", path),
+        },
+    ]
+}
+
+const ATOM: &str = "application/atom+xml";
+const RSS: &str = "application/rss+xml";
+
+/// The data common to Atom and RSS feeds.
+struct Feed {
+    title: String,
+
+    /// Absolute URL of the HTML page that this feed follows.
+    html_url: String,
+
+    /// Absolute URL of the feed itself.
+    self_url: String,
+
+    entries: Vec<FeedEntry>,
+}
+
+struct FeedEntry {
+    title: String,
+    author: String,
+
+    /// Absolute URL of the item's HTML page.
+    url: String,
+
+    /// The item's body, rendered as HTML.
+    html: String,
+
+    timestamp: Timestamp,
+    utc_offset_minutes: i16,
+}
+
+impl FeedEntry {
+    fn new(base_url: &str, row: &ItemRow, item: &Item, author: String) -> Self {
+        let post = item.get_post();
+        FeedEntry {
+            title: post.get_title().to_string(),
+            author,
+            url: format!("{}/u/{}/i/{}/", base_url, row.user.to_base58(), row.signature.to_base58()),
+            html: post.get_body().md_to_html(),
+            timestamp: Timestamp{ unix_utc_ms: item.timestamp_ms_utc },
+            utc_offset_minutes: item.utc_offset_minutes as i16,
+        }
+    }
+
+    fn updated(&self) -> String {
+        self.timestamp.format_rfc3339(self.utc_offset_minutes)
+    }
+
+    fn pub_date(&self) -> String {
+        self.timestamp.format_rfc2822(self.utc_offset_minutes)
+    }
+}
+
+impl Feed {
+    fn new(req: &HttpRequest, title: String, html_path: &str) -> Self {
+        let base_url = base_url(req);
+        Feed {
+            title,
+            html_url: format!("{}{}", base_url, html_path),
+            self_url: format!("{}{}", base_url, req.path()),
+            entries: vec![],
+        }
+    }
+
+    /// When the feed last changed. (i.e.: its newest entry.)
+    fn last_entry(&self) -> (Timestamp, i16) {
+        match self.entries.first() {
+            Some(entry) => (entry.timestamp, entry.utc_offset_minutes),
+            None => (Timestamp::now(), 0),
+        }
+    }
+
+    fn updated(&self) -> String {
+        let (timestamp, offset) = self.last_entry();
+        timestamp.format_rfc3339(offset)
+    }
+
+    fn pub_date(&self) -> String {
+        let (timestamp, offset) = self.last_entry();
+        timestamp.format_rfc2822(offset)
+    }
+
+    /// Collects entries from Backend methods that return ItemDisplayRows.
+    fn collect(&mut self, base_url: &str, row: ItemDisplayRow) -> Result<bool, failure::Error> {
+        let mut item = Item::new();
+        item.merge_from_bytes(&row.item.item_bytes)?;
+        if display_by_default(&item) {
+            let page_item = IndexPageItem{ row, item };
+            let author = page_item.display_name().into_owned();
+            self.entries.push(FeedEntry::new(base_url, &page_item.row.item, &page_item.item, author));
+        }
+        Ok(self.entries.len() < FEED_ITEMS)
+    }
+
+    /// Collects entries from a Backend listing until the feed is full.
+    async fn collect_all<S>(&mut self, base_url: &str, mut rows: S) -> Result<(), failure::Error>
+    where S: Stream<Item=Result<ItemDisplayRow, backend::Error>> + Unpin
+    {
+        while let Some(row) = rows.next().await {
+            if !self.collect(base_url, row?)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn atom(&self) -> Result<HttpResponse, Error> {
+        let body = AtomFeed{ feed: self }.render().compat()?;
+        Ok(HttpResponse::Ok().content_type(ATOM).body(body))
+    }
+
+    fn rss(&self) -> Result<HttpResponse, Error> {
+        let body = RssFeed{ feed: self }.render().compat()?;
+        Ok(HttpResponse::Ok().content_type(RSS).body(body))
+    }
+}
+
+#[derive(Template)]
+#[template(path = "atom.xml")]
+struct AtomFeed<'a> {
+    feed: &'a Feed,
+}
+
+#[derive(Template)]
+#[template(path = "rss.xml")]
+struct RssFeed<'a> {
+    feed: &'a Feed,
+}
+
+/// ex: `https://feo.example.com`
+pub(super) fn base_url(req: &HttpRequest) -> String {
+    let info = req.connection_info();
+    format!("{}://{}", info.scheme(), info.host())
+}
+
+/// The name to use for a user in feed titles.
+async fn user_name(data: &AppData, user_id: &UserID) -> Result<String, Error> {
+    let profile = {
+        let user_id = user_id.clone();
+        data.backend.run(move |backend| backend.user_profile(&user_id)).await?
+    };
+    let mut display_name = String::new();
+    if let Some(row) = profile {
+        let mut item = Item::new();
+        item.merge_from_bytes(&row.item_bytes)?;
+        display_name = item.get_profile().display_name.clone();
+    }
+    Ok(feed_title(&display_name, user_id))
+}
+
+async fn homepage_feed(data: &AppData, req: &HttpRequest) -> Result<Feed, Error> {
+    let base_url = base_url(req);
+    let mut feed = Feed::new(req, "FeoBlog".into(), "/");
+    let rows = data.backend.homepage_items(Cursor::before(Timestamp::now()));
+    feed.collect_all(&base_url, rows).await.compat()?;
+    Ok(feed)
+}
+
+async fn user_feed(data: &AppData, req: &HttpRequest, user_id: &UserID) -> Result<Feed, Error> {
+    let base_url = base_url(req);
+    let name = user_name(data, user_id).await?;
+    let mut feed = Feed::new(req, name.clone(), &format!("/u/{}/", user_id.to_base58()));
+    let rows = data.backend.user_items(user_id.clone(), Cursor::before(Timestamp::now()))
+        .map(|row| row.map(|row: ItemRow| ItemDisplayRow{ item: row, display_name: Some(name.clone()) }));
+    feed.collect_all(&base_url, rows).await.compat()?;
+    Ok(feed)
+}
+
+async fn user_feed_feed(data: &AppData, req: &HttpRequest, user_id: &UserID) -> Result<Feed, Error> {
+    let base_url = base_url(req);
+    let name = user_name(data, user_id).await?;
+    let mut feed = Feed::new(req, format!("Feed for {}", name), &format!("/u/{}/feed/", user_id.to_base58()));
+    let rows = data.backend.user_feed_items(user_id.clone(), Cursor::before(Timestamp::now()));
+    feed.collect_all(&base_url, rows).await.compat()?;
+    Ok(feed)
+}
+
+/// `/atom.xml`
+pub(super) async fn homepage_atom(data: Data<AppData>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    homepage_feed(&data, &req).await?.atom()
+}
+
+/// `/rss.xml`
+pub(super) async fn homepage_rss(data: Data<AppData>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    homepage_feed(&data, &req).await?.rss()
+}
+
+/// `/u/{userID}/atom.xml`
+pub(super) async fn user_atom(data: Data<AppData>, Path((user_id,)): Path<(UserID,)>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    user_feed(&data, &req, &user_id).await?.atom()
+}
+
+/// `/u/{userID}/rss.xml`
+pub(super) async fn user_rss(data: Data<AppData>, Path((user_id,)): Path<(UserID,)>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    user_feed(&data, &req, &user_id).await?.rss()
+}
+
+/// `/u/{userID}/feed/atom.xml`
+pub(super) async fn user_feed_atom(data: Data<AppData>, Path((user_id,)): Path<(UserID,)>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    user_feed_feed(&data, &req, &user_id).await?.atom()
+}
+
+/// `/u/{userID}/feed/rss.xml`
+pub(super) async fn user_feed_rss(data: Data<AppData>, Path((user_id,)): Path<(UserID,)>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    user_feed_feed(&data, &req, &user_id).await?.rss()
+}
@@ -18,4 +18,15 @@ pub(crate) fn with_offset(utc_ms: &i64, offset_mins: &i32) -> Result<String> {
     Ok(
         timestamp.format_with_offset(*offset_mins as i16)
     )
-}
\ No newline at end of file
+}
+/// Percent-encode a string for use as a single URL path segment.
+pub(crate) fn path_segment(s: &str) -> Result<String> {
+    let mut out = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,246 @@
+use actix_web::{App, test};
+use failure::format_err;
+use sodiumoxide::crypto::sign;
+
+use super::*;
+use crate::backend::ServerUser;
+use crate::client::new_item;
+use crate::testing::{TempDB, app_data};
+
+/// Sign arbitrary bytes, which may not be a valid Item.
+fn sign_bytes(secret: &sign::SecretKey, bytes: &[u8]) -> Signature {
+    Signature::from_vec(sign::sign_detached(bytes, secret).as_ref().to_vec()).unwrap()
+}
+
+#[test]
+fn put_item_errors() {
+    actix_web::rt::System::new("test").block_on(async {
+        let db = TempDB::new("put_item_errors");
+        let (public, secret) = sign::gen_keypair();
+        let user = UserID::from_vec(public.as_ref().to_vec()).unwrap();
+        db.factory().open().unwrap().add_server_user(&ServerUser{
+            user: user.clone(),
+            notes: "".into(),
+            on_homepage: true,
+            max_bytes: Some(100),
+            max_item_size: None,
+        }).unwrap();
+        let mut app = test::init_service(
+            App::new().data(app_data(db.factory())).configure(routes)
+        ).await;
+
+        let mut item = new_item();
+        item.mut_post().set_body("Hello".into());
+        let valid = item.write_to_bytes().unwrap();
+
+        let mut large = new_item();
+        large.mut_post().set_body("x".repeat(200));
+        let large = large.write_to_bytes().unwrap();
+
+        let mut untimed = item.clone();
+        untimed.set_timestamp_ms_utc(0);
+        let untimed = untimed.write_to_bytes().unwrap();
+
+        let mut future = item.clone();
+        future.set_timestamp_ms_utc(Timestamp::now().unix_utc_ms + 3_600_000);
+        let future = future.write_to_bytes().unwrap();
+
+        let garbage = vec![0xFF; 10];
+
+        let cases = vec![
+            ("wrong signature", valid.clone(), sign_bytes(&secret, &garbage), 400, Some("validation")),
+            ("not a protobuf", garbage.clone(), sign_bytes(&secret, &garbage), 400, Some("validation")),
+            ("invalid item", untimed.clone(), sign_bytes(&secret, &untimed), 400, Some("validation")),
+            ("future item", future.clone(), sign_bytes(&secret, &future), 400, Some("validation")),
+            ("over quota", large.clone(), sign_bytes(&secret, &large), 507, Some("quota")),
+            ("valid", valid.clone(), sign_bytes(&secret, &valid), 201, None),
+        ];
+
+        for (name, bytes, signature, status, error) in cases {
+            let request = test::TestRequest::put()
+                .uri(&format!("/u/{}/i/{}/proto3", user.to_base58(), signature.to_base58()))
+                .header("content-length", bytes.len())
+                .set_payload(bytes)
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(status, response.status().as_u16(), "{}", name);
+
+            let body = test::read_body(response).await;
+            let body = String::from_utf8_lossy(&body);
+            if let Some(error) = error {
+                assert!(body.contains(&format!("\"error\":\"{}\"", error)), "{}: {}", name, body);
+            }
+        }
+    });
+}
+
+#[test]
+fn put_item_size_limits() {
+    actix_web::rt::System::new("test").block_on(async {
+        let db = TempDB::new("put_item_size_limits");
+        let mut users = vec![];
+        for max_item_size in [None, Some(1000)].iter().copied() {
+            let (public, secret) = sign::gen_keypair();
+            let user = UserID::from_vec(public.as_ref().to_vec()).unwrap();
+            db.factory().open().unwrap().add_server_user(&ServerUser{
+                user: user.clone(),
+                notes: "".into(),
+                on_homepage: true,
+                max_bytes: None,
+                max_item_size,
+            }).unwrap();
+            users.push((user, secret));
+        }
+        let (default_user, custom_user) = (&users[0], &users[1]);
+
+        let mut data = app_data(db.factory());
+        data.max_item_size = 200;
+        let mut app = test::init_service(App::new().data(data).configure(routes)).await;
+
+        let item = |body: &str| {
+            let mut item = new_item();
+            item.mut_post().set_body(body.into());
+            item.write_to_bytes().unwrap()
+        };
+        let small = item("Hello");
+        let large = item(&"x".repeat(300));
+
+        // (name, user, bytes, content-length, expected status)
+        let cases = vec![
+            ("chunked", default_user, small.clone(), None, 201),
+            ("chunked, too large", default_user, large.clone(), None, 413),
+            ("content-length too large", default_user, large.clone(), Some(large.len()), 413),
+            ("content-length too small", default_user, large.clone(), Some(10), 413),
+            ("user's own limit", custom_user, large.clone(), None, 201),
+        ];
+
+        for (name, (user, secret), bytes, length, status) in cases {
+            let mut request = test::TestRequest::put()
+                .uri(&format!("/u/{}/i/{}/proto3", user.to_base58(), sign_bytes(secret, &bytes).to_base58()));
+            if let Some(length) = length {
+                request = request.header("content-length", length);
+            }
+            let request = request.set_payload(bytes).to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(status, response.status().as_u16(), "{}", name);
+        }
+    });
+}
+
+#[test]
+fn revoked_user() {
+    actix_web::rt::System::new("test").block_on(async {
+        let db = TempDB::new("revoked_user");
+        let (public, secret) = sign::gen_keypair();
+        let user = UserID::from_vec(public.as_ref().to_vec()).unwrap();
+        crate::testing::add_server_user(&db.factory(), &user);
+        let mut app = test::init_service(
+            App::new().data(app_data(db.factory())).configure(routes)
+        ).await;
+
+        let put = |item: &Item| {
+            let bytes = item.write_to_bytes().unwrap();
+            let signature = sign_bytes(&secret, &bytes);
+            let uri = format!("/u/{}/i/{}/proto3", user.to_base58(), signature.to_base58());
+            (signature, test::TestRequest::put().uri(&uri).set_payload(bytes).to_request())
+        };
+
+        let mut post = new_item();
+        post.mut_post().set_body("Hello".into());
+        let (post_signature, request) = put(&post);
+        assert_eq!(201, test::call_service(&mut app, request).await.status().as_u16());
+
+        let mut revocation = new_item();
+        revocation.mut_profile().set_revoked(true);
+        let (_, request) = put(&revocation);
+        assert_eq!(201, test::call_service(&mut app, request).await.status().as_u16());
+
+        // No more uploads:
+        let mut late = new_item();
+        late.mut_post().set_body("Hello again".into());
+        let (_, request) = put(&late);
+        let response = test::call_service(&mut app, request).await;
+        assert_eq!(403, response.status().as_u16());
+        let body = test::read_body(response).await;
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("revoked"), "{}", body);
+        assert!(body.contains(r#""error":"quota""#), "{}", body);
+
+        let pages = vec![
+            format!("/u/{}/profile/", user.to_base58()),
+            format!("/u/{}/i/{}/", user.to_base58(), post_signature.to_base58()),
+        ];
+        for page in pages {
+            let response = test::call_service(&mut app, test::TestRequest::get().uri(&page).to_request()).await;
+            assert_eq!(200, response.status().as_u16(), "{}", page);
+            let body = test::read_body(response).await;
+            assert!(String::from_utf8_lossy(&body).contains("This ID has been revoked"), "{}", page);
+        }
+    });
+}
+
+#[test]
+fn search_profiles() {
+    actix_web::rt::System::new("test").block_on(async {
+        let db = TempDB::new("search_profiles");
+        let (public, secret) = sign::gen_keypair();
+        let user = UserID::from_vec(public.as_ref().to_vec()).unwrap();
+        crate::testing::add_server_user(&db.factory(), &user);
+        let mut app = test::init_service(
+            App::new().data(app_data(db.factory())).configure(routes)
+        ).await;
+
+        let mut profile = new_item();
+        profile.mut_profile().set_display_name("Gardener".into());
+        profile.mut_profile().set_about("I grow tomatoes.".into());
+        let mut post = new_item();
+        post.mut_post().set_body("More tomatoes this year.".into());
+        for item in &[profile, post] {
+            let bytes = item.write_to_bytes().unwrap();
+            let signature = sign_bytes(&secret, &bytes);
+            let uri = format!("/u/{}/i/{}/proto3", user.to_base58(), signature.to_base58());
+            let request = test::TestRequest::put().uri(&uri).set_payload(bytes).to_request();
+            assert_eq!(201, test::call_service(&mut app, request).await.status().as_u16());
+        }
+
+        let request = test::TestRequest::get().uri("/search?q=tomatoes").to_request();
+        let response = test::call_service(&mut app, request).await;
+        assert_eq!(200, response.status().as_u16());
+        let body = test::read_body(response).await;
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("I grow tomatoes."), "{}", body);
+        assert!(body.contains("More tomatoes this year."), "{}", body);
+    });
+}
+
+#[test]
+fn error_status_codes() {
+    let cases = vec![
+        (backend::Error::NotFound("".into()).into(), 404, "not_found"),
+        (backend::Error::Conflict("".into()).into(), 409, "conflict"),
+        (backend::Error::Validation("".into()).into(), 400, "validation"),
+        (backend::Error::Quota(backend::QuotaDenyReason::UnknownUser).into(), 403, "quota"),
+        (backend::Error::Storage(format_err!("")).into(), 500, "storage"),
+        // Backend errors passed through failure keep their kind:
+        (failure::Error::from(backend::Error::NotFound("".into())).compat().into(), 404, "not_found"),
+        (failure::Error::from(backend::Error::Conflict("".into())).compat().into(), 409, "conflict"),
+        (format_err!("").compat().into(), 500, "internal"),
+    ];
+
+    for (error, status, code) in cases {
+        let error: Error = error;
+        assert_eq!(status, actix_web::error::ResponseError::status_code(&error).as_u16());
+        assert_eq!(code, error.code());
+    }
+}
+
+#[test]
+fn error_messages() {
+    let error: Error = backend::Error::Storage(format_err!("disk I/O error at /var/feoblog.sqlite3")).into();
+    assert_eq!("Internal server error", error.message());
+    let error: Error = format_err!("secret details").compat().into();
+    assert_eq!("Internal server error", error.message());
+
+    let error: Error = backend::Error::Validation("Invalid signature".into()).into();
+    assert!(error.message().contains("Invalid signature"));
+}
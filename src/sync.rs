@@ -0,0 +1,399 @@
+//! Copies items to this server from the servers listed in users' profiles.
+//!
+//! We sync items for "server users", and for the users they follow. For each
+//! server that a user lists, we page through the user's items in the order
+//! that server received them, and fetch the ones we're missing. We remember
+//! how far we got, so that later syncs only need to look at new arrivals.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use failure::{Error, bail};
+use protobuf::Message;
+
+use crate::backend::{Backend, ItemRow, Signature, SyncServer, Timestamp, UserID};
+use crate::backend::nonblocking::AsyncBackend;
+use crate::client::{Client, TooLarge};
+use crate::protos::{DEFAULT_MAX_CLOCK_DRIFT, Item, ItemListEntry, Profile, ProtoValid, validate_timestamp};
+use crate::server::MAX_ITEM_SIZE;
+
+/// Give up on requests to remote servers after this long.
+pub(crate) const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait before retrying a server after it fails.
+/// This doubles with each failure in a row, up to MAX_BACKOFF_MS.
+const BASE_BACKOFF_MS: i64 = 1000 * 60;
+const MAX_BACKOFF_MS: i64 = 1000 * 60 * 60 * 24;
+
+#[derive(Debug, Clone)]
+pub(crate) struct SyncOptions {
+    /// Sync from servers even if they're backing off after failures.
+    pub ignore_backoff: bool,
+
+    /// Reject items timestamped further in the future than this.
+    pub max_clock_drift: Duration,
+
+    /// Reject items larger than this, from users who don't have their own limit.
+    pub max_item_size: usize,
+
+    /// This server's public URL, if known, which we don't sync from.
+    pub own_url: Option<String>,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        SyncOptions {
+            ignore_backoff: false,
+            max_clock_drift: DEFAULT_MAX_CLOCK_DRIFT,
+            max_item_size: MAX_ITEM_SIZE,
+            own_url: None,
+        }
+    }
+}
+
+/// What happened when we synced from one server.
+pub(crate) struct ServerReport {
+    pub url: String,
+    pub stats: SyncStats,
+    pub outcome: Outcome,
+}
+
+pub(crate) enum Outcome {
+    Synced,
+    /// Stopped at an error. We'll retry after `retry_after`.
+    Failed{ error: String, retry_after: Timestamp },
+    /// Skipped, because of earlier failures.
+    BackingOff{ retry_after: Timestamp },
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct SyncStats {
+    /// Items copied to this server.
+    pub saved: u64,
+
+    /// Listed items that we already had.
+    pub existing: u64,
+
+    /// Items we refused to save. (Bad signatures, invalid Items, too large, or over quota.)
+    pub rejected: u64,
+
+    /// Items that were listed, but which the server couldn't find.
+    pub missing: u64,
+}
+
+impl fmt::Display for ServerReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let SyncStats{saved, existing, rejected, missing} = self.stats;
+        match &self.outcome {
+            Outcome::BackingOff{retry_after} => {
+                return write!(f, "{}: Skipped after errors. Will retry after {}", self.url, retry_after.format_rfc3339(0));
+            },
+            Outcome::Synced => write!(f, "{}: ", self.url)?,
+            Outcome::Failed{error, retry_after} => {
+                write!(f, "{}: Error: {}. Will retry after {}.\n    Before the error: ", self.url, error, retry_after.format_rfc3339(0))?;
+            },
+        }
+        write!(f, "saved {}, already had {}, rejected {}, missing {}", saved, existing, rejected, missing)
+    }
+}
+
+/// Sync items for all known users from the servers in their profiles.
+pub(crate) async fn sync(backend: &AsyncBackend, options: &SyncOptions) -> Result<Vec<ServerReport>, Error> {
+    let own_url = options.own_url.clone();
+    let plan = backend.run(move |backend| Ok(plan(backend, own_url.as_deref())?)).await?;
+
+    let mut reports = vec![];
+    for (url, users) in plan {
+        let server_url = url.clone();
+        let server = backend.run(move |backend| backend.sync_server(&server_url)).await?;
+        let mut server = server.unwrap_or_else(|| SyncServer{
+            url: url.clone(),
+            failures: 0,
+            retry_after: None,
+            last_error: None,
+        });
+
+        if let Some(retry_after) = server.retry_after {
+            if !options.ignore_backoff && retry_after.unix_utc_ms > Timestamp::now().unix_utc_ms {
+                reports.push(ServerReport{ url, stats: SyncStats::default(), outcome: Outcome::BackingOff{retry_after} });
+                continue;
+            }
+        }
+
+        let client = Client::new(&url);
+        let mut stats = SyncStats::default();
+        let mut result = Ok(());
+        for user in &users {
+            result = sync_user(&client, backend, &url, user, options, &mut stats).await;
+            if result.is_err() { break; }
+        }
+
+        let outcome = match result {
+            Ok(()) => {
+                server.failures = 0;
+                server.retry_after = None;
+                server.last_error = None;
+                Outcome::Synced
+            },
+            Err(error) => {
+                let error = error.to_string();
+                server.failures += 1;
+                let retry_after = Timestamp{
+                    unix_utc_ms: Timestamp::now().unix_utc_ms + backoff_ms(server.failures)
+                };
+                server.retry_after = Some(retry_after);
+                server.last_error = Some(error.clone());
+                Outcome::Failed{ error, retry_after }
+            },
+        };
+        backend.run(move |backend| backend.save_sync_server(&server)).await?;
+        reports.push(ServerReport{ url, stats, outcome });
+    }
+
+    Ok(reports)
+}
+
+/// Sync every `interval`, for as long as the server runs.
+pub(crate) async fn sync_periodically(backend: AsyncBackend, interval: Duration, options: SyncOptions) {
+    loop {
+        match sync(&backend, &options).await {
+            Err(error) => println!("Sync error: {}", error),
+            Ok(reports) => {
+                // Don't flood the log with servers that had nothing new:
+                for report in reports {
+                    let quiet = matches!(report.outcome, Outcome::Synced) && report.stats.saved == 0;
+                    if !quiet {
+                        println!("Sync: {}", report);
+                    }
+                }
+            },
+        }
+        actix_web::rt::time::delay_for(interval).await;
+    }
+}
+
+/// How long to wait before retrying a server that has failed `failures` times in a row.
+pub(crate) fn backoff_ms(failures: u32) -> i64 {
+    let doublings = failures.saturating_sub(1).min(20);
+    (BASE_BACKOFF_MS << doublings).min(MAX_BACKOFF_MS)
+}
+
+/// Decide which users to sync from which servers, skipping `own_url`.
+fn plan(backend: &dyn Backend, own_url: Option<&str>) -> Result<BTreeMap<String, Vec<UserID>>, Error> {
+    let mut server_users = vec![];
+    backend.server_users(&mut |server_user| {
+        server_users.push(server_user.user);
+        Ok(true)
+    })?;
+
+    let mut plan = BTreeMap::new();
+    for user in server_users {
+        let profile = match user_profile(backend, &user)? {
+            Some(profile) => profile,
+            None => continue,
+        };
+        // Revoked users won't post anything new, and don't follow anyone:
+        if profile.get_revoked() {
+            continue;
+        }
+        let own_url = own_url.map(|url| url.trim_end_matches('/'));
+        let servers = server_urls(&profile);
+        add_to_plan(&mut plan, &user, &servers, own_url);
+
+        for follow in profile.get_follows() {
+            let followed = match UserID::from_vec(follow.get_user().get_bytes().to_vec()) {
+                Ok(followed) => followed,
+                Err(_) => continue,
+            };
+
+            // Like the web client, we also look for followed users on their
+            // followers' servers. This finds them even if we don't have their
+            // profile yet, or it doesn't list any servers.
+            if backend.user_revoked(&followed)? {
+                continue;
+            }
+            add_to_plan(&mut plan, &followed, &servers, own_url);
+            if let Some(profile) = user_profile(backend, &followed)? {
+                add_to_plan(&mut plan, &followed, &server_urls(&profile), own_url);
+            }
+        }
+    }
+
+    Ok(plan)
+}
+
+/// `own_url` must not have a trailing slash. (Like [`server_urls`])
+fn add_to_plan(plan: &mut BTreeMap<String, Vec<UserID>>, user: &UserID, servers: &[String], own_url: Option<&str>) {
+    for url in servers {
+        if Some(url.as_str()) == own_url { continue; }
+        let users = plan.entry(url.clone()).or_default();
+        if !users.iter().any(|u| u.bytes() == user.bytes()) {
+            users.push(user.clone());
+        }
+    }
+}
+
+pub(crate) fn user_profile(backend: &dyn Backend, user: &UserID) -> Result<Option<Profile>, Error> {
+    let row = match backend.user_profile(user)? {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let mut item = Item::new();
+    item.merge_from_bytes(&row.item_bytes)?;
+    Ok(Some(item.take_profile()))
+}
+
+/// Base URLs of the servers listed in a profile, without trailing slashes.
+/// Skips those that we can't sync from.
+pub(crate) fn server_urls(profile: &Profile) -> Vec<String> {
+    profile.get_servers().iter()
+        .map(|server| server.get_url().trim().trim_end_matches('/'))
+        .filter(|url| url.starts_with("https://") || url.starts_with("http://"))
+        .map(|url| url.to_string())
+        .collect()
+}
+
+/// Sync one user's items from one server.
+async fn sync_user(
+    client: &Client,
+    backend: &AsyncBackend,
+    url: &str,
+    user: &UserID,
+    options: &SyncOptions,
+    stats: &mut SyncStats,
+) -> Result<(), Error> {
+    let (server_url, user_id) = (url.to_string(), user.clone());
+    let position = backend.run(move |backend| backend.sync_position(&server_url, &user_id)).await?;
+
+    // More items may have arrived during the same millisecond as the last
+    // one we saw, so list that millisecond again.
+    let received_after = position.map(|t| t.unix_utc_ms - 1).unwrap_or(0);
+    let mut newest = position.map(|t| t.unix_utc_ms).unwrap_or(0);
+
+    let mut page: Option<String> = None;
+    loop {
+        let list = client.received_items(user, received_after, page.as_deref()).await?;
+
+        for entry in list.get_items() {
+            sync_item(client, backend, user, entry, options, stats).await?;
+            newest = newest.max(entry.get_received_utc_ms());
+        }
+
+        let last = match list.get_items().last() {
+            Some(last) if !list.get_no_more_items() => last,
+            _ => break,
+        };
+
+        // Servers without `received_after` support list items newest first,
+        // and may not give us a cursor.
+        let next_page = if list.get_next_cursor().is_empty() {
+            format!("before={}", last.get_timestamp_ms_utc())
+        } else {
+            format!("cursor={}", list.get_next_cursor())
+        };
+        if page.as_deref() == Some(next_page.as_str()) {
+            bail!("Server returned the same page twice: {}", next_page);
+        }
+        page = Some(next_page);
+    }
+
+    // Servers that don't report received times leave this at 0, so we'll
+    // list all of their items each time.
+    if newest > 0 {
+        let (server_url, user_id) = (url.to_string(), user.clone());
+        backend.run(move |backend| {
+            backend.save_sync_position(&server_url, &user_id, Timestamp{ unix_utc_ms: newest })
+        }).await?;
+    }
+
+    Ok(())
+}
+
+/// Copy one item from a remote server, if we don't have it yet.
+/// Returns an error only if the server or our backend did.
+async fn sync_item(
+    client: &Client,
+    backend: &AsyncBackend,
+    user: &UserID,
+    entry: &ItemListEntry,
+    options: &SyncOptions,
+    stats: &mut SyncStats,
+) -> Result<(), Error> {
+    // Note: We ignore entry.user_id. We only want items for `user`, and
+    // checking the signature proves that's what we got.
+    let signature = match Signature::from_vec(entry.get_signature().get_bytes().to_vec()) {
+        Ok(signature) => signature,
+        Err(_) => {
+            stats.rejected += 1;
+            return Ok(());
+        }
+    };
+
+    let (user_id, item_signature) = (user.clone(), signature.clone());
+    let (exists, user_max_item_size) = backend.run(move |backend| {
+        let exists = backend.user_item_exists(&user_id, &item_signature)?;
+        let max_item_size = backend.server_user(&user_id)?.and_then(|user| user.max_item_size);
+        Ok((exists, max_item_size))
+    }).await?;
+    if exists {
+        stats.existing += 1;
+        return Ok(());
+    }
+
+    // Like uploads, server users may have their own limit:
+    let max_item_size = user_max_item_size.map(|size| size as usize).unwrap_or(options.max_item_size);
+    let bytes = match client.get_item_bytes(user, &signature, max_item_size).await {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => {
+            stats.missing += 1;
+            return Ok(());
+        },
+        Err(error) if error.downcast_ref::<TooLarge>().is_some() => {
+            stats.rejected += 1;
+            return Ok(());
+        },
+        Err(error) => return Err(error),
+    };
+
+    if !signature.is_valid(user, &bytes) {
+        stats.rejected += 1;
+        return Ok(());
+    }
+
+    let mut item = Item::new();
+    let now = Timestamp::now().unix_utc_ms;
+    if item.merge_from_bytes(&bytes).is_err()
+        || item.validate().is_err()
+        || validate_timestamp(&item, now, options.max_clock_drift).is_err()
+    {
+        stats.rejected += 1;
+        return Ok(());
+    }
+
+    let row = ItemRow{
+        user: user.clone(),
+        signature,
+        timestamp: Timestamp{ unix_utc_ms: item.get_timestamp_ms_utc() },
+        received: Timestamp::now(),
+        item_bytes: bytes,
+    };
+    let saved = backend.run(move |backend| {
+        if backend.quota_check_item(&row.user, &row.item_bytes, &item)?.is_some() {
+            return Ok(false);
+        }
+        backend.save_user_item(&row, &item)?;
+        Ok(true)
+    }).await?;
+
+    if saved {
+        stats.saved += 1;
+    } else {
+        stats.rejected += 1;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
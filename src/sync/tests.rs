@@ -0,0 +1,123 @@
+use super::*;
+
+use actix_web::App;
+
+use crate::backend::{Cursor, Factory};
+use crate::client::Keys;
+use crate::server::routes;
+use crate::testing::{TempDB, add_server_user, app_data, async_backend, post, profile, save, sign_row};
+
+fn item_count(factory: &dyn Factory, user: &UserID) -> usize {
+    let mut count = 0;
+    factory.open().unwrap()
+        .user_items(user, &Cursor::before(Timestamp::now()), &mut |_| { count += 1; Ok(true) })
+        .unwrap();
+    count
+}
+
+#[test]
+fn sync_from_remote_server() {
+    actix_web::rt::System::new("test").block_on(async {
+        let remote_db = TempDB::new("sync-remote");
+        let remote = remote_db.factory();
+        let server_factory = remote.clone();
+        let server = actix_web::test::start(move || {
+            App::new()
+                .data(app_data(server_factory.clone()))
+                .configure(routes)
+        });
+        let remote_url = format!("http://{}", server.addr());
+
+        let local_db = TempDB::new("sync-local");
+        let local = local_db.factory();
+
+        let (alice, bob) = (Keys::generate(), Keys::generate());
+        add_server_user(&local, &alice.user);
+        // We don't sync from ourselves:
+        let own_url = "http://local.example.com";
+        let alice_profile = sign_row(&alice, &profile(1, &[&format!("{}/", remote_url), "ftp://example.com", own_url], &[&bob.user]), 500);
+        save(&local, &alice_profile);
+        save(&remote, &alice_profile);
+
+        save(&remote, &sign_row(&alice, &post(100, "one"), 1000));
+        save(&remote, &sign_row(&bob, &post(150, "bob"), 1500));
+        save(&remote, &sign_row(&alice, &post(200, "two"), 2000));
+
+        // Signed by the wrong user:
+        let mut forged = sign_row(&bob, &post(300, "forged"), 2500);
+        forged.user = alice.user.clone();
+        save(&remote, &forged);
+
+        // Larger than we'll accept:
+        let mut large = post(250, "large");
+        large.mut_post().set_body("x".repeat(MAX_ITEM_SIZE));
+        save(&remote, &sign_row(&alice, &large, 2200));
+
+        let options = SyncOptions{ own_url: Some(format!("{}/", own_url)), ..Default::default() };
+        let reports = sync(&async_backend(local.clone()), &options).await.unwrap();
+        assert_eq!(1, reports.len());
+        let report = &reports[0];
+        assert_eq!(remote_url, report.url);
+        assert!(matches!(report.outcome, Outcome::Synced), "{}", report);
+        assert_eq!(3, report.stats.saved);
+        assert_eq!(1, report.stats.existing);
+        assert_eq!(2, report.stats.rejected);
+        assert_eq!(3, item_count(&local, &alice.user));
+        // Bob's profile doesn't list servers, but Alice's does:
+        assert_eq!(1, item_count(&local, &bob.user));
+
+        // Items that arrive later are found, even if they're back-dated:
+        save(&remote, &sign_row(&alice, &post(50, "back-dated"), 3000));
+        let reports = sync(&async_backend(local.clone()), &options).await.unwrap();
+        let stats = reports[0].stats;
+        assert_eq!(1, stats.saved);
+        assert_eq!(4, item_count(&local, &alice.user));
+
+        // We only looked at items received since the last sync.
+        // (Plus those received in the same millisecond as the last one.)
+        assert_eq!(1, stats.existing);
+        assert_eq!(1, stats.rejected);
+
+        let position = local.open().unwrap().sync_position(&remote_url, &alice.user).unwrap();
+        assert_eq!(Some(3000), position.map(|t| t.unix_utc_ms));
+    });
+}
+
+#[test]
+fn failing_servers_back_off() {
+    actix_web::rt::System::new("test").block_on(async {
+        let db = TempDB::new("sync-backoff");
+        let factory = db.factory();
+
+        let carol = Keys::generate();
+        add_server_user(&factory, &carol.user);
+        // Nothing should be listening on port 1:
+        let url = "http://127.0.0.1:1";
+        save(&factory, &sign_row(&carol, &profile(1, &[url], &[]), 1));
+
+        let reports = sync(&async_backend(factory.clone()), &SyncOptions::default()).await.unwrap();
+        assert!(matches!(reports[0].outcome, Outcome::Failed{..}));
+        let server = factory.open().unwrap().sync_server(url).unwrap().unwrap();
+        assert_eq!(1, server.failures);
+        assert!(server.retry_after.unwrap().unix_utc_ms > Timestamp::now().unix_utc_ms);
+        assert!(server.last_error.is_some());
+
+        let reports = sync(&async_backend(factory.clone()), &SyncOptions::default()).await.unwrap();
+        assert!(matches!(reports[0].outcome, Outcome::BackingOff{..}));
+
+        let options = SyncOptions{ ignore_backoff: true, ..Default::default() };
+        let reports = sync(&async_backend(factory.clone()), &options).await.unwrap();
+        assert!(matches!(reports[0].outcome, Outcome::Failed{..}));
+        let server = factory.open().unwrap().sync_server(url).unwrap().unwrap();
+        assert_eq!(2, server.failures);
+    });
+}
+
+#[test]
+fn backoff_doubles() {
+    assert_eq!(BASE_BACKOFF_MS, backoff_ms(1));
+    assert_eq!(BASE_BACKOFF_MS * 2, backoff_ms(2));
+    assert_eq!(BASE_BACKOFF_MS * 4, backoff_ms(3));
+    assert_eq!(MAX_BACKOFF_MS, backoff_ms(20));
+    assert_eq!(MAX_BACKOFF_MS, backoff_ms(u32::MAX));
+}
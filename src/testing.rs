@@ -0,0 +1,110 @@
+//! Helpers shared by tests.
+
+use std::sync::Arc;
+
+use protobuf::Message as _;
+
+use crate::backend::{Factory, ItemRow, ServerUser, Timestamp, UserID, sqlite};
+use crate::backend::nonblocking::AsyncBackend;
+use crate::client::Keys;
+use crate::outbox::Wakeup;
+use crate::protos::{DEFAULT_MAX_CLOCK_DRIFT, Follow, Item, Server};
+use crate::server::{AppData, MAX_ITEM_SIZE};
+
+/// An initialized SQLite database in the temp directory, which is deleted
+/// when dropped.
+pub(crate) struct TempDB {
+    path: String,
+}
+
+impl TempDB {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir()
+            .join(format!("feoblog-test-{}-{}-{}.sqlite3", name, std::process::id(), Timestamp::now().unix_utc_ms))
+            .to_string_lossy()
+            .into_owned();
+        let db = TempDB{ path };
+        db.factory().open().unwrap().init().unwrap();
+        db
+    }
+
+    pub fn factory(&self) -> sqlite::Factory {
+        sqlite::Factory::new(self.path.clone())
+    }
+}
+
+impl Drop for TempDB {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+pub(crate) fn add_server_user(factory: &dyn Factory, user: &UserID) {
+    factory.open().unwrap().add_server_user(&ServerUser{
+        user: user.clone(),
+        notes: "".into(),
+        on_homepage: true,
+        max_bytes: None,
+        max_item_size: None,
+    }).unwrap();
+}
+
+/// Sign an item as `keys`, as if a server received it at `received`.
+/// Doesn't validate the item.
+pub(crate) fn sign_row(keys: &Keys, item: &Item, received: i64) -> ItemRow {
+    let bytes = item.write_to_bytes().unwrap();
+    ItemRow{
+        user: keys.user.clone(),
+        signature: keys.sign_bytes(&bytes),
+        timestamp: Timestamp{ unix_utc_ms: item.get_timestamp_ms_utc() },
+        received: Timestamp{ unix_utc_ms: received },
+        item_bytes: bytes,
+    }
+}
+
+/// Save a signed item, without checking quotas.
+pub(crate) fn save(factory: &dyn Factory, row: &ItemRow) {
+    let item = Item::parse_from_bytes(&row.item_bytes).unwrap();
+    factory.open().unwrap().save_user_item(row, &item).unwrap();
+}
+
+/// A post with just a title.
+pub(crate) fn post(timestamp: i64, title: &str) -> Item {
+    let mut item = Item::new();
+    item.set_timestamp_ms_utc(timestamp);
+    item.mut_post().set_title(title.into());
+    item
+}
+
+/// A profile that lists `servers`, and follows `follows`.
+pub(crate) fn profile(timestamp: i64, servers: &[&str], follows: &[&UserID]) -> Item {
+    let mut item = Item::new();
+    item.set_timestamp_ms_utc(timestamp);
+    let profile = item.mut_profile();
+    for url in servers {
+        let mut server = Server::new();
+        server.set_url(url.to_string());
+        profile.mut_servers().push(server);
+    }
+    for user in follows {
+        let mut follow = Follow::new();
+        follow.mut_user().set_bytes(user.bytes().to_vec());
+        profile.mut_follows().push(follow);
+    }
+    item
+}
+
+pub(crate) fn async_backend(factory: impl Factory + 'static) -> AsyncBackend {
+    AsyncBackend::new(Arc::new(factory))
+}
+
+/// AppData for a test server that uses `factory`.
+pub(crate) fn app_data(factory: impl Factory + 'static) -> AppData {
+    AppData{
+        backend: async_backend(factory),
+        max_clock_drift: DEFAULT_MAX_CLOCK_DRIFT,
+        max_item_size: MAX_ITEM_SIZE,
+        public_url: None,
+        outbox: Wakeup::new().0,
+    }
+}
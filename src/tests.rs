@@ -92,4 +92,38 @@ fn time_duration() {
     // FeoBlog uses an i64 # ms since epoch, so its max is:
     let max_feo = Duration::milliseconds(i64::MAX);
     assert_eq!(292471208, max_feo.whole_days() / 365);
-}
\ No newline at end of file
+}
+#[test]
+fn timestamp_formats() {
+    use crate::backend::Timestamp;
+
+    // 2020-01-02 10:04:05.678 UTC
+    let timestamp = Timestamp{ unix_utc_ms: 1577959445678 };
+    assert_eq!("2020-01-02 03:04:05 -0700", timestamp.format_with_offset(-420));
+    assert_eq!("2020-01-02T03:04:05-07:00", timestamp.format_rfc3339(-420));
+    assert_eq!("Thu, 02 Jan 2020 03:04:05 -0700", timestamp.format_rfc2822(-420));
+    assert_eq!("2020-01-02T10:04:05+00:00", timestamp.format_rfc3339(0));
+}
+
+#[test]
+fn cursor_base58() {
+    use crate::backend::{Cursor, Signature, Timestamp, UserID};
+
+    let cursor = Cursor::before(Timestamp{ unix_utc_ms: -12345 });
+    let decoded = Cursor::from_base58(&cursor.to_base58()).unwrap();
+    assert_eq!(-12345, decoded.timestamp.unix_utc_ms);
+    assert!(decoded.item.is_none());
+
+    let user = UserID::from_vec(vec![1; 32]).unwrap();
+    let signature = Signature::from_vec(vec![2; 64]).unwrap();
+    let cursor = Cursor{ timestamp: Timestamp{ unix_utc_ms: 1234 }, item: Some((user, signature)) };
+    let decoded = Cursor::from_base58(&cursor.to_base58()).unwrap();
+    assert_eq!(1234, decoded.timestamp.unix_utc_ms);
+    let (user, signature) = decoded.item.unwrap();
+    assert_eq!(vec![1; 32], user.bytes());
+    assert_eq!(vec![2; 64], signature.bytes());
+
+    assert!(Cursor::from_base58("").is_err());
+    assert!(Cursor::from_base58("not base58!").is_err());
+    assert!(Cursor::from_base58(&bs58::encode(vec![0; 20]).into_string()).is_err());
+}
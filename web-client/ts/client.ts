@@ -156,54 +156,23 @@ export class Client {
     }
 
     async * getHomepageItems(): AsyncGenerator<ItemListEntry> {
-        let before: number|undefined = undefined
-        while (true) {
-
-            let list: ItemList = await this.getItemList("/homepage/proto3", {before})
-
-            if (list.items.length == 0) {
-                // There are no more items.
-                return
-            }
-    
-            for (let entry of list.items) yield entry
-            
-            if (list.no_more_items) {
-                return
-            }
-    
-            before = list.items[list.items.length - 1].timestamp_ms_utc
-        }
+        yield* this.paginate("/homepage/proto3")
     }
 
     async * getUserFeedItems(userID: UserID): AsyncGenerator<ItemListEntry> {
-        let before: number|undefined = undefined
-        while (true) {
-
-            let list: ItemList = await this.getItemList(`/u/${userID}/feed/proto3`, {before})
-
-            if (list.items.length == 0) {
-                // There are no more items.
-                return
-            }
-    
-            for (let entry of list.items) yield entry
-            
-            if (list.no_more_items) {
-                return
-            }
-    
-            before = list.items[list.items.length - 1].timestamp_ms_utc
-        }
+        yield* this.paginate(`/u/${userID}/feed/proto3`)
     }
 
-    // TODO: getUserItems, getUserFeedItems, getHomepageItems, could share more code. They're basically all
-    // paginating through an ItemList endpoint.
     async * getUserItems(userID: UserID): AsyncGenerator<ItemListEntry> {
-        let before: number|undefined = undefined
+        yield* this.paginate(`/u/${userID}/proto3`)
+    }
+
+    // Yields every entry from an ItemList endpoint, fetching pages as needed.
+    private async * paginate(itemsPath: string): AsyncGenerator<ItemListEntry> {
+        let params: Record<string,string|number|undefined> = {}
         while (true) {
 
-            let list: ItemList = await this.getItemList(`/u/${userID}/proto3`, {before})
+            let list: ItemList = await this.getItemList(itemsPath, params)
 
             if (list.items.length == 0) {
                 // There are no more items.
@@ -216,7 +185,12 @@ export class Client {
                 return
             }
     
-            before = list.items[list.items.length - 1].timestamp_ms_utc
+            if (list.next_cursor) {
+                params = {cursor: list.next_cursor}
+            } else {
+                // Older servers don't return cursors:
+                params = {before: list.items[list.items.length - 1].timestamp_ms_utc}
+            }
         }
     }
 
@@ -362,4 +336,4 @@ const PASSWORD_BYTES = USER_ID_BYTES + 4 // 4 bytes b58 checksum.
 const MAX_ITEM_SIZE = 32 * 1024 // 32KiB
 // Some servers may increase max item size? Eh, we'll be lenient in what we accept
 // Though, we do want to protect against trying to load absolutely massive ones in the browser:
-const LENIENT_MAX_ITEM_SIZE = 1024 * 1024 // 1 MiB
\ No newline at end of file
+const LENIENT_MAX_ITEM_SIZE = 1024 * 1024 // 1 MiB
\ No newline at end of file
@@ -193,6 +193,19 @@ type LogEntry = {
 }
 
 
+// Returns a non-empty error string if `bytes` is not a valid (optional) byte count.
+export function validateMaxBytes(bytes: string): string {
+    if (bytes === "") {
+        return "" // Optional.
+    }
+
+    if (!/^[0-9]+$/.test(bytes)) {
+        return "Must be a number of bytes"
+    }
+
+    return ""
+}
+
 const serverURLPattern = /^(https?:\/\/[^/ ]+)$/
 // Returns a non-empty error string if `url` is not a valid server URL.
 export function validateServerURL(url: string): string {